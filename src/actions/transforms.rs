@@ -167,7 +167,7 @@ where
 	}
 }
 
-async fn transform_old_entry_into_new_entries<T>(
+pub(crate) async fn transform_old_entry_into_new_entries<T>(
 	this: &mut T,
 	old_entry: Entry,
 ) -> Result<Vec<Entry>, TransformError>
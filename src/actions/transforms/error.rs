@@ -24,7 +24,7 @@ use crate::actions::transforms::json::JsonError;
 #[cfg(feature = "action-html")]
 use crate::actions::transforms::html::HtmlError;
 
-use std::{convert::Infallible, error::Error as StdError};
+use std::{convert::Infallible, error::Error as StdError, time::Duration};
 
 /// An error that occured during transforming of entries
 #[expect(missing_docs, reason = "error message is self-documenting")]
@@ -93,11 +93,20 @@ impl Error for TransformError {
 	fn is_network_related(&self) -> Option<&dyn Error> {
 		match &self.kind {
 			#[cfg(feature = "action-http")]
-			TransformErrorKind::Http(HttpError::Other(_)) => Some(self),
+			TransformErrorKind::Http(HttpError::Other(e)) if e.is_network_related().is_some() => Some(self),
 			TransformErrorKind::Other(other_err) if other_err.is_network_related().is_some() => {
 				Some(self)
 			}
 			_ => None,
 		}
 	}
+
+	fn retry_after(&self) -> Option<Duration> {
+		match &self.kind {
+			#[cfg(feature = "action-http")]
+			TransformErrorKind::Http(HttpError::Other(e)) => e.retry_after(),
+			TransformErrorKind::Other(other_err) => other_err.retry_after(),
+			_ => None,
+		}
+	}
 }
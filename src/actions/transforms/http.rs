@@ -6,7 +6,6 @@
 
 //! This module contains the [`Http`] transform that fetches a web page from a link located in a field of the passed [`Entry`]
 
-use reqwest::Client;
 use url::Url;
 
 use super::Transform;
@@ -17,7 +16,10 @@ use crate::{
 	},
 	entry::Entry,
 	error::InvalidUrlError,
-	sources::{self, http::HttpError as SourceHttpError, http::Request},
+	sources::{
+		self,
+		http::{HttpClient, HttpError as SourceHttpError, Request},
+	},
 	utils::OptionExt,
 };
 
@@ -27,8 +29,8 @@ pub struct Http {
 	/// The field to get the URL from
 	pub from_field: Field,
 
-	/// Reqwest HTTP client
-	client: Client,
+	/// HTTP client used to send requests
+	client: HttpClient,
 }
 
 #[expect(missing_docs, reason = "error message is self-documenting")]
@@ -45,21 +47,21 @@ pub enum HttpError {
 }
 
 impl Http {
-	/// Create a new [`Http`] transform
+	/// Create a new [`Http`] transform that uses the [default client](`HttpClient::shared_default`)
 	///
 	/// # Errors
 	/// This method fails if TLS couldn't be initialized
 	pub fn new(from_field: Field) -> Result<Self, SourceHttpError> {
-		let client = sources::http::CLIENT
-			.get_or_try_init(|| {
-				reqwest::ClientBuilder::new()
-					.timeout(std::time::Duration::from_secs(30))
-					.build()
-					.map_err(SourceHttpError::TlsInitFailed)
-			})?
-			.clone();
+		Ok(Self::new_with_client(
+			from_field,
+			HttpClient::shared_default()?,
+		))
+	}
 
-		Ok(Self { from_field, client })
+	/// Create a new [`Http`] transform that uses the provided `client` to send requests
+	#[must_use]
+	pub fn new_with_client(from_field: Field, client: HttpClient) -> Self {
+		Self { from_field, client }
 	}
 }
 
@@ -108,7 +110,7 @@ impl Transform for Http {
 
 		let url = url.ok_or_else(|| HttpError::MissingUrl(self.from_field))?;
 
-		let new_page = sources::http::send_request(&self.client, &Request::Get, &url).await?;
+		let new_page = sources::http::send_request(&self.client, &Request::get(), &url).await?;
 
 		Ok(vec![TransformedEntry {
 			raw_contents: TransformResult::New(new_page),
@@ -153,6 +153,20 @@ impl Google {
 	}
 }
 
+#[cfg(test)]
+impl Google {
+	/// Creates an authenticator that already has a valid access token and thus never contacts Google
+	pub(crate) fn with_access_token(token: &str) -> Self {
+		Self {
+			access_token: Some(AccessToken {
+				token: token.to_owned(),
+				expires: Instant::now() + Duration::from_hours(1),
+			}),
+			..Self::new("client_id", "client_secret", "refresh_token")
+		}
+	}
+}
+
 impl Error for GoogleOAuth2Error {
 	fn is_network_related(&self) -> Option<&dyn Error> {
 		match self {
@@ -12,6 +12,7 @@ pub use id::EntryId;
 
 use crate::{safe_slice::SafeSliceUntilExt, sinks::message::Message};
 
+use serde::{Deserialize, Serialize};
 use std::fmt::Debug;
 
 /// A [`fetcher`](`crate`) primitive that contains a message and an id returned from a source that can be send to a sink.
@@ -27,7 +28,7 @@ use std::fmt::Debug;
 ///         .body("message body".to_owned()))  // notice no `.build()` on the message builder
 ///     .build(); // this `.build()` builds both
 /// ```
-#[derive(PartialEq, Eq, Clone, Default, bon::Builder)]
+#[derive(PartialEq, Eq, Clone, Default, Serialize, Deserialize, bon::Builder)]
 pub struct Entry {
 	/// ID of the entry
 	///
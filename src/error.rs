@@ -21,7 +21,7 @@ use crate::{
 	sources::error::SourceError,
 };
 
-use std::{convert::Infallible, error::Error as StdError};
+use std::{convert::Infallible, error::Error as StdError, time::Duration};
 
 // TODO: attach backtraces to all inner errors
 #[expect(missing_docs, reason = "error message is self-documenting")]
@@ -73,6 +73,15 @@ impl Error for FetcherError {
 			Self::Other(e) => e.is_network_related(),
 		}
 	}
+
+	fn retry_after(&self) -> Option<Duration> {
+		match self {
+			Self::Source(e) => e.retry_after(),
+			Self::Transform(e) => e.retry_after(),
+			Self::Other(e) => e.retry_after(),
+			_ => None,
+		}
+	}
 }
 
 impl From<TransformError> for FetcherError {
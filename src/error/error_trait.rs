@@ -4,7 +4,7 @@
  * file, You can obtain one at https://mozilla.org/MPL/2.0/.
  */
 
-use std::error::Error as StdError;
+use std::{error::Error as StdError, time::Duration};
 
 // TODO: better name??
 // TODO: use maybesendsync?
@@ -15,6 +15,15 @@ pub trait Error: StdError + Send + Sync {
 	/// Usually these errors shouldn't contribute to error handling in jobs and should just sleep for a bit before retrying.
 	#[must_use]
 	fn is_network_related(&self) -> Option<&dyn Error>;
+
+	/// Returns the minimum duration to wait for before retrying, if the error specifies one.
+	///
+	/// For example, HTTP servers may specify it in the `Retry-After` header of a `503 Service Unavailable` or `429 Too Many Requests` response.
+	/// Error handlers (e.g. [`ExponentialBackoff`](`crate::job::error_handling::ExponentialBackoff`)) shouldn't retry any sooner than that.
+	#[must_use]
+	fn retry_after(&self) -> Option<Duration> {
+		None
+	}
 }
 
 impl StdError for Box<dyn Error> {
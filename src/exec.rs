@@ -6,8 +6,17 @@
 
 //! This module contains [`Exec`] source and sink. It is re-exported in the [`crate::sinks`] and [`crate::sources`] modules
 
-use std::{io, process::Stdio, string::FromUtf8Error};
-use tokio::{io::AsyncWriteExt, process::Command};
+#[cfg(feature = "exec-json")]
+mod json_lines;
+
+use std::{
+	io,
+	path::PathBuf,
+	process::{ExitStatus, Stdio},
+	string::FromUtf8Error,
+	time::Duration,
+};
+use tokio::{io::AsyncWriteExt, process::Command, try_join};
 
 use crate::{
 	entry::Entry,
@@ -24,20 +33,106 @@ const SHELL: &str = "sh";
 const SHELL: &str = "cmd";
 
 #[cfg(not(target_os = "windows"))]
-const SHELL_RUN_ARG: &str = r"\C";
+const SHELL_RUN_ARG: &str = "-c";
 #[cfg(target_os = "windows")]
-const SHELL: &str = "-c";
+const SHELL_RUN_ARG: &str = r"/C";
 
-/// Exec source. It can execute a shell command and source its stdout
-#[derive(Debug)]
+/// Exec source and sink.
+///
+/// As a source, it executes a command and sources its stdout.
+/// As a sink, it executes a command and passes the body of the message to its stdin.
+///
+/// # Example
+/// ```
+/// # use fetcher::sources::Exec;
+/// # use std::time::Duration;
+/// let exec = Exec::builder()
+///     .program("git")
+///     .args(["log", "--oneline", "-n", "10"])
+///     .env("GIT_PAGER", "cat")
+///     .cwd("/srv/repo")
+///     .timeout(Duration::from_secs(30))
+///     .check_exit_status(true)
+///     .build();
+/// ```
+#[derive(bon::Builder, Debug)]
 pub struct Exec {
+	/// Arguments to pass to the command.
+	///
+	/// If the command is a [shell command](`Cmd::Shell`), they are available to it as positional parameters, i.e. `$1`, `$2`, etc.
+	#[builder(field)]
+	pub args: Vec<String>,
+
+	/// Environment variables to set for the process in addition to the ones it inherits
+	#[builder(field)]
+	pub env: Vec<(String, String)>,
+
 	/// The command to execute
-	pub cmd: String,
+	#[builder(setters(name = cmd_internal, vis = ""))]
+	pub cmd: Cmd,
+
+	/// Working directory of the process. Inherited from the current process if `None`
+	#[builder(into)]
+	pub cwd: Option<PathBuf>,
+
+	/// Max time the process is allowed to run for before it's killed and an [`ExecError::TimedOut`] is returned.
+	/// Unlimited if `None`
+	pub timeout: Option<Duration>,
+
+	/// Return an [`ExecError::UnsuccessfulExit`] containing the stderr of the process if it exits with a non-zero status.
+	///
+	/// If `false`, the exit status is ignored and the stderr isn't captured but is inherited from the current process instead
+	#[builder(default)]
+	pub check_exit_status: bool,
+
+	/// How entries and messages are passed between fetcher and the process
+	#[builder(default)]
+	pub protocol: Protocol,
+}
+
+/// A command that an [`Exec`] executes
+#[derive(Clone, Debug)]
+pub enum Cmd {
+	/// A command line that is passed to the shell, i.e. to `sh -c` (or `cmd /C` on Windows)
+	Shell(String),
+
+	/// A program that is executed directly, without a shell. It's searched for in `PATH` if it's not a path itself
+	Program(PathBuf),
 }
+
+/// How entries and messages are passed between fetcher and the process
+#[derive(Clone, Copy, Default, Debug)]
+pub enum Protocol {
+	/// As a source, the entire stdout is put into a single entry's [`raw_contents`](`crate::entry::Entry::raw_contents`).
+	/// As a sink, only the body of the message is passed to stdin and the message is never assigned an ID.
+	#[default]
+	Raw,
+
+	/// Entries and messages are passed as JSON objects, one per line.
+	///
+	/// As a source, the process prints an entry per line, e.g.
+	/// ```json
+	/// {"id": "42", "reply_to": "41", "title": "Title", "body": "Body", "link": "https://example.com/42", "media": [{"type": "photo", "url": "https://example.com/42.png"}]}
+	/// ```
+	/// All fields are optional. The media type is either `photo` or `video`.
+	///
+	/// As a sink, the process gets a single line with the message, the ID of the message it replies to, and the tag, e.g.
+	/// ```json
+	/// {"title": "Title", "body": "Body", "link": null, "media": [], "reply_to": 1337, "tag": null}
+	/// ```
+	/// It may print the ID of the sent message in response, e.g. `{"id": 1338}`, to make it possible to reply to it later.
+	/// Only the last non-empty line is parsed, so anything can be printed before it. Empty output means the message has no ID
+	#[cfg(feature = "exec-json")]
+	JsonLines,
+}
+
 /// Errors that happened while executing a process
 #[expect(missing_docs, reason = "error message is self-documenting")]
 #[derive(thiserror::Error, Debug)]
 pub enum ExecError {
+	#[deprecated(
+		note = "never returned anymore, see ExecError::CantStart and ExecError::CantWait instead"
+	)]
 	#[error("Bad command")]
 	BadCommand(#[source] io::Error),
 
@@ -49,69 +144,350 @@ pub enum ExecError {
 
 	#[error("Can't pass data to the stdin of the process")]
 	CantWriteStdin(#[source] io::Error),
+
+	#[error("Can't wait for the process to exit")]
+	CantWait(#[source] io::Error),
+
+	#[error("Process didn't exit in {0:?} and was killed")]
+	TimedOut(Duration),
+
+	#[error("Process exited unsuccessfully with {status}: {stderr:?}")]
+	UnsuccessfulExit {
+		/// Exit status of the process
+		status: ExitStatus,
+
+		/// Everything the process has written to its stderr
+		stderr: String,
+	},
+
+	#[cfg(feature = "exec-json")]
+	#[error("Invalid JSON line {1:?}")]
+	BadJson(#[source] serde_json::Error, String),
+
+	#[cfg(feature = "exec-json")]
+	#[error("Invalid base64 data of an attachment")]
+	BadBase64(#[source] base64::DecodeError),
+}
+
+impl Exec {
+	/// Creates a new [`Exec`] that passes the `cmd` command line to the shell.
+	///
+	/// Use [`Exec::builder`] for more options
+	#[must_use]
+	pub fn new_shell(cmd: impl Into<String>) -> Self {
+		Self::builder().shell(cmd).build()
+	}
+
+	/// Runs the process, passing `stdin` to it if it's some, and returns its stdout if `capture_stdout` is set.
+	///
+	/// Otherwise the stdout is discarded and an empty [`Vec`] is returned
+	async fn run(&self, stdin: Option<&[u8]>, capture_stdout: bool) -> Result<Vec<u8>, ExecError> {
+		let mut command = match &self.cmd {
+			Cmd::Shell(cmd) => {
+				let mut command = Command::new(SHELL);
+				command.arg(SHELL_RUN_ARG).arg(cmd);
+
+				// the first arg is $0, i.e. the name of the script
+				#[cfg(not(target_os = "windows"))]
+				if !self.args.is_empty() {
+					command.arg(SHELL);
+				}
+
+				command
+			}
+			Cmd::Program(program) => Command::new(program),
+		};
+
+		command
+			.args(&self.args)
+			.envs(self.env.iter().map(|(key, value)| (key, value)))
+			.stdin(if stdin.is_some() {
+				Stdio::piped()
+			} else {
+				Stdio::null()
+			})
+			.stdout(if capture_stdout {
+				Stdio::piped()
+			} else {
+				Stdio::null()
+			})
+			.stderr(if self.check_exit_status {
+				Stdio::piped()
+			} else {
+				Stdio::inherit()
+			})
+			// makes sure the process is killed if it has timed out
+			.kill_on_drop(true);
+
+		if let Some(cwd) = &self.cwd {
+			command.current_dir(cwd);
+		}
+
+		tracing::debug!("Spawning process {:?} with args {:?}", self.cmd, self.args);
+		let mut child = command.spawn().map_err(ExecError::CantStart)?;
+
+		let child_stdin = child.stdin.take();
+		let write_stdin = async move {
+			if let (Some(input), Some(mut child_stdin)) = (stdin, child_stdin) {
+				tracing::trace!("Writing {} bytes to stdin of the process", input.len());
+				child_stdin
+					.write_all(input)
+					.await
+					.map_err(ExecError::CantWriteStdin)?;
+
+				// stdin is closed when dropped, letting the process know there's nothing more to read
+			}
+
+			Ok(())
+		};
+
+		// the output has to be read while stdin is being written to.
+		// Otherwise the process may block on writing to a full stdout pipe and never read the rest of its stdin
+		let run = async move {
+			let wait = async {
+				tracing::trace!("Waiting for the process to exit");
+				child.wait_with_output().await.map_err(ExecError::CantWait)
+			};
+
+			let ((), output) = try_join!(write_stdin, wait)?;
+			Ok::<_, ExecError>(output)
+		};
+
+		let output = match self.timeout {
+			Some(timeout) => tokio::time::timeout(timeout, run)
+				.await
+				.map_err(|_| ExecError::TimedOut(timeout))??,
+			None => run.await?,
+		};
+
+		if self.check_exit_status && !output.status.success() {
+			return Err(ExecError::UnsuccessfulExit {
+				status: output.status,
+				stderr: String::from_utf8_lossy(&output.stderr).trim().to_owned(),
+			});
+		}
+
+		tracing::trace!("Process exited with {}", output.status);
+		Ok(output.stdout)
+	}
+}
+
+/// Creates a [`Cmd::Shell`], since [`Exec`] used to only support shell commands
+impl From<String> for Cmd {
+	fn from(cmd: String) -> Self {
+		Self::Shell(cmd)
+	}
+}
+
+impl<S: exec_builder::State> ExecBuilder<S> {
+	/// Executes the `cmd` command line in the shell. See [`Cmd::Shell`]
+	pub fn shell(self, cmd: impl Into<String>) -> ExecBuilder<exec_builder::SetCmd<S>>
+	where
+		S::Cmd: exec_builder::IsUnset,
+	{
+		self.cmd_internal(Cmd::Shell(cmd.into()))
+	}
+
+	/// Executes the `program` directly, without a shell. See [`Cmd::Program`]
+	pub fn program(self, program: impl Into<PathBuf>) -> ExecBuilder<exec_builder::SetCmd<S>>
+	where
+		S::Cmd: exec_builder::IsUnset,
+	{
+		self.cmd_internal(Cmd::Program(program.into()))
+	}
+
+	/// Adds an argument to pass to the command
+	pub fn arg(mut self, arg: impl Into<String>) -> Self {
+		self.args.push(arg.into());
+		self
+	}
+
+	/// Adds several arguments to pass to the command
+	pub fn args<A>(mut self, args: impl IntoIterator<Item = A>) -> Self
+	where
+		A: Into<String>,
+	{
+		self.args.extend(args.into_iter().map(Into::into));
+		self
+	}
+
+	/// Sets an environment variable for the process
+	pub fn env(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+		self.env.push((key.into(), value.into()));
+		self
+	}
 }
 
 impl Fetch for Exec {
 	type Err = ExecError;
 
 	async fn fetch(&mut self) -> Result<Vec<Entry>, Self::Err> {
-		tracing::debug!("Spawning a shell with command {:?}", self.cmd);
-		let out = Command::new(SHELL)
-			.arg(SHELL_RUN_ARG)
-			.arg(&self.cmd)
-			.output()
-			.await
-			.map_err(ExecError::BadCommand)?
-			.stdout;
+		let out = self.run(None, true).await?;
 
 		let out = String::from_utf8(out).map_err(ExecError::BadUtf8)?;
 		tracing::debug!("Got {out:?} from the command");
 
-		let entry = Entry::builder().raw_contents(out).build();
-
-		Ok(vec![entry])
+		match self.protocol {
+			Protocol::Raw => Ok(vec![Entry::builder().raw_contents(out).build()]),
+			#[cfg(feature = "exec-json")]
+			Protocol::JsonLines => json_lines::parse_entries(&out),
+		}
 	}
 }
 
 impl Sink for Exec {
 	type Err = ExecError;
 
-	/// Passes message's body to the stdin of the process. The tag parameter is ignored
+	/// Passes message's body to the stdin of the process. The tag parameter is ignored.
+	///
+	/// Passes the entire message, the reply to ID, and the tag if the protocol is [`Protocol::JsonLines`]
 	///
 	/// # Errors
 	/// * if the process couldn't be started
 	/// * if the data couldn't be passed to the stdin pipe of the process
+	/// * if the process has timed out or exited unsuccessfully, if configured to check for that
+	/// * if the process printed an invalid message ID, if the protocol is [`Protocol::JsonLines`]
+	#[cfg_attr(
+		not(feature = "exec-json"),
+		expect(unused_variables, reason = "only used by the JSON lines protocol")
+	)]
 	async fn send(
 		&mut self,
 		message: &Message,
-		_reply_to: Option<&MessageId>,
-		_tag: Option<&str>,
+		reply_to: Option<&MessageId>,
+		tag: Option<&str>,
 	) -> Result<Option<MessageId>, Self::Err> {
-		let Some(body) = &message.body else {
-			return Ok(None);
-		};
+		match self.protocol {
+			Protocol::Raw => {
+				let Some(body) = &message.body else {
+					return Ok(None);
+				};
 
-		tracing::debug!("Spawning process {:?}", self.cmd);
-		let mut shell = Command::new(SHELL)
-			.arg(SHELL_RUN_ARG)
-			.arg(&self.cmd)
-			.stdin(Stdio::piped())
-			.stdout(Stdio::null())
-			.spawn()
-			.map_err(ExecError::CantStart)?;
-
-		if let Some(stdin) = &mut shell.stdin {
-			tracing::debug!("Writing {body:?} to stdin of the process");
-			stdin
-				.write_all(body.as_bytes())
-				.await
-				.map_err(ExecError::CantWriteStdin)?;
+				tracing::debug!("Passing {body:?} to the process");
+				self.run(Some(body.as_bytes()), false).await?;
+				tracing::trace!("Process successfully exited");
+
+				Ok(None)
+			}
+			#[cfg(feature = "exec-json")]
+			Protocol::JsonLines => {
+				let line = json_lines::serialize_message(message, reply_to, tag)?;
+				let out = String::from_utf8(self.run(Some(&line), true).await?)?;
+
+				json_lines::parse_message_id(&out)
+			}
 		}
+	}
+}
+
+#[cfg(test)]
+#[cfg(unix)]
+mod tests {
+	use assert_matches::assert_matches;
+
+	use super::*;
+
+	#[tokio::test]
+	async fn program_gets_args_env_and_cwd() {
+		let mut exec = Exec::builder()
+			.program("sh")
+			.args(["-c", r#"printf '%s %s %s' "$1" "$GREETING" "$PWD""#, "sh"])
+			.arg("with space")
+			.env("GREETING", "hello")
+			.cwd("/")
+			.build();
+
+		let out = exec.fetch().await.unwrap().remove(0).raw_contents;
+		assert_eq!(out.as_deref(), Some("with space hello /"));
+	}
+
+	#[tokio::test]
+	async fn shell_gets_positional_args() {
+		let mut exec = Exec::builder()
+			.shell(r#"printf '%s' "$1""#)
+			.arg("first")
+			.build();
+
+		let out = exec.fetch().await.unwrap().remove(0).raw_contents;
+		assert_eq!(out.as_deref(), Some("first"));
+	}
+
+	#[tokio::test]
+	async fn unsuccessful_exit_contains_stderr() {
+		let mut exec = Exec::builder()
+			.shell("echo oops >&2; exit 3")
+			.check_exit_status(true)
+			.build();
+
+		assert_matches!(
+			exec.fetch().await,
+			Err(ExecError::UnsuccessfulExit { status, stderr }) if status.code() == Some(3) && stderr == "oops"
+		);
+
+		// ignored by default
+		assert!(Exec::new_shell("exit 3").fetch().await.is_ok());
+	}
+
+	#[tokio::test]
+	async fn timeout_kills_the_process() {
+		let mut exec = Exec::builder()
+			.program("sleep")
+			.arg("10")
+			.timeout(Duration::from_millis(100))
+			.build();
+
+		assert_matches!(exec.fetch().await, Err(ExecError::TimedOut(_)));
+	}
+
+	#[tokio::test]
+	async fn sink_passes_body_to_stdin() {
+		let mut exec = Exec::builder()
+			.shell(r#"[ "$(cat)" = "hello" ]"#)
+			.check_exit_status(true)
+			.build();
+
+		let msg = Message::builder().body("hello".to_owned()).build();
+		exec.send(&msg, None, None).await.unwrap();
+	}
+
+	#[cfg(feature = "exec-json")]
+	#[tokio::test]
+	async fn json_lines_plugin() {
+		let mut source = Exec::builder()
+			.shell(r#"echo '{"id": "1", "title": "first"}'; echo '{"id": "2", "reply_to": "1"}'"#)
+			.protocol(Protocol::JsonLines)
+			.build();
+
+		let entries = source.fetch().await.unwrap();
+		assert_eq!(entries.len(), 2);
+		assert_eq!(entries[0].msg.title.as_deref(), Some("first"));
+
+		let mut sink = Exec::builder()
+			.shell(r#"grep -q '"reply_to":1,"tag":"tag"' && echo '{"id": 2}'"#)
+			.protocol(Protocol::JsonLines)
+			.check_exit_status(true)
+			.build();
+
+		let msg = Message::builder().body("hello".to_owned()).build();
+		let id = sink
+			.send(&msg, Some(&MessageId(1)), Some("tag"))
+			.await
+			.unwrap();
+		assert_eq!(id.map(|id| id.0), Some(2));
+	}
 
-		tracing::trace!("Waiting for the process to exit");
-		shell.wait().await.map_err(ExecError::CantStart)?;
-		tracing::trace!("Process successfully exited");
+	#[cfg(feature = "exec-json")]
+	#[tokio::test]
+	async fn large_stdin_and_stdout_dont_deadlock() {
+		// echoes the message back, which is more than fits into a pipe buffer
+		let mut sink = Exec::builder()
+			.program("cat")
+			.protocol(Protocol::JsonLines)
+			.timeout(Duration::from_secs(10))
+			.build();
 
-		Ok(None)
+		let msg = Message::builder().body("a".repeat(1024 * 1024)).build();
+		let id = sink.send(&msg, None, None).await.unwrap();
+		assert_eq!(id.map(|id| id.0), None);
 	}
 }
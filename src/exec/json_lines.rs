@@ -0,0 +1,259 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+
+//! This module contains the (de)serialization of entries and messages for the [`Protocol::JsonLines`](`super::Protocol::JsonLines`)
+
+use base64::{Engine, engine::general_purpose::STANDARD as BASE64};
+use non_non_full::NonEmptyVec;
+use serde::{Deserialize, Serialize};
+
+use super::ExecError;
+use crate::{
+	entry::{Entry, EntryId},
+	sinks::message::{Attachment, Media, Message, MessageId},
+};
+
+/// An entry, printed by the process on a single line
+#[derive(Deserialize, Debug)]
+struct JsonEntry {
+	id: Option<String>,
+	reply_to: Option<String>,
+	title: Option<String>,
+	body: Option<String>,
+	link: Option<String>,
+	#[serde(default)]
+	media: Vec<JsonMedia>,
+}
+
+/// A message, passed to the process on a single line
+#[derive(Serialize, Debug)]
+struct JsonMessage<'a> {
+	title: Option<&'a str>,
+	body: Option<&'a str>,
+	link: Option<&'a str>,
+	media: Vec<JsonMedia>,
+	reply_to: Option<i64>,
+	tag: Option<&'a str>,
+}
+
+/// ID of the sent message, printed by the process
+#[derive(Deserialize, Debug)]
+struct JsonMessageId {
+	id: Option<i64>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(tag = "type", rename_all = "lowercase")]
+enum JsonMedia {
+	Photo {
+		url: String,
+	},
+	Video {
+		url: String,
+	},
+	Attachment {
+		filename: Option<String>,
+		mime_type: String,
+		/// Contents of the file, encoded as base64
+		data: String,
+	},
+}
+
+/// Parses every non-empty line of the `stdout` as an entry
+pub(super) fn parse_entries(stdout: &str) -> Result<Vec<Entry>, ExecError> {
+	stdout
+		.lines()
+		.map(str::trim)
+		.filter(|line| !line.is_empty())
+		.map(|line| {
+			let entry = serde_json::from_str::<JsonEntry>(line)
+				.map_err(|e| ExecError::BadJson(e, line.to_owned()))?;
+
+			let media = entry
+				.media
+				.into_iter()
+				.map(Media::try_from)
+				.collect::<Result<Vec<_>, _>>()?;
+
+			Ok(Entry {
+				id: entry.id.and_then(EntryId::new),
+				reply_to: entry.reply_to.and_then(EntryId::new),
+				raw_contents: None,
+				msg: Message {
+					title: entry.title,
+					body: entry.body,
+					link: entry.link,
+					media: NonEmptyVec::new(media),
+				},
+			})
+		})
+		.collect()
+}
+
+/// Serializes the message as a single line of JSON, including the trailing newline
+pub(super) fn serialize_message(
+	message: &Message,
+	reply_to: Option<&MessageId>,
+	tag: Option<&str>,
+) -> Result<Vec<u8>, ExecError> {
+	let json_message = JsonMessage {
+		title: message.title.as_deref(),
+		body: message.body.as_deref(),
+		link: message.link.as_deref(),
+		media: message
+			.media
+			.iter()
+			.flatten()
+			.cloned()
+			.map(JsonMedia::from)
+			.collect(),
+		reply_to: reply_to.map(|id| id.0),
+		tag,
+	};
+
+	let mut line = serde_json::to_vec(&json_message)
+		.map_err(|e| ExecError::BadJson(e, format!("{json_message:?}")))?;
+	line.push(b'\n');
+
+	Ok(line)
+}
+
+/// Parses the ID of the sent message from the last non-empty line of the `stdout`. Empty output means the message has no ID
+pub(super) fn parse_message_id(stdout: &str) -> Result<Option<MessageId>, ExecError> {
+	// the process may print other stuff, e.g. logs, before the ID
+	let Some(line) = stdout.lines().map(str::trim).rfind(|line| !line.is_empty()) else {
+		return Ok(None);
+	};
+
+	let JsonMessageId { id } =
+		serde_json::from_str(line).map_err(|e| ExecError::BadJson(e, line.to_owned()))?;
+
+	Ok(id.map(MessageId))
+}
+
+impl TryFrom<JsonMedia> for Media {
+	type Error = ExecError;
+
+	fn try_from(media: JsonMedia) -> Result<Self, Self::Error> {
+		Ok(match media {
+			JsonMedia::Photo { url } => Self::Photo(url),
+			JsonMedia::Video { url } => Self::Video(url),
+			JsonMedia::Attachment {
+				filename,
+				mime_type,
+				data,
+			} => Self::Attachment(Attachment {
+				filename,
+				mime_type,
+				bytes: BASE64.decode(data).map_err(ExecError::BadBase64)?,
+			}),
+		})
+	}
+}
+
+impl From<Media> for JsonMedia {
+	fn from(media: Media) -> Self {
+		match media {
+			Media::Photo(url) => Self::Photo { url },
+			Media::Video(url) => Self::Video { url },
+			Media::Attachment(Attachment {
+				filename,
+				mime_type,
+				bytes,
+			}) => Self::Attachment {
+				filename,
+				mime_type,
+				data: BASE64.encode(bytes),
+			},
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use assert_matches::assert_matches;
+
+	use super::*;
+
+	#[test]
+	fn entry_per_line() {
+		let entries = parse_entries(concat!(
+			r#"{"id": "1", "title": "First", "media": [{"type": "photo", "url": "https://example.com/1.png"}]}"#,
+			"\n\n",
+			r#"{"id": "2", "reply_to": "1", "body": "Second"}"#,
+			"\n",
+		))
+		.unwrap();
+
+		assert_eq!(entries.len(), 2);
+		assert_eq!(entries[0].msg.title.as_deref(), Some("First"));
+		assert_eq!(
+			entries[0].msg.media.as_deref(),
+			Some(&[Media::Photo("https://example.com/1.png".to_owned())][..])
+		);
+		assert_eq!(entries[1].reply_to.as_ref().map(EntryId::as_str), Some("1"));
+		assert_eq!(entries[1].msg.body.as_deref(), Some("Second"));
+	}
+
+	#[test]
+	fn invalid_line_is_an_error() {
+		assert_matches!(
+			parse_entries("not json"),
+			Err(ExecError::BadJson(_, line)) if line == "not json"
+		);
+	}
+
+	#[test]
+	fn message_round_trip() {
+		let msg = Message::builder()
+			.title("title".to_owned())
+			.link("https://example.com".to_owned())
+			.build();
+
+		let line = serialize_message(&msg, Some(&MessageId(5)), Some("tag")).unwrap();
+		let line = String::from_utf8(line).unwrap();
+
+		assert_eq!(
+			line,
+			"{\"title\":\"title\",\"body\":null,\"link\":\"https://example.com\",\"media\":[],\"reply_to\":5,\"tag\":\"tag\"}\n"
+		);
+
+		assert_eq!(
+			parse_message_id("{\"id\": 42}\n").unwrap().map(|id| id.0),
+			Some(42)
+		);
+		assert_eq!(
+			parse_message_id("sending...\n{\"id\": 43}\n\n")
+				.unwrap()
+				.map(|id| id.0),
+			Some(43)
+		);
+		assert_eq!(parse_message_id("").unwrap().map(|id| id.0), None);
+	}
+
+	#[test]
+	fn attachments_are_base64() {
+		let attachment = Media::Attachment(Attachment {
+			filename: Some("report.txt".to_owned()),
+			mime_type: "text/plain".to_owned(),
+			bytes: b"hello".to_vec(),
+		});
+
+		let msg = Message::builder()
+			.media(NonEmptyVec::with_first(attachment.clone()))
+			.build();
+		let line = String::from_utf8(serialize_message(&msg, None, None).unwrap()).unwrap();
+		assert!(line.contains(
+			r#"{"type":"attachment","filename":"report.txt","mime_type":"text/plain","data":"aGVsbG8="}"#
+		));
+
+		let entries = parse_entries(
+			r#"{"media": [{"type": "attachment", "filename": "report.txt", "mime_type": "text/plain", "data": "aGVsbG8="}]}"#,
+		)
+		.unwrap();
+		assert_eq!(entries[0].msg.media.as_deref(), Some(&[attachment][..]));
+	}
+}
@@ -4,7 +4,7 @@
  * file, You can obtain one at https://mozilla.org/MPL/2.0/.
  */
 
-//! This module contains the [`ExternalSave`] trait that implementors can use to add a way to save read filter data and entry to message map externally,
+//! This module contains the [`ExternalSave`] trait that implementors can use to add a way to save read filter data, entry to message map, and source state externally,
 // TODO: rename to something more explicit, e.g. "StoreInPermanentStorage" or something?
 
 use std::{collections::HashMap, convert::Infallible, fmt::Debug, io};
@@ -19,6 +19,8 @@ use crate::{
 
 /// This trait represent some kind of external save destination.
 /// A way to preserve the state of a read filter, i.e. what has and has not been read, across restarts.
+///
+/// It's also used to preserve the state of some sources, e.g. cache validators or file offsets.
 pub trait ExternalSave: MaybeSendSync {
 	/// This function will be called every time something has been marked as read and should be saved externally
 	///
@@ -36,6 +38,27 @@ pub trait ExternalSave: MaybeSendSync {
 		&mut self,
 		map: &HashMap<EntryId, MessageId>,
 	) -> impl Future<Output = Result<(), ExternalSaveError>> + MaybeSend;
+
+	/// This function will be called every time a source has updated some internal state that should be preserved across restarts.
+	///
+	/// For example, it's used to save the cache validators of the [`Http`](`crate::sources::http::Http`) source.
+	/// The `key` identifies the source the state belongs to, e.g. its URL or path,
+	/// so that the states of several sources of the same task don't overwrite each other.
+	///
+	/// Does nothing by default, i.e. the state of sources isn't preserved
+	///
+	/// # Errors
+	/// It may return an error if there has been issues saving, e.g. writing to disk
+	fn save_source_state<T>(
+		&mut self,
+		_key: &str,
+		_state: &T,
+	) -> impl Future<Output = Result<(), ExternalSaveError>> + MaybeSend
+	where
+		T: Serialize + MaybeSync,
+	{
+		async { Ok(()) }
+	}
 }
 
 #[expect(missing_docs, reason = "error message is self-documenting")]
@@ -80,6 +103,17 @@ impl ExternalSave for Infallible {
 	) -> Result<(), ExternalSaveError> {
 		match *self {}
 	}
+
+	async fn save_source_state<T>(
+		&mut self,
+		_key: &str,
+		_state: &T,
+	) -> Result<(), ExternalSaveError>
+	where
+		T: Serialize + MaybeSync,
+	{
+		match *self {}
+	}
 }
 
 #[cfg(feature = "nightly")]
@@ -97,6 +131,17 @@ impl ExternalSave for ! {
 	) -> Result<(), ExternalSaveError> {
 		match *self {}
 	}
+
+	async fn save_source_state<T>(
+		&mut self,
+		_key: &str,
+		_state: &T,
+	) -> Result<(), ExternalSaveError>
+	where
+		T: Serialize + MaybeSync,
+	{
+		match *self {}
+	}
 }
 
 impl<E> ExternalSave for Option<E>
@@ -124,6 +169,17 @@ where
 
 		inner.save_entry_to_msg_map(map).await
 	}
+
+	async fn save_source_state<T>(&mut self, key: &str, state: &T) -> Result<(), ExternalSaveError>
+	where
+		T: Serialize + MaybeSync,
+	{
+		let Some(inner) = self else {
+			return Ok(());
+		};
+
+		inner.save_source_state(key, state).await
+	}
 }
 
 impl<E> ExternalSave for &mut E
@@ -146,4 +202,15 @@ where
 	) -> impl Future<Output = Result<(), ExternalSaveError>> + MaybeSend {
 		(*self).save_entry_to_msg_map(map)
 	}
+
+	fn save_source_state<T>(
+		&mut self,
+		key: &str,
+		state: &T,
+	) -> impl Future<Output = Result<(), ExternalSaveError>> + MaybeSend
+	where
+		T: Serialize + MaybeSync,
+	{
+		(*self).save_source_state(key, state)
+	}
 }
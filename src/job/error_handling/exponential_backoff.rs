@@ -43,6 +43,9 @@ use super::{HandleError, HandleErrorContext, HandleErrorResult};
 /// - Interval-based jobs: Two successful intervals without errors
 /// - Time-based jobs: Two days without errors
 ///
+/// If any of the errors specify how long to wait before retrying (see [`Error::retry_after`]),
+/// e.g. via the `Retry-After` HTTP header, the job is paused for at least that long.
+///
 /// # Example
 /// ```rust
 /// use fetcher::job::error_handling::ExponentialBackoff;
@@ -164,6 +167,9 @@ impl ExponentialBackoff {
 		// reset counter if a while has passed since last error
 		self.reset_error_count(cx.job_trigger);
 
+		// the longest time any of the errors asked us to wait before retrying
+		let retry_after = errors.iter().filter_map(Error::retry_after).max();
+
 		// get all errors that are not network related
 		let fatal_errors = errors.iter().filter(|e| {
 			e.is_network_related()
@@ -175,7 +181,7 @@ impl ExponentialBackoff {
 
 		// if all errors are network related(e.g. internet disconnected), pause for a static amount of time and try again
 		if fatal_errors.clone().count() == 0 {
-			return pause_job(self.pause_duration_net_error, cx).await;
+			return pause_job(at_least(self.pause_duration_net_error, retry_after), cx).await;
 		}
 
 		// check if the attempt limit has been reached
@@ -189,8 +195,10 @@ impl ExponentialBackoff {
 			return false;
 		};
 
-		let pause_duration =
-			exponential_backoff_duration(current_attempt, self.use_jitter, rand::rng());
+		let pause_duration = at_least(
+			exponential_backoff_duration(current_attempt, self.use_jitter, rand::rng()),
+			retry_after,
+		);
 
 		self.last_error_info = Some(ErrorInfo {
 			attempt: current_attempt,
@@ -319,6 +327,17 @@ fn exponential_backoff_duration(attempt: u32, use_jitter: bool, mut rng: impl Rn
 	Duration::from_secs(final_duration)
 }
 
+/// Returns `duration` but not shorter than `retry_after`, if any
+fn at_least(duration: Duration, retry_after: Option<Duration>) -> Duration {
+	match retry_after {
+		Some(retry_after) if retry_after > duration => {
+			tracing::debug!("Extending the pause to {retry_after:?} as requested by the error");
+			retry_after
+		}
+		_ => duration,
+	}
+}
+
 /// Returns `true` if the job should be resumed after the pause.
 /// Returns `false` if the pause was interrupted and the job should stop
 async fn pause_job<Tr: MaybeSync>(dur: Duration, cx: HandleErrorContext<'_, Tr>) -> bool {
@@ -343,7 +362,7 @@ mod tests {
 
 	use rand::Rng;
 
-	use super::exponential_backoff_duration;
+	use super::{at_least, exponential_backoff_duration};
 
 	/// Asserts that [`exponential_backoff_duration`] returned the expected duration (of within range if jitter is enabled)
 	fn check_exp_backoff_duration(
@@ -413,4 +432,11 @@ mod tests {
 			check_exp_backoff_duration(i, true, m(expected_mins), &mut always_extremes);
 		}
 	}
+
+	#[test]
+	fn retry_after_extends_pause() {
+		assert_eq!(at_least(m(1), Some(m(5))), m(5));
+		assert_eq!(at_least(m(5), Some(m(1))), m(5));
+		assert_eq!(at_least(m(5), None), m(5));
+	}
 }
@@ -166,6 +166,9 @@ pub use url;
 
 pub(crate) mod safe_slice;
 
+#[cfg(test)]
+mod test_utils;
+
 #[cfg(test)]
 mod tests {
 	use std::{convert::Infallible, marker::PhantomData};
@@ -18,6 +18,9 @@ use crate::{
 #[cfg(feature = "source-email")]
 use crate::sources::email::ImapError;
 
+#[cfg(feature = "source-gmail")]
+use crate::sources::email::GmailError;
+
 // TODO: better docs
 /// A trait that defines a way to mark an entry as read
 pub trait MarkAsRead: MaybeSendSync {
@@ -42,9 +45,16 @@ pub enum MarkAsReadError {
 	#[error("Failed to mark the email as read")]
 	Imap(#[from] ImapError),
 
+	#[cfg(feature = "source-gmail")]
+	#[error("Failed to mark the email as read via the Gmail API")]
+	Gmail(#[from] GmailError),
+
 	#[error(transparent)]
 	ExternalSave(#[from] ExternalSaveError),
 
+	#[error("Entry ID {0:?} doesn't belong to any of the sources")]
+	UnknownSource(EntryId),
+
 	#[error(transparent)]
 	Other(#[from] Box<dyn Error>),
 }
@@ -9,7 +9,7 @@
 use std::num::TryFromIntError;
 
 use serenity::{
-	all::{CreateEmbed, CreateEmbedFooter},
+	all::{CreateAttachment, CreateEmbed, CreateEmbedFooter},
 	builder::CreateMessage,
 	http::Http as Bot,
 	model::{
@@ -29,6 +29,9 @@ use crate::utils::OptionExt;
 const MAX_MSG_LEN: usize = 2000;
 const MAX_EMBED_DESCIPTION_LEN: usize = 2000;
 
+/// Name of an uploaded [`Attachment`](`crate::sinks::message::Attachment`) that doesn't have one
+const DEFAULT_ATTACHMENT_FILENAME: &str = "attachment";
+
 /// Discord sink. Supports both text channels and DMs with a user
 #[derive(Debug)]
 pub struct Discord {
@@ -157,17 +160,31 @@ impl Sink for Discord {
 				embed = embed.footer(CreateEmbedFooter::new(tag));
 			}
 
+			let mut create_msg = CreateMessage::new();
+
 			if let Some(media) = media {
 				for media in media {
-					if let Media::Photo(image) = media {
-						embed = embed.image(image);
+					match media {
+						Media::Photo(image) => embed = embed.image(image),
+						Media::Video(_) => (),
+						Media::Attachment(attachment) => {
+							let filename = attachment
+								.filename
+								.clone()
+								.unwrap_or_else(|| DEFAULT_ATTACHMENT_FILENAME.to_owned());
+
+							create_msg = create_msg.add_file(CreateAttachment::bytes(
+								attachment.bytes.clone(),
+								filename,
+							));
+						}
 					}
 				}
 			}
 
 			let msg = self
 				.target
-				.send_message(&self.bot, CreateMessage::new().embed(embed))
+				.send_message(&self.bot, create_msg.embed(embed))
 				.await
 				.map_err(|e| SinkError::Discord {
 					source: e,
@@ -12,11 +12,12 @@ pub(crate) mod length_limiter;
 use std::{borrow::Cow, fmt::Debug};
 
 use non_non_full::NonEmptyVec;
+use serde::{Deserialize, Serialize};
 
 use crate::safe_slice::SafeSliceUntilExt;
 
 /// The finalized and composed message meant to be sent to a sink
-#[derive(PartialEq, Eq, Clone, Default, bon::Builder)]
+#[derive(PartialEq, Eq, Clone, Default, Serialize, Deserialize, bon::Builder)]
 pub struct Message {
 	/// title of the message
 	pub title: Option<String>,
@@ -38,13 +39,28 @@ pub struct Message {
 pub struct MessageId(pub i64);
 
 // TODO: rename photo to image mb?
-/// A link to some kind of external media
-#[derive(PartialEq, Eq, Clone, Debug)]
+/// A link to some kind of external media or a file kept in memory
+#[derive(PartialEq, Eq, Clone, Serialize, Deserialize, Debug)]
 pub enum Media {
 	/// A link to a photo
 	Photo(String),
 	/// A link to a video
 	Video(String),
+	/// A file kept in memory, e.g. an email attachment
+	Attachment(Attachment),
+}
+
+/// A file kept in memory
+#[derive(PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct Attachment {
+	/// Name of the file, if known
+	pub filename: Option<String>,
+
+	/// MIME type of the file, e.g. `image/png`
+	pub mime_type: String,
+
+	/// Contents of the file
+	pub bytes: Vec<u8>,
 }
 
 impl Message {
@@ -55,6 +71,24 @@ impl Message {
 	}
 }
 
+impl Attachment {
+	/// Checks if the attachment is an image, i.e. if its MIME type is `image/*`
+	#[must_use]
+	pub fn is_image(&self) -> bool {
+		self.mime_type
+			.get(.."image/".len())
+			.is_some_and(|ty| ty.eq_ignore_ascii_case("image/"))
+	}
+
+	/// Checks if the attachment is a video, i.e. if its MIME type is `video/*`
+	#[must_use]
+	pub fn is_video(&self) -> bool {
+		self.mime_type
+			.get(.."video/".len())
+			.is_some_and(|ty| ty.eq_ignore_ascii_case("video/"))
+	}
+}
+
 impl Debug for Message {
 	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
 		#[expect(clippy::ptr_arg, reason = "the same as Option::map expected signature")]
@@ -85,3 +119,13 @@ where
 		value.build()
 	}
 }
+
+impl Debug for Attachment {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		f.debug_struct("Attachment")
+			.field("filename", &self.filename)
+			.field("mime_type", &self.mime_type)
+			.field("len", &self.bytes.len())
+			.finish()
+	}
+}
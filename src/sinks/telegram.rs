@@ -23,8 +23,9 @@ use teloxide::{
 	payloads::{SendMediaGroupSetters, SendMessageSetters},
 	requests::{Request, Requester, RequesterExt},
 	types::{
-		ChatId, InputFile, InputMedia, InputMediaPhoto, InputMediaVideo, LinkPreviewOptions,
-		Message as TelMessage, MessageId as TelMessageId, ParseMode, ReplyParameters,
+		ChatId, InputFile, InputMedia, InputMediaDocument, InputMediaPhoto, InputMediaVideo,
+		LinkPreviewOptions, Message as TelMessage, MessageId as TelMessageId, ParseMode,
+		ReplyParameters,
 	},
 };
 use tokio::time::sleep;
@@ -129,21 +130,28 @@ impl Telegram {
 
 		// if the message contains media, send it and MAX_MEDIA_MSG_LEN chars first
 		if let Some(media) = media {
-			// send media only (i.e. without caption) if all the media wouldn't fit in a single message
-			if media.len() > 10 {
-				for ch in media.chunks(10) {
-					let sent_msg = self.send_media(ch, None, last_message).await?;
+			// documents can't be mixed with photos and videos in a single media group
+			let (documents, media): (Vec<_>, Vec<_>) = media.iter().partition(|m| is_document(m));
+
+			for media in [media, documents] {
+				if media.is_empty() {
+					continue;
+				}
+
+				// send media only (i.e. without caption) if all the media wouldn't fit in a single message
+				if media.len() > 10 {
+					for ch in media.chunks(10) {
+						let sent_msg = self.send_media(ch, None, last_message).await?;
+						last_message = sent_msg.and_then(|v| v.first().map(|m| m.id));
+					}
+				} else {
+					let media_caption = msg.split_at(MAX_MEDIA_MSG_LEN);
+
+					let sent_msg = self
+						.send_media(&media, media_caption.as_deref(), last_message)
+						.await?;
 					last_message = sent_msg.and_then(|v| v.first().map(|m| m.id));
 				}
-			} else {
-				let media_caption = msg.split_at(MAX_MEDIA_MSG_LEN).expect(
-					"should always return a valid split at least once since msg char len is > max_char_limit",
-				);
-
-				let sent_msg = self
-					.send_media(media, Some(&media_caption), last_message)
-					.await?;
-				last_message = sent_msg.and_then(|v| v.first().map(|m| m.id));
 			}
 		}
 
@@ -219,7 +227,7 @@ impl Telegram {
 	#[tracing::instrument(level = "trace", skip(self))]
 	async fn send_media(
 		&self,
-		media: &[Media],
+		media: &[&Media],
 		mut caption: Option<&str>,
 		mut reply_to: Option<TelMessageId>,
 	) -> Result<Option<Vec<TelMessage>>, SinkError> {
@@ -239,11 +247,8 @@ impl Telegram {
 				macro_rules! input_media {
 					// $type example: Photo
 					// $full_type example: InputMediaPhoto
-					($type:tt, $full_type:tt, $url:expr) => {{
-						let url = Url::parse($url).map_err(|e| InvalidUrlError(e, $url.clone()))?;
-
-						let input_media =
-							$full_type::new(InputFile::url(url)).parse_mode(ParseMode::Html);
+					($type:tt, $full_type:tt, $file:expr) => {{
+						let input_media = $full_type::new($file).parse_mode(ParseMode::Html);
 
 						let input_media = if let Some(caption) = caption.take() {
 							input_media.caption(caption)
@@ -255,9 +260,30 @@ impl Telegram {
 					}};
 				}
 
-				let m = match &m {
-					Media::Photo(url) => input_media!(Photo, InputMediaPhoto, url),
-					Media::Video(url) => input_media!(Video, InputMediaVideo, url),
+				let url_file = |url: &String| {
+					Url::parse(url)
+						.map(InputFile::url)
+						.map_err(|e| InvalidUrlError(e, url.clone()))
+				};
+
+				let m = match m {
+					Media::Photo(url) => input_media!(Photo, InputMediaPhoto, url_file(url)?),
+					Media::Video(url) => input_media!(Video, InputMediaVideo, url_file(url)?),
+					Media::Attachment(attachment) => {
+						let file = InputFile::memory(attachment.bytes.clone());
+						let file = match &attachment.filename {
+							Some(filename) => file.file_name(filename.clone()),
+							None => file,
+						};
+
+						if attachment.is_image() {
+							input_media!(Photo, InputMediaPhoto, file)
+						} else if attachment.is_video() {
+							input_media!(Video, InputMediaVideo, file)
+						} else {
+							input_media!(Document, InputMediaDocument, file)
+						}
+					}
 				};
 
 				Ok(m)
@@ -375,6 +401,14 @@ impl Telegram {
 	}
 }
 
+/// Checks if the media has to be sent as a document, i.e. isn't a photo or a video
+fn is_document(media: &Media) -> bool {
+	match media {
+		Media::Photo(_) | Media::Video(_) => false,
+		Media::Attachment(attachment) => !attachment.is_image() && !attachment.is_video(),
+	}
+}
+
 type HeadBodyTailMedia<'a> = (
 	Option<String>,
 	Option<String>,
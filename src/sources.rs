@@ -5,19 +5,30 @@
  */
 
 //! This module contains [`Source`]s that can fetch data and create new [`Entries`](`Entry`) out of it
-// TODO: add google calendar source. Google OAuth2 is already implemented :)
 
 pub mod error;
 
+pub mod dir;
+pub mod fallback;
 pub mod file;
-
-pub use self::file::File;
+pub mod streaming;
+mod tuples;
+
+pub use self::{
+	dir::Dir,
+	fallback::Fallback,
+	file::File,
+	streaming::{Batched, FetchStream},
+	tuples::PartialResults,
+};
 pub use crate::exec::Exec;
 
 #[cfg(feature = "source-email")]
 pub mod email;
 #[cfg(feature = "source-email")]
 pub use self::email::Email;
+#[cfg(feature = "source-gmail")]
+pub use self::email::Gmail;
 
 #[cfg(feature = "source-reddit")]
 pub mod reddit;
@@ -29,6 +40,26 @@ pub mod http;
 #[cfg(feature = "source-http")]
 pub use self::http::Http;
 
+#[cfg(feature = "source-webhook")]
+pub mod webhook;
+#[cfg(feature = "source-webhook")]
+pub use self::webhook::Webhook;
+
+#[cfg(feature = "source-sqlite")]
+pub mod sqlite;
+#[cfg(feature = "source-sqlite")]
+pub use self::sqlite::Sqlite;
+
+#[cfg(feature = "record-replay")]
+pub mod record_replay;
+#[cfg(feature = "record-replay")]
+pub use self::record_replay::RecordReplay;
+
+#[cfg(feature = "source-calendar")]
+pub mod calendar;
+#[cfg(feature = "source-calendar")]
+pub use self::calendar::Calendar;
+
 use self::error::SourceError;
 use crate::{
 	entry::{Entry, EntryId},
@@ -36,6 +67,7 @@ use crate::{
 	read_filter::MarkAsRead,
 };
 
+use futures::{Stream, StreamExt, TryStreamExt, stream};
 use std::convert::Infallible;
 
 // TODO: better docs
@@ -55,6 +87,18 @@ pub trait Fetch: MaybeSendSync {
 	/// Refer to implementator's docs.
 	fn fetch(&mut self) -> impl Future<Output = Result<Vec<Entry>, Self::Err>> + MaybeSend;
 
+	/// Starts fetching the entries in batches as they become available, if the source supports it. See [`FetchStream`] and [`Batched`].
+	///
+	/// The stream doesn't borrow the source, so it can still be used, e.g. to mark entries of a batch as read, while the next ones are being fetched.
+	/// Nothing should be fetched until the stream is polled, since wrappers may drop it if another of their sources doesn't support fetching in batches.
+	/// Returns `None` by default, in which case [`Fetch::fetch`] should be used instead
+	fn fetch_batches(
+		&mut self,
+	) -> Option<impl Stream<Item = Result<Vec<Entry>, Self::Err>> + MaybeSend + use<Self>> {
+		// the stream is never created, it just has to have some type
+		None::<stream::Map<stream::Empty<Infallible>, fn(Infallible) -> _>>
+	}
+
 	/// Converts the value into a source with the provided read-filter, implementing [`MarkAsRead`].
 	fn into_source_with_read_filter<RF>(self, read_filter: RF) -> SourceWithSharedRF<Self, RF>
 	where
@@ -114,6 +158,18 @@ where
 
 		Ok(entries)
 	}
+
+	/// Fetches the batches of all sources one after another if all of them support it
+	fn fetch_batches(
+		&mut self,
+	) -> Option<impl Stream<Item = Result<Vec<Entry>, Self::Err>> + MaybeSend + use<T>> {
+		let batches = self
+			.iter_mut()
+			.map(Fetch::fetch_batches)
+			.collect::<Option<Vec<_>>>()?;
+
+		Some(stream::iter(batches).flatten().map_err(Into::into))
+	}
 }
 
 impl Fetch for () {
@@ -154,8 +210,15 @@ where
 
 		inner.fetch().await
 	}
+
+	fn fetch_batches(
+		&mut self,
+	) -> Option<impl Stream<Item = Result<Vec<Entry>, Self::Err>> + MaybeSend + use<F>> {
+		self.as_mut()?.fetch_batches()
+	}
 }
-impl<F> Fetch for &mut F
+
+impl<'a, F> Fetch for &'a mut F
 where
 	F: Fetch,
 {
@@ -164,6 +227,12 @@ where
 	fn fetch(&mut self) -> impl Future<Output = Result<Vec<Entry>, Self::Err>> + MaybeSend {
 		(*self).fetch()
 	}
+
+	fn fetch_batches(
+		&mut self,
+	) -> Option<impl Stream<Item = Result<Vec<Entry>, Self::Err>> + MaybeSend + use<'a, F>> {
+		(*self).fetch_batches()
+	}
 }
 
 impl<F, RF> Source for SourceWithSharedRF<F, RF>
@@ -190,6 +259,12 @@ where
 	async fn fetch(&mut self) -> Result<Vec<Entry>, Self::Err> {
 		self.source.fetch().await
 	}
+
+	fn fetch_batches(
+		&mut self,
+	) -> Option<impl Stream<Item = Result<Vec<Entry>, Self::Err>> + MaybeSend + use<F, RF>> {
+		self.source.fetch_batches()
+	}
 }
 
 impl<F, RF> MarkAsRead for SourceWithSharedRF<F, RF>
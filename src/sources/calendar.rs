@@ -0,0 +1,638 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+
+#![doc = "This module contains the [`Calendar`] source that fetches events from Google Calendar"]
+
+use chrono::{DateTime, FixedOffset, NaiveDate, SecondsFormat, TimeDelta, Utc};
+use serde::Deserialize;
+use std::{
+	convert::Infallible,
+	fmt::{self, Debug, Display, Write as _},
+	time::Duration,
+};
+use url::Url;
+
+use super::{
+	Fetch,
+	http::{HttpClient, HttpError, Json, Request, send_request},
+};
+use crate::{
+	StaticStr,
+	auth::{Google as GoogleAuth, google::GoogleOAuth2Error as GoogleAuthError},
+	entry::{Entry, EntryId},
+	error::Error,
+	external_save::{ExternalSave, ExternalSaveError},
+	sinks::message::Message,
+};
+
+/// URL of the Google Calendar REST API
+pub const DEFAULT_API_URL: &str = "https://www.googleapis.com/calendar/v3/";
+
+/// How far into the future to look for upcoming events by default
+pub const DEFAULT_LOOK_AHEAD: Duration = Duration::from_hours(24);
+
+/// ID of the main calendar of the user
+const PRIMARY_CALENDAR: &str = "primary";
+
+/// How much earlier than the last sync to look for changes, to not miss any if the local clock is behind the one of Google
+const SYNC_OVERLAP: TimeDelta = TimeDelta::minutes(5);
+
+#[expect(clippy::doc_markdown, reason = "false positive")]
+/// Source that fetches upcoming and changed events from Google Calendar, authenticating with [`Google OAuth2`](`GoogleAuth`).
+///
+/// Every event that starts (or is still ongoing) within [`Calendar::look_ahead`] becomes an entry with the ID of the event,
+/// its start time and location in the body, and a link to the event.
+/// If [`Calendar::look_ahead`] is set to 15 minutes, that makes it possible to notify about meetings 15 minutes before they start.
+///
+/// If [`Calendar::changes`] is enabled, every change to and cancellation of a future event since the last fetch
+/// becomes an entry as well. Its ID is unique for each version of the event and it has the ID of the event as its [`Entry::reply_to`].
+/// Events that have been created since the last fetch aren't reported until they become upcoming.
+///
+/// The time of the last fetch is only kept in memory and is thus lost when the program is restarted.
+/// Use [`Calendar::with_external_save`] to preserve it across restarts.
+///
+/// ```
+/// # use fetcher::{auth::Google, sources::calendar::Calendar};
+/// # use std::time::Duration;
+/// let calendar = Calendar::builder()
+///     .auth(Google::new("client_id", "client_secret", "refresh_token"))
+///     .calendar("primary")
+///     .calendar("team@group.calendar.google.com")
+///     .look_ahead(Duration::from_mins(15))
+///     .build();
+/// ```
+pub struct Calendar<E = Infallible> {
+	/// IDs of the calendars to fetch events from. Uses the primary calendar of the user if empty
+	pub calendars: Vec<StaticStr>,
+
+	/// Google OAuth2 authenticator with read access to the calendars
+	auth: GoogleAuth,
+
+	/// How far into the future to look for upcoming events
+	pub look_ahead: Duration,
+
+	/// Whether to report changes to and cancellations of events since the last fetch
+	pub changes: bool,
+
+	/// URL of the Google Calendar REST API
+	pub api_url: Url,
+
+	/// HTTP client to send the requests with. Uses the [default client](`HttpClient::shared_default`) if `None`
+	pub client: Option<HttpClient>,
+
+	/// When the calendars were last fetched successfully
+	last_sync: Option<DateTime<Utc>>,
+	external_save: Option<E>,
+}
+
+#[bon::bon]
+impl Calendar {
+	/// Creates a new [`Calendar`] source using the builder syntax
+	#[builder]
+	pub fn new(
+		/// IDs of the calendars to fetch events from. Uses the primary calendar of the user if empty
+		#[builder(field)]
+		calendars: Vec<StaticStr>,
+
+		/// [`Google OAuth2`](`GoogleAuth`) authenticator with read access to the calendars
+		auth: GoogleAuth,
+
+		/// How far into the future to look for upcoming events. Defaults to [`DEFAULT_LOOK_AHEAD`]
+		#[builder(default = DEFAULT_LOOK_AHEAD)]
+		look_ahead: Duration,
+
+		/// Whether to report changes to and cancellations of events since the last fetch. Enabled by default
+		#[builder(default = true)]
+		changes: bool,
+
+		/// URL of the Google Calendar REST API. Defaults to [`DEFAULT_API_URL`]
+		#[builder(default = Url::parse(DEFAULT_API_URL).expect("default API URL should be valid"))]
+		api_url: Url,
+
+		/// HTTP client to send the requests with. Uses the [default client](`HttpClient::shared_default`) if `None`
+		client: Option<HttpClient>,
+	) -> Self {
+		Self {
+			calendars,
+			auth,
+			look_ahead,
+			changes,
+			api_url,
+			client,
+			last_sync: None,
+			external_save: None,
+		}
+	}
+}
+
+#[expect(missing_docs, reason = "error message is self-documenting")]
+#[derive(thiserror::Error, Debug)]
+pub enum CalendarError {
+	#[error("Can't authenticate with Google")]
+	GoogleAuth(#[from] GoogleAuthError),
+
+	#[error("HTTP error")]
+	Http(#[from] HttpError),
+
+	#[error("API URL {0} can't be a base URL")]
+	InvalidApiUrl(Url),
+
+	#[error("Invalid response from the Google Calendar API: {1:?}")]
+	BadResponse(#[source] serde_json::Error, String),
+
+	#[error("Failed to save the time of the last sync")]
+	ExternalSave(#[from] ExternalSaveError),
+}
+
+/// A page of events as returned by the API
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct EventsPage {
+	#[serde(default)]
+	items: Vec<Json>,
+	next_page_token: Option<String>,
+}
+
+/// The parts of an event this source uses
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct Event {
+	id: String,
+	status: Option<String>,
+	summary: Option<String>,
+	description: Option<String>,
+	location: Option<String>,
+	html_link: Option<String>,
+	start: Option<EventTime>,
+	created: Option<DateTime<Utc>>,
+	updated: Option<DateTime<Utc>>,
+}
+
+/// Start or end of an event. Only `date` is present for all-day events
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct EventTime {
+	date_time: Option<DateTime<FixedOffset>>,
+	date: Option<NaiveDate>,
+}
+
+impl<S: calendar_builder::State> CalendarBuilder<S> {
+	/// Adds a calendar to fetch events from, e.g. `primary` or `team@group.calendar.google.com`
+	pub fn calendar(mut self, id: impl Into<StaticStr>) -> Self {
+		self.calendars.push(id.into());
+		self
+	}
+}
+
+impl Calendar {
+	/// Starts reporting changes since the previously saved `last_sync`, if there is one.
+	///
+	/// [`ExternalSave::save_source_state`] is called with the time of the last sync every time the calendars have been fetched.
+	/// The key is the IDs of the calendars, e.g. `google-calendar:primary`
+	pub fn with_external_save<E>(
+		self,
+		last_sync: Option<DateTime<Utc>>,
+		external_save: E,
+	) -> Calendar<E>
+	where
+		E: ExternalSave,
+	{
+		Calendar {
+			calendars: self.calendars,
+			auth: self.auth,
+			look_ahead: self.look_ahead,
+			changes: self.changes,
+			api_url: self.api_url,
+			client: self.client,
+			last_sync,
+			external_save: Some(external_save),
+		}
+	}
+}
+
+impl<E> Calendar<E> {
+	/// Returns when the calendars were last fetched successfully
+	#[must_use]
+	pub fn last_sync(&self) -> Option<DateTime<Utc>> {
+		self.last_sync
+	}
+}
+
+impl<E> Fetch for Calendar<E>
+where
+	E: ExternalSave,
+{
+	type Err = CalendarError;
+
+	/// Fetches upcoming events of all calendars and, if enabled, changes to events since the last fetch
+	///
+	/// # Errors
+	/// This function may error if the authentication failed, the network connection is down,
+	/// or the API returns an error or a garbage response
+	async fn fetch(&mut self) -> Result<Vec<Entry>, Self::Err> {
+		let client = match &self.client {
+			Some(client) => client.clone(),
+			None => HttpClient::shared_default()?,
+		};
+
+		let calendars = if self.calendars.is_empty() {
+			vec![StaticStr::from(PRIMARY_CALENDAR)]
+		} else {
+			self.calendars.clone()
+		};
+
+		let now = Utc::now();
+		let mut entries = Vec::new();
+
+		for calendar in &calendars {
+			entries.extend(self.fetch_upcoming(&client, calendar, now).await?);
+
+			if self.changes
+				&& let Some(last_sync) = self.last_sync
+			{
+				entries.extend(
+					self.fetch_changed(&client, calendar, now, last_sync)
+						.await?,
+				);
+			}
+		}
+
+		self.last_sync = Some(now);
+
+		if let Some(external_save) = &mut self.external_save {
+			let ids = calendars.iter().map(|id| &**id).collect::<Vec<_>>();
+			let key = format!("google-calendar:{}", ids.join(","));
+			external_save.save_source_state(&key, &now).await?;
+		}
+
+		Ok(entries)
+	}
+}
+
+impl<E> Calendar<E> {
+	/// Fetches events that are happening between `now` and [`Calendar::look_ahead`]
+	async fn fetch_upcoming(
+		&mut self,
+		client: &HttpClient,
+		calendar: &str,
+		now: DateTime<Utc>,
+	) -> Result<Vec<Entry>, CalendarError> {
+		let until = TimeDelta::from_std(self.look_ahead)
+			.ok()
+			.and_then(|look_ahead| now.checked_add_signed(look_ahead))
+			.unwrap_or(DateTime::<Utc>::MAX_UTC);
+
+		let query = [
+			("timeMin", rfc3339(now)),
+			("timeMax", rfc3339(until)),
+			("singleEvents", "true".to_owned()),
+			("orderBy", "startTime".to_owned()),
+		];
+
+		let events = self.list_events(client, calendar, &query).await?;
+		tracing::debug!("Got {} upcoming events in {calendar}", events.len());
+
+		Ok(events
+			.into_iter()
+			.map(|(event, json)| {
+				Entry::builder()
+					.id(event.id.clone())
+					.raw_contents(json.to_string())
+					.msg(event_message(&event, None))
+					.build()
+			})
+			.collect())
+	}
+
+	/// Fetches future events that have been changed or cancelled since `last_sync`, minus [`SYNC_OVERLAP`]
+	async fn fetch_changed(
+		&mut self,
+		client: &HttpClient,
+		calendar: &str,
+		now: DateTime<Utc>,
+		last_sync: DateTime<Utc>,
+	) -> Result<Vec<Entry>, CalendarError> {
+		let query = [
+			("timeMin", rfc3339(now)),
+			("updatedMin", rfc3339(last_sync - SYNC_OVERLAP)),
+			("singleEvents", "true".to_owned()),
+			("showDeleted", "true".to_owned()),
+		];
+
+		let events = self.list_events(client, calendar, &query).await?;
+		tracing::debug!("Got {} changed events in {calendar}", events.len());
+
+		Ok(events
+			.into_iter()
+			// new events are reported once they become upcoming
+			.filter(|(event, _)| event.created.is_none_or(|created| created < last_sync))
+			.map(|(event, json)| {
+				let change = if event.status.as_deref() == Some("cancelled") {
+					"Cancelled"
+				} else {
+					"Changed"
+				};
+
+				let version = event
+					.updated
+					.map_or(0, |updated| updated.timestamp_millis());
+
+				Entry::builder()
+					.id(format!("{}@{version}", event.id))
+					.maybe_reply_to(EntryId::new(event.id.clone()))
+					.raw_contents(json.to_string())
+					.msg(event_message(&event, Some(change)))
+					.build()
+			})
+			.collect())
+	}
+
+	/// Lists all events of the `calendar` matching the `query`, following all pages
+	async fn list_events(
+		&mut self,
+		client: &HttpClient,
+		calendar: &str,
+		query: &[(&'static str, String)],
+	) -> Result<Vec<(Event, Json)>, CalendarError> {
+		let mut url = self.api_url.clone();
+		url.path_segments_mut()
+			.map_err(|()| CalendarError::InvalidApiUrl(self.api_url.clone()))?
+			.pop_if_empty()
+			.extend(["calendars", calendar, "events"]);
+
+		let mut events = Vec::new();
+		let mut page_token = None;
+
+		loop {
+			let mut request =
+				Request::builder().bearer_auth(self.auth.access_token().await?.to_owned());
+			for (key, value) in query {
+				request = request.query(*key, value.clone());
+			}
+
+			if let Some(page_token) = page_token.take() {
+				request = request.query("pageToken", page_token);
+			}
+
+			let response = send_request(client, &request.build(), &url).await?;
+			let page = serde_json::from_str::<EventsPage>(&response)
+				.map_err(|e| CalendarError::BadResponse(e, response.clone()))?;
+
+			for json in page.items {
+				let event = Event::deserialize(&json)
+					.map_err(|e| CalendarError::BadResponse(e, json.to_string()))?;
+
+				events.push((event, json));
+			}
+
+			match page.next_page_token {
+				Some(next) => page_token = Some(next),
+				None => return Ok(events),
+			}
+		}
+	}
+}
+
+/// Creates a message with the summary, start time, location, description, and link of the event.
+///
+/// The `change`, e.g. `Changed`, is put in front of the title if it's present
+fn event_message(event: &Event, change: Option<&str>) -> Message {
+	let summary = event.summary.as_deref().unwrap_or("(No title)");
+	let title = match change {
+		Some(change) => format!("{change}: {summary}"),
+		None => summary.to_owned(),
+	};
+
+	let mut body = String::new();
+	if let Some(start) = &event.start {
+		_ = writeln!(body, "Starts: {start}");
+	}
+
+	if let Some(location) = &event.location {
+		_ = writeln!(body, "Location: {location}");
+	}
+
+	if let Some(description) = &event.description {
+		if !body.is_empty() {
+			body.push('\n');
+		}
+
+		body.push_str(description);
+	}
+
+	let body = body.trim_end().to_owned();
+
+	Message::builder()
+		.title(title)
+		.maybe_body((!body.is_empty()).then_some(body))
+		.maybe_link(event.html_link.clone())
+		.build()
+}
+
+fn rfc3339(time: DateTime<Utc>) -> String {
+	time.to_rfc3339_opts(SecondsFormat::Secs, true)
+}
+
+impl Display for EventTime {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match (self.date_time, self.date) {
+			(Some(date_time), _) => write!(f, "{}", date_time.format("%Y-%m-%d %H:%M %:z")),
+			(None, Some(date)) => write!(f, "{date} (all day)"),
+			(None, None) => f.write_str("unknown"),
+		}
+	}
+}
+
+impl Error for CalendarError {
+	fn is_network_related(&self) -> Option<&dyn Error> {
+		match self {
+			Self::GoogleAuth(e) if e.is_network_related().is_some() => Some(self),
+			Self::Http(e) if e.is_network_related().is_some() => Some(self),
+			_ => None,
+		}
+	}
+
+	fn retry_after(&self) -> Option<Duration> {
+		match self {
+			Self::Http(e) => e.retry_after(),
+			_ => None,
+		}
+	}
+}
+
+impl<E> Debug for Calendar<E> {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.debug_struct("Calendar")
+			.field("calendars", &self.calendars)
+			.field("look_ahead", &self.look_ahead)
+			.field("changes", &self.changes)
+			.field("api_url", &self.api_url.as_str())
+			.field("last_sync", &self.last_sync)
+			.finish_non_exhaustive()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::{
+		sources::http::mock_server::{self, Request, Response},
+		test_utils::SourceStates,
+	};
+
+	fn calendar(api_url: Url, calendars: &[&'static str]) -> Calendar {
+		let mut builder = Calendar::builder();
+		for id in calendars {
+			builder = builder.calendar(*id);
+		}
+
+		builder
+			.auth(GoogleAuth::with_access_token("token"))
+			.api_url(api_url)
+			.build()
+	}
+
+	fn respond_with_pages(request: &Request) -> Response {
+		let json = if request.target.contains("pageToken=2") {
+			r#"{ "items": [
+				{ "id": "holiday", "summary": "Holiday", "start": { "date": "2030-01-01" } }
+			] }"#
+		} else {
+			r#"{ "nextPageToken": "2", "items": [
+				{
+					"id": "standup",
+					"summary": "Standup",
+					"location": "Room 1",
+					"description": "Daily sync",
+					"htmlLink": "https://calendar.google.com/event?eid=standup",
+					"start": { "dateTime": "2030-01-01T10:00:00+02:00" }
+				}
+			] }"#
+		};
+
+		Response::ok(json)
+	}
+
+	#[tokio::test]
+	async fn upcoming_events_follow_pages() {
+		let server = mock_server::start(respond_with_pages).await;
+		let mut calendar = calendar(server.url.clone(), &["team#1"]);
+
+		let entries = calendar.fetch().await.unwrap();
+
+		let ids = entries
+			.iter()
+			.map(|entry| entry.id.as_ref().unwrap().as_str())
+			.collect::<Vec<_>>();
+		assert_eq!(ids, ["standup", "holiday"]);
+
+		let standup = &entries[0].msg;
+		assert_eq!(standup.title.as_deref(), Some("Standup"));
+		assert_eq!(
+			standup.body.as_deref(),
+			Some("Starts: 2030-01-01 10:00 +02:00\nLocation: Room 1\n\nDaily sync")
+		);
+		assert_eq!(
+			standup.link.as_deref(),
+			Some("https://calendar.google.com/event?eid=standup")
+		);
+		assert_eq!(
+			entries[1].msg.body.as_deref(),
+			Some("Starts: 2030-01-01 (all day)")
+		);
+
+		let requests = server.requests();
+		assert_eq!(requests.len(), 2);
+		assert!(
+			requests[0]
+				.target
+				.starts_with("/calendars/team%231/events?timeMin=")
+		);
+		assert!(requests[0].target.contains("singleEvents=true"));
+		assert!(requests[1].target.ends_with("pageToken=2"));
+		assert!(
+			requests
+				.iter()
+				.all(|request| request.header("authorization") == Some("Bearer token"))
+		);
+	}
+
+	fn respond_with_changes(request: &Request) -> Response {
+		let json = if request.target.contains("updatedMin=") {
+			r#"{ "items": [
+				{ "id": "moved", "summary": "Review", "created": "2020-01-01T00:00:00Z", "updated": "2030-01-01T00:00:00Z" },
+				{ "id": "dropped", "status": "cancelled", "created": "2020-01-01T00:00:00Z", "updated": "2030-01-01T00:00:00Z" },
+				{ "id": "new", "summary": "Party", "created": "2999-01-01T00:00:00Z", "updated": "2999-01-01T00:00:00Z" }
+			] }"#
+		} else {
+			r#"{ "items": [] }"#
+		};
+
+		Response::ok(json)
+	}
+
+	#[tokio::test]
+	async fn changes_since_last_fetch_reply_to_the_event() {
+		let server = mock_server::start(respond_with_changes).await;
+		let mut calendar = calendar(server.url.clone(), &[]);
+
+		assert!(calendar.fetch().await.unwrap().is_empty());
+		assert!(
+			server.requests()[0]
+				.target
+				.starts_with("/calendars/primary/events?"),
+			"should use the primary calendar by default"
+		);
+
+		let entries = calendar.fetch().await.unwrap();
+		let entries = entries
+			.iter()
+			.map(|entry| {
+				(
+					entry.id.as_ref().unwrap().as_str(),
+					entry.reply_to.as_ref().unwrap().as_str(),
+					entry.msg.title.as_deref().unwrap(),
+				)
+			})
+			.collect::<Vec<_>>();
+
+		assert_eq!(
+			entries,
+			[
+				("moved@1893456000000", "moved", "Changed: Review"),
+				("dropped@1893456000000", "dropped", "Cancelled: (No title)"),
+			]
+		);
+	}
+
+	#[tokio::test]
+	async fn last_sync_is_saved_and_restored() {
+		let server = mock_server::start(respond_with_changes).await;
+		let states = SourceStates::default();
+
+		let mut first_run =
+			calendar(server.url.clone(), &[]).with_external_save(None, states.clone());
+		first_run.fetch().await.unwrap();
+
+		let last_sync = states
+			.get::<DateTime<Utc>>("google-calendar:primary")
+			.unwrap();
+		assert_eq!(first_run.last_sync(), Some(last_sync));
+
+		// pretend the program has been restarted
+		let mut second_run =
+			calendar(server.url.clone(), &[]).with_external_save(Some(last_sync), states);
+		assert_eq!(second_run.fetch().await.unwrap().len(), 2);
+
+		let updated_min = rfc3339(last_sync - SYNC_OVERLAP);
+		assert!(
+			server.requests()[2]
+				.target
+				.contains(&format!("updatedMin={}", updated_min.replace(':', "%3A"))),
+			"should look for changes a bit before the last sync"
+		);
+	}
+}
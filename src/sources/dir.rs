@@ -0,0 +1,434 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+
+//! Local directory source
+//!
+//! This module contains the [`Dir`] source and the [`Glob`] pattern it uses to match files
+
+use chrono::{DateTime, SecondsFormat, Utc};
+use regex::Regex;
+use std::{
+	collections::HashSet,
+	fmt::{self, Debug},
+	fs::Metadata,
+	io,
+	path::{Path, PathBuf},
+};
+use tokio::fs;
+
+use super::{Fetch, error::SourceError};
+use crate::{entry::Entry, sinks::message::Message};
+
+/// Directory source. Lists files in a directory and emits an [`Entry`] per file.
+///
+/// The contents of the file are put into [`raw_contents`](`crate::entry::Entry::raw_contents`)
+/// and its path relative to the directory into the title of the message.
+/// The ID of the entry is made from the path of the file and either its modification time or a hash of its contents (see [`FileId`]),
+/// which makes it possible for a read filter (e.g. [`NotPresent`](`crate::read_filter::NotPresent`)) to notice both new and changed files.
+///
+/// Files that disappear while the directory is being listed and dangling symlinks are skipped with a warning.
+/// Every directory is only listed once, even if a symlink points back to one of its parents
+///
+/// # Example
+/// ```
+/// # use fetcher::sources::dir::{Dir, FileId};
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// let dir = Dir::builder()
+///     .path("/srv/reports")
+///     .glob("**/*.csv")?
+///     .recursive(true)
+///     .id(FileId::ContentHash)
+///     .build();
+/// # Ok(())
+/// # }
+/// ```
+#[derive(bon::Builder)]
+pub struct Dir {
+	/// Path of the directory
+	#[builder(into)]
+	pub path: PathBuf,
+
+	/// Only files whose path relative to the directory matches this pattern are emitted. All files are emitted if `None`
+	#[builder(with = |glob: &str| -> Result<_, regex::Error> { Glob::new(glob) })]
+	pub glob: Option<Glob>,
+
+	/// Whether to list the subdirectories as well
+	#[builder(default)]
+	pub recursive: bool,
+
+	/// What to make the ID of an entry from, alongside the path of the file
+	#[builder(default)]
+	pub id: FileId,
+
+	/// IDs of the files found by the last fetch if the [`FileId`] doesn't depend on the contents,
+	/// to avoid reading unchanged files again
+	#[builder(skip)]
+	seen: HashSet<String>,
+}
+
+/// What to make the ID of an entry from, alongside the path of the file
+#[derive(Clone, Copy, Default, Debug)]
+pub enum FileId {
+	/// Modification time of the file.
+	///
+	/// Only new and modified files are read and emitted.
+	/// Files that haven't changed since the previous fetch are skipped since they would have the same ID anyway
+	#[default]
+	Modified,
+
+	/// Hash of the contents of the file.
+	///
+	/// Unlike [`FileId::Modified`], this doesn't re-emit files that were overwritten with the same contents.
+	/// The hash is stable across restarts and versions of fetcher
+	ContentHash,
+}
+
+/// A shell-like pattern matched against the path of a file relative to the directory, using `/` as the separator.
+///
+/// Supports `*` that matches any part of a file or directory name, `?` that matches a single character,
+/// and `**` that matches any number of directories, e.g. `**/*.csv` or `reports/????-??-??.txt`
+#[derive(Clone, Debug)]
+pub struct Glob(Regex);
+
+impl Glob {
+	/// Creates a new pattern from the `glob` string
+	///
+	/// # Errors
+	/// This method fails if the resulting pattern is too large
+	pub fn new(glob: &str) -> Result<Self, regex::Error> {
+		let mut re = String::from("^");
+		let mut chars = glob.chars().peekable();
+
+		while let Some(c) = chars.next() {
+			match c {
+				'*' if chars.peek() == Some(&'*') => {
+					chars.next();
+
+					// "**/" also matches no directories at all
+					if chars.peek() == Some(&'/') {
+						chars.next();
+						re.push_str("(?:.*/)?");
+					} else {
+						re.push_str(".*");
+					}
+				}
+				'*' => re.push_str("[^/]*"),
+				'?' => re.push_str("[^/]"),
+				c => re.push_str(&regex::escape(c.encode_utf8(&mut [0; 4]))),
+			}
+		}
+
+		re.push('$');
+		Regex::new(&re).map(Self)
+	}
+
+	/// Returns true if the `path` matches the pattern
+	#[must_use]
+	pub fn is_match(&self, path: &str) -> bool {
+		self.0.is_match(path)
+	}
+}
+
+impl Fetch for Dir {
+	type Err = SourceError;
+
+	/// List the files in the directory, returning an entry per file, sorted by path
+	#[tracing::instrument(skip_all, fields(path = %self.path.display()))]
+	async fn fetch(&mut self) -> Result<Vec<Entry>, SourceError> {
+		let mut entries = Vec::new();
+		let mut seen = HashSet::new();
+
+		for (path, metadata) in self.list_files().await? {
+			let relative_path = path
+				.strip_prefix(&self.path)
+				.unwrap_or(&path)
+				.to_string_lossy()
+				.replace(std::path::MAIN_SEPARATOR, "/");
+
+			if let Some(glob) = &self.glob
+				&& !glob.is_match(&relative_path)
+			{
+				tracing::trace!("Skipping {relative_path:?} not matching the glob");
+				continue;
+			}
+
+			let (id, contents) = match self.id {
+				FileId::Modified => {
+					let modified = metadata
+						.modified()
+						.map_err(|e| SourceError::File(e, path.clone()))?;
+
+					let modified =
+						DateTime::<Utc>::from(modified).to_rfc3339_opts(SecondsFormat::Nanos, true);
+
+					let id = format!("{relative_path}@{modified}");
+
+					seen.insert(id.clone());
+					if self.seen.contains(&id) {
+						tracing::trace!("Skipping {relative_path:?} that hasn't been modified");
+						continue;
+					}
+
+					let Some(contents) = read_file(&path).await? else {
+						continue;
+					};
+
+					(id, String::from_utf8_lossy(&contents).into_owned())
+				}
+				FileId::ContentHash => {
+					let Some(contents) = read_file(&path).await? else {
+						continue;
+					};
+
+					(
+						format!("{relative_path}#{:016x}", fnv1a(&contents)),
+						String::from_utf8_lossy(&contents).into_owned(),
+					)
+				}
+			};
+
+			tracing::trace!("Found file {relative_path:?} with ID {id:?}");
+
+			let entry = Entry::builder()
+				.id(id)
+				.raw_contents(contents.trim().to_owned())
+				.msg(Message::builder().title(relative_path))
+				.build();
+
+			entries.push(entry);
+		}
+
+		self.seen = seen;
+		Ok(entries)
+	}
+}
+
+impl Dir {
+	/// Returns paths and metadata of all files in the directory (and its subdirectories if recursive), sorted by path
+	async fn list_files(&self) -> Result<Vec<(PathBuf, Metadata)>, SourceError> {
+		let mut files = Vec::new();
+		let mut dirs = vec![self.path.clone()];
+
+		// canonical paths of all directories listed so far to avoid following symlink loops
+		let mut listed = HashSet::from([fs::canonicalize(&self.path)
+			.await
+			.map_err(|e| SourceError::File(e, self.path.clone()))?]);
+
+		while let Some(dir) = dirs.pop() {
+			let Some(mut read_dir) = skip_not_found(fs::read_dir(&dir).await, &dir)
+				.map_err(|e| SourceError::File(e, dir.clone()))?
+			else {
+				continue;
+			};
+
+			while let Some(dir_entry) = read_dir
+				.next_entry()
+				.await
+				.map_err(|e| SourceError::File(e, dir.clone()))?
+			{
+				let path = dir_entry.path();
+
+				// follows symlinks, unlike DirEntry::metadata()
+				let Some(metadata) = skip_not_found(fs::metadata(&path).await, &path)
+					.map_err(|e| SourceError::File(e, path.clone()))?
+				else {
+					continue;
+				};
+
+				if metadata.is_dir() {
+					if !self.recursive {
+						continue;
+					}
+
+					let Some(canonical) = skip_not_found(fs::canonicalize(&path).await, &path)
+						.map_err(|e| SourceError::File(e, path.clone()))?
+					else {
+						continue;
+					};
+
+					if listed.insert(canonical) {
+						dirs.push(path);
+					} else {
+						tracing::debug!(
+							"Skipping directory {} that has already been listed",
+							path.display()
+						);
+					}
+				} else if metadata.is_file() {
+					files.push((path, metadata));
+				}
+			}
+		}
+
+		files.sort_by(|(a, _), (b, _)| Path::cmp(a, b));
+		Ok(files)
+	}
+}
+
+/// Reads the file at the `path`. Returns `None` if it doesn't exist anymore
+async fn read_file(path: &Path) -> Result<Option<Vec<u8>>, SourceError> {
+	skip_not_found(fs::read(path).await, path).map_err(|e| SourceError::File(e, path.to_owned()))
+}
+
+/// Turns a [`NotFound`](`io::ErrorKind::NotFound`) error into `None`,
+/// e.g. for a dangling symlink or a file that has been removed while the directory was being listed
+fn skip_not_found<T>(result: io::Result<T>, path: &Path) -> io::Result<Option<T>> {
+	match result {
+		Ok(val) => Ok(Some(val)),
+		Err(e) if e.kind() == io::ErrorKind::NotFound => {
+			tracing::warn!("Skipping {} that doesn't exist anymore", path.display());
+			Ok(None)
+		}
+		Err(e) => Err(e),
+	}
+}
+
+/// 64-bit FNV-1a hash. Used instead of [`std::hash::DefaultHasher`] because the latter isn't guaranteed to be stable across Rust versions
+fn fnv1a(bytes: &[u8]) -> u64 {
+	const OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+	const PRIME: u64 = 0x0100_0000_01b3;
+
+	bytes.iter().fold(OFFSET_BASIS, |hash, &byte| {
+		(hash ^ u64::from(byte)).wrapping_mul(PRIME)
+	})
+}
+
+impl Debug for Dir {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.debug_struct("Dir")
+			.field("path", &self.path)
+			.field("glob", &self.glob)
+			.field("recursive", &self.recursive)
+			.field("id", &self.id)
+			.finish_non_exhaustive()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::time::Duration;
+
+	use super::*;
+	use crate::test_utils::TempDir;
+
+	#[test]
+	fn glob_matching() {
+		let glob = Glob::new("**/*.csv").unwrap();
+		assert!(glob.is_match("report.csv"));
+		assert!(glob.is_match("2024/01/report.csv"));
+		assert!(!glob.is_match("report.csv.bak"));
+
+		let glob = Glob::new("reports/????.txt").unwrap();
+		assert!(glob.is_match("reports/2024.txt"));
+		assert!(!glob.is_match("reports/24.txt"));
+		assert!(!glob.is_match("reports/sub/2024.txt"));
+	}
+
+	#[tokio::test]
+	async fn entry_per_matching_file() {
+		let temp_dir = TempDir::new("dir-entry_per_matching_file");
+		let path = temp_dir.path();
+		fs::create_dir(path.join("sub")).await.unwrap();
+		fs::write(path.join("a.txt"), "first\n").await.unwrap();
+		fs::write(path.join("b.log"), "skipped").await.unwrap();
+		fs::write(path.join("sub/c.txt"), "second").await.unwrap();
+
+		let mut dir = Dir::builder()
+			.path(path)
+			.glob("**/*.txt")
+			.unwrap()
+			.recursive(true)
+			.build();
+
+		let entries = dir.fetch().await.unwrap();
+		let files = entries
+			.iter()
+			.map(|entry| {
+				(
+					entry.msg.title.as_deref().unwrap(),
+					entry.raw_contents.as_deref().unwrap(),
+				)
+			})
+			.collect::<Vec<_>>();
+
+		assert_eq!(files, [("a.txt", "first"), ("sub/c.txt", "second")]);
+	}
+
+	#[cfg(unix)]
+	#[tokio::test]
+	async fn symlink_loops_and_dangling_symlinks_are_skipped() {
+		let temp_dir = TempDir::new("dir-symlink_loops_and_dangling_symlinks_are_skipped");
+		let path = temp_dir.path();
+		fs::create_dir(path.join("sub")).await.unwrap();
+		fs::write(path.join("sub/a.txt"), "a").await.unwrap();
+		fs::symlink(path, path.join("sub/loop")).await.unwrap();
+		fs::symlink(path.join("missing"), path.join("dangling"))
+			.await
+			.unwrap();
+
+		let mut dir = Dir::builder().path(path).recursive(true).build();
+
+		let entries = dir.fetch().await.unwrap();
+		let titles = entries
+			.iter()
+			.map(|entry| entry.msg.title.as_deref().unwrap())
+			.collect::<Vec<_>>();
+
+		assert_eq!(titles, ["sub/a.txt"]);
+	}
+
+	#[tokio::test]
+	async fn unmodified_files_arent_read_again() {
+		let temp_dir = TempDir::new("dir-unmodified_files_arent_read_again");
+		let path = temp_dir.path();
+		let file = path.join("report");
+		let mut dir = Dir::builder().path(path).build();
+
+		let fetch_contents =
+			async |dir: &mut Dir| dir.fetch().await.unwrap().remove(0).raw_contents.unwrap();
+
+		fs::write(&file, "v1").await.unwrap();
+		assert_eq!(fetch_contents(&mut dir).await, "v1");
+
+		// overwrite the file without changing its modification time
+		let modified = fs::metadata(&file).await.unwrap().modified().unwrap();
+		fs::write(&file, "v2").await.unwrap();
+		std::fs::File::options()
+			.write(true)
+			.open(&file)
+			.unwrap()
+			.set_modified(modified)
+			.unwrap();
+
+		assert!(dir.fetch().await.unwrap().is_empty());
+
+		fs::write(&file, "v3").await.unwrap();
+		std::fs::File::options()
+			.write(true)
+			.open(&file)
+			.unwrap()
+			.set_modified(modified + Duration::from_secs(1))
+			.unwrap();
+
+		assert_eq!(fetch_contents(&mut dir).await, "v3");
+	}
+
+	#[tokio::test]
+	async fn content_hash_id_changes_with_contents() {
+		let temp_dir = TempDir::new("dir-content_hash_id_changes_with_contents");
+		let path = temp_dir.path();
+		let mut dir = Dir::builder().path(path).id(FileId::ContentHash).build();
+
+		let fetch_id = async |dir: &mut Dir| dir.fetch().await.unwrap().remove(0).id.unwrap();
+
+		fs::write(path.join("report"), "v1").await.unwrap();
+		let first = fetch_id(&mut dir).await;
+		assert_eq!(first, fetch_id(&mut dir).await);
+
+		fs::write(path.join("report"), "v2").await.unwrap();
+		assert_ne!(first, fetch_id(&mut dir).await);
+	}
+}
@@ -6,15 +6,38 @@
 
 //! A email source that uses IMAP to connect to an email server
 //!
-//! This module includes the [`Email`] source, the [`ViewMode`] enum, and the [`Filters`] struct
+//! This module includes the [`Email`] source, the [`ViewMode`] enum, the [`Filters`] struct, the [`Security`] enum,
+//! and the [`Idle`] trigger.
+//! With the `source-gmail` feature, it also includes the `Gmail` source that uses the Gmail REST API instead of IMAP
 
+mod attachments;
 mod auth;
+mod connection;
 mod filters;
+#[cfg(feature = "source-gmail")]
+mod gmail;
+mod html_to_text;
+mod idle;
+mod search;
+mod threading;
 mod view_mode;
 
-pub use self::{auth::Auth, filters::Filters, view_mode::ViewMode};
+#[cfg(test)]
+mod mock_server;
+
+#[cfg(feature = "source-gmail")]
+pub use self::gmail::{DEFAULT_GMAIL_API_URL, Gmail, GmailError};
+pub use self::{
+	attachments::{AttachmentFilter, AttachmentRules, DEFAULT_MAX_ATTACHMENT_SIZE},
+	auth::Auth,
+	connection::Security,
+	filters::Filters,
+	idle::{DEFAULT_RECONNECT_AFTER, DEFAULT_REIDLE_EVERY, Idle},
+	view_mode::ViewMode,
+};
+pub use tokio_rustls::rustls::pki_types::{self, CertificateDer};
 
-use self::auth::GoogleAuthExt;
+use self::{auth::GoogleAuthExt, connection::MaybeTlsStream};
 use super::{Fetch, MarkAsRead, Source};
 use crate::{
 	StaticStr,
@@ -26,39 +49,41 @@ use crate::{
 use async_imap::{Client, Session};
 use futures::{StreamExt, TryStreamExt};
 use mailparse::ParsedMail;
-use std::{
-	fmt::{Debug, Write as _},
-	io,
-	sync::{Arc, LazyLock},
-};
-use tokio::net::TcpStream;
-use tokio_rustls::{
-	TlsConnector,
-	client::TlsStream,
-	rustls::{
-		ClientConfig, RootCertStore,
-		pki_types::{InvalidDnsNameError, ServerName},
-	},
-};
-
-const IMAP_PORT: u16 = 993;
-
-static TLS_CONNECTOR: LazyLock<TlsConnector> = LazyLock::new(|| {
-	let mut root_cert_store = RootCertStore::empty();
-	root_cert_store.extend(webpki_roots::TLS_SERVER_ROOTS.iter().cloned());
+use non_non_full::NonEmptyVec;
+use std::{collections::HashMap, fmt::Debug, io};
+use tokio_rustls::rustls::pki_types::InvalidDnsNameError;
 
-	let config = ClientConfig::builder()
-		.with_root_certificates(root_cert_store)
-		.with_no_client_auth();
-
-	TlsConnector::from(Arc::new(config))
-});
+/// Mailbox that is watched if none are specified
+const DEFAULT_MAILBOX: &str = "INBOX";
 
 /// Email source. Fetches an email's subject and body fields using IMAP
+///
+/// If the email has several alternative bodies, the richest one is used.
+/// HTML bodies are converted to plain text, keeping paragraphs, lists, and links
+///
+/// # Entry IDs
+/// If only a single mailbox is watched, the ID of an entry is the UID of the email.
+/// Since UIDs are only unique within a mailbox, if several mailboxes are watched,
+/// the ID is the name of the mailbox and the UID separated by a slash, e.g. `Work/42`
+///
+/// If [`Email::threading`] is enabled, the ID is the `Message-ID` of the email instead, e.g. `<42@example.com>`,
+/// and [`Entry::reply_to`] is set to the `Message-ID` of the email it's replying to,
+/// taken from the `In-Reply-To` or the `References` header.
+/// This way a whole email conversation is sent as a chain of replies.
+/// Emails without a `Message-ID` still use their UID
 pub struct Email {
 	/// IMAP server address
 	pub imap_server: StaticStr,
 
+	/// Port of the IMAP server
+	pub port: u16,
+
+	/// Security of the connection to the IMAP server
+	pub security: Security,
+
+	/// Mailboxes to fetch emails from, e.g. `INBOX`
+	pub mailboxes: NonEmptyVec<StaticStr>,
+
 	/// Email address/IMAP login
 	pub email: StaticStr,
 
@@ -70,6 +95,15 @@ pub struct Email {
 
 	/// IMAP view mode, e.g. read only
 	pub view_mode: ViewMode,
+
+	/// Use the `Message-ID` as the entry ID and thread replies. See [`Email#entry-ids`]
+	pub threading: bool,
+
+	/// Root certificates to trust in addition to the webpki ones
+	root_certs: Vec<CertificateDer<'static>>,
+
+	/// Mailboxes and UIDs of the fetched emails by their `Message-ID`s if [`Email::threading`] is enabled
+	uids_by_message_id: HashMap<EntryId, (StaticStr, u32)>,
 }
 
 #[expect(missing_docs, reason = "error message is self-documenting")]
@@ -92,6 +126,15 @@ pub enum ImapError {
 	#[error("Failed to get the domain name of the IMAP server")]
 	InvalidImapServerAddress(#[from] InvalidDnsNameError),
 
+	#[error("Refusing to connect to {0:?} without encryption since it's not a local address")]
+	PlaintextNotLocal(String),
+
+	#[error("Entry ID {0:?} doesn't refer to an email in any of the watched mailboxes")]
+	InvalidEntryId(EntryId),
+
+	#[error("The IMAP server doesn't support IDLE")]
+	IdleUnsupported,
+
 	#[error(transparent)]
 	GoogleOAuth2(#[from] GoogleAuthError),
 
@@ -105,21 +148,61 @@ pub enum ImapError {
 #[bon::bon]
 impl Email {
 	/// Creates an [`Email`] source that uses a password to authenticate via IMAP
+	///
+	/// # Example
+	/// ```
+	/// # use fetcher::sources::email::{Email, Filters, Security, ViewMode};
+	/// let email = Email::new_generic()
+	///     .imap_server("imap.example.com")
+	///     .security(Security::StartTls)
+	///     .mailboxes(["INBOX".into(), "Reports".into()])
+	///     .email("me@example.com")
+	///     .password("hunter2")
+	///     .filters(Filters::builder().build())
+	///     .view_mode(ViewMode::ReadOnly)
+	///     .call();
+	/// ```
 	#[builder]
 	#[must_use]
 	pub fn new_generic(
 		#[builder(into)] imap_server: StaticStr,
+
+		/// Port of the IMAP server. Defaults to the [usual port](`Security::default_port`) for the `security`
+		port: Option<u16>,
+
+		/// Security of the connection. Defaults to [`Security::Tls`]
+		#[builder(default)]
+		security: Security,
+
+		/// Root certificates to trust in addition to the webpki ones, e.g. of an internal CA
+		#[builder(default)]
+		root_certs: Vec<CertificateDer<'static>>,
+
+		/// Mailboxes to fetch emails from. Defaults to `INBOX`
+		#[builder(default, with = FromIterator::from_iter)]
+		mailboxes: Vec<StaticStr>,
+
 		#[builder(into)] email: StaticStr,
 		#[builder(into)] password: StaticStr,
 		filters: Filters,
 		view_mode: ViewMode,
+
+		/// Use the `Message-ID` as the entry ID and thread replies. See [`Email#entry-ids`]
+		#[builder(default)]
+		threading: bool,
 	) -> Self {
 		Self {
 			imap_server,
+			port: port.unwrap_or_else(|| security.default_port()),
+			security,
+			mailboxes: mailboxes_or_default(mailboxes),
 			email,
 			auth: Auth::Password(password),
 			filters,
 			view_mode,
+			threading,
+			root_certs,
+			uids_by_message_id: HashMap::new(),
 		}
 	}
 
@@ -127,105 +210,102 @@ impl Email {
 	#[builder]
 	#[must_use]
 	pub fn new_gmail(
+		/// Mailboxes (labels) to fetch emails from. Defaults to `INBOX`
+		#[builder(default, with = FromIterator::from_iter)]
+		mailboxes: Vec<StaticStr>,
+
 		#[builder(into)] email: StaticStr,
 		auth: GoogleAuth,
 		filters: Filters,
 		view_mode: ViewMode,
+
+		/// Use the `Message-ID` as the entry ID and thread replies. See [`Email#entry-ids`]
+		#[builder(default)]
+		threading: bool,
 	) -> Self {
 		Self {
 			imap_server: "imap.gmail.com".into(),
+			port: Security::Tls.default_port(),
+			security: Security::Tls,
+			mailboxes: mailboxes_or_default(mailboxes),
 			email,
 			auth: Auth::GmailOAuth2(auth),
 			filters,
 			view_mode,
+			threading,
+			root_certs: Vec::new(),
+			uids_by_message_id: HashMap::new(),
 		}
 	}
 }
 
-impl Fetch for Email {
-	type Err = EmailError;
-
-	async fn fetch(&mut self) -> Result<Vec<Entry>, Self::Err> {
-		self.with_session(async |this, session| {
-			tracing::debug!("Fetching emails");
-
-			session.examine("INBOX").await.map_err(ImapError::Other)?;
-
-			let search_string = {
-				let mut tmp = "UNSEEN ".to_owned();
+/// Finds the part of the email that contains its text.
+///
+/// Picks the last alternative of a `multipart/alternative` that is text, since the alternatives are ordered from the simplest to the richest one,
+/// or the first text part of any other `multipart`, e.g. `multipart/mixed`. Skips attachments
+fn body_part<'a>(part: &'a ParsedMail<'a>) -> Option<&'a ParsedMail<'a>> {
+	if attachments::is_attachment(part) {
+		return None;
+	}
 
-				if let Some(sender) = &this.filters.sender {
-					_ = write!(tmp, r#"FROM "{sender}" "#);
-				}
+	let mimetype = part.ctype.mimetype.as_str();
+	match mimetype {
+		"multipart/alternative" => part.subparts.iter().rev().find_map(body_part),
+		_ if mimetype.starts_with("multipart/") => part.subparts.iter().find_map(body_part),
+		_ if mimetype.starts_with("text/") => Some(part),
+		_ => None,
+	}
+}
 
-				if let Some(subjects) = &this.filters.subjects {
-					for s in subjects {
-						_ = write!(tmp, r#"SUBJECT "{s}" "#);
-					}
-				}
+/// Searches all `mailboxes` for the email with the `message_id` and returns the mailbox and the UID of the first one found
+async fn find_by_message_id(
+	session: &mut Session<MaybeTlsStream>,
+	mailboxes: &NonEmptyVec<StaticStr>,
+	message_id: &EntryId,
+) -> Result<Option<(String, String)>, ImapError> {
+	tracing::debug!("Searching for the email with Message-ID {message_id:?}");
 
-				if let Some(ex_subjects) = &this.filters.exclude_subjects {
-					for exs in ex_subjects {
-						_ = write!(tmp, r#"NOT SUBJECT "{exs}" "#);
-					}
-				}
+	for mailbox in mailboxes {
+		session.examine(&**mailbox).await?;
 
-				tmp.trim_end().to_owned()
-			};
+		let uids = session
+			.uid_search(format!("HEADER Message-ID {}", quote(message_id.as_str())))
+			.await?;
 
-			tracing::debug!(
-				"Fetching all emails that match the search string: {:?}",
-				search_string,
-			);
-			let mail_ids = session
-				.uid_search(&search_string)
-				.await
-				.map_err(ImapError::Other)?;
-
-			let unread_num = mail_ids.len();
-			if unread_num > 0 {
-				tracing::info!("Got {unread_num} unread filtered mails");
-			} else {
-				tracing::debug!(
-					"All email for the search query have already been read, none remaining to send"
-				);
-				return Ok(Vec::new());
-			}
+		if let Some(uid) = uids.into_iter().min() {
+			return Ok(Some((mailbox.to_string(), uid.to_string())));
+		}
+	}
 
-			let mail_id_search_str = mail_ids
-				.iter()
-				.map(ToString::to_string)
-				.collect::<Vec<_>>()
-				.join(",");
+	Ok(None)
+}
 
-			tracing::trace!("Fetching all email bodies via the UIDs returned from the search");
-			let mails = session
-				.uid_fetch(&mail_id_search_str, "BODY[]")
-				.await
-				.map_err(ImapError::Other)?;
+/// Quotes the string for use in an IMAP command, escaping quotes and backslashes.
+/// Line breaks can't be quoted at all, so they are replaced with spaces
+fn quote(s: &str) -> String {
+	let s = s
+		.replace('\\', "\\\\")
+		.replace('"', "\\\"")
+		.replace(['\r', '\n'], " ");
 
-			let entries = mails
-				.map(|mail| {
-					let mail = mail.map_err(ImapError::Other)?;
+	format!("\"{s}\"")
+}
 
-					let body = mail
-						.body()
-						.expect("Body should always be present because we explicitly requested it");
+fn mailboxes_or_default(mailboxes: Vec<StaticStr>) -> NonEmptyVec<StaticStr> {
+	NonEmptyVec::new(mailboxes)
+		.unwrap_or_else(|| NonEmptyVec::with_first(StaticStr::from(DEFAULT_MAILBOX)))
+}
 
-					let uid = mail
-						.uid
-						.expect(
-							"UIDs should always be present because we used uid_fetch().\
-						The server probably doesn't support them which isn't something ~we~ support for now",
-						)
-						.into();
+impl Fetch for Email {
+	type Err = EmailError;
 
-					parse(&mailparse::parse_mail(body)?, uid)
-				})
-				.try_collect::<Vec<Entry>>()
-				.await?;
+	async fn fetch(&mut self) -> Result<Vec<Entry>, Self::Err> {
+		self.with_session(async |this, session| {
+			let mut entries = Vec::new();
 
-			assert_eq!(mail_ids.len(), entries.len(), "The number of email IDs and the number of fetched email bodies should be the same unless aborted by an error");
+			for mailbox in this.mailboxes.clone() {
+				entries.extend(this.fetch_mailbox(session, &mailbox).await?);
+			}
 
 			Ok(entries)
 		})
@@ -241,11 +321,30 @@ impl MarkAsRead for Email {
 			return Ok(());
 		}
 
+		let location = if threading::is_message_id(id) {
+			self.uids_by_message_id
+				.get(id)
+				.map(|(mailbox, uid)| (mailbox.to_string(), uid.to_string()))
+		} else {
+			let (mailbox, uid) = self.parse_entry_id(id)?;
+			Some((mailbox.to_owned(), uid.to_owned()))
+		};
+
 		self.with_session(async |this, session| {
-			session.select("INBOX").await?;
+			let location = match location {
+				Some(location) => Some(location),
+				None => find_by_message_id(session, &this.mailboxes, id).await?,
+			};
 
-			let id = id.as_str();
-			match this.view_mode {
+			let Some((mailbox, uid)) = location else {
+				tracing::warn!("Email with Message-ID {id:?} not found in any of the mailboxes, it might have already been moved or deleted");
+				return Ok(());
+			};
+
+			session.select(&mailbox).await?;
+
+			let id = uid.as_str();
+			match &this.view_mode {
 				ViewMode::MarkAsRead => {
 					session
 						.uid_store(id, "+FLAGS.SILENT (\\Seen)")
@@ -253,7 +352,7 @@ impl MarkAsRead for Email {
 						.try_collect::<Vec<_>>()
 						.await?;
 
-					tracing::debug!("Marked email uid {id} as read");
+					tracing::debug!("Marked email uid {id} in {mailbox} as read");
 				}
 				ViewMode::Delete => {
 					session
@@ -267,14 +366,55 @@ impl MarkAsRead for Email {
 						.await?
 						.try_collect::<Vec<_>>()
 						.await?;
-					tracing::debug!("Deleted email uid {id}");
+					tracing::debug!("Deleted email uid {id} in {mailbox}");
+				}
+				ViewMode::MoveTo(target) => {
+					if session.capabilities().await?.has_str("MOVE") {
+						session.uid_mv(id, target.as_str()).await?;
+					} else {
+						tracing::trace!(
+							"The server doesn't support MOVE, copying and deleting instead"
+						);
+
+						session.uid_copy(id, quote(target)).await?;
+						session
+							.uid_store(id, "+FLAGS.SILENT (\\Deleted)")
+							.await?
+							.try_collect::<Vec<_>>()
+							.await?;
+						session
+							.uid_expunge(id)
+							.await?
+							.try_collect::<Vec<_>>()
+							.await?;
+					}
+
+					tracing::debug!("Moved email uid {id} from {mailbox} to {target}");
+				}
+				ViewMode::Label(label) => {
+					session
+						.uid_store(id, format!("+X-GM-LABELS.SILENT ({})", quote(label)))
+						.await?
+						.try_collect::<Vec<_>>()
+						.await?;
+
+					session
+						.uid_store(id, "+FLAGS.SILENT (\\Seen)")
+						.await?
+						.try_collect::<Vec<_>>()
+						.await?;
+
+					tracing::debug!("Labeled email uid {id} in {mailbox} as {label}");
 				}
 				ViewMode::ReadOnly => unreachable!(),
 			}
 
-			Ok(())
+			Ok::<_, ImapError>(())
 		})
-		.await
+		.await?;
+
+		self.uids_by_message_id.remove(id);
+		Ok(())
 	}
 
 	async fn set_read_only(&mut self) {
@@ -285,22 +425,134 @@ impl MarkAsRead for Email {
 impl Source for Email {}
 
 impl Email {
-	async fn client(&self) -> Result<Client<TlsStream<TcpStream>>, ImapError> {
-		tracing::trace!("Connecting to the IMAP server");
-
-		let tcp_stream = TcpStream::connect((self.imap_server.as_str(), IMAP_PORT))
+	/// Fetches all unread emails matching the filters from the `mailbox`
+	async fn fetch_mailbox(
+		&mut self,
+		session: &mut Session<MaybeTlsStream>,
+		mailbox: &str,
+	) -> Result<Vec<Entry>, EmailError> {
+		tracing::debug!("Fetching emails from {mailbox}");
+
+		session.examine(mailbox).await.map_err(ImapError::Other)?;
+
+		let search_string = self.filters.imap_search().to_string();
+
+		tracing::debug!(
+			"Fetching all emails that match the search string: {:?}",
+			search_string,
+		);
+		let mail_ids = search::uid_search(session, &search_string)
 			.await
-			.map_err(ImapError::ConnectionFailed)?;
+			.map_err(ImapError::Other)?;
+
+		let unread_num = mail_ids.len();
+		if unread_num > 0 {
+			tracing::info!("Got {unread_num} unread filtered mails in {mailbox}");
+		} else {
+			tracing::debug!(
+				"All email for the search query have already been read, none remaining to send"
+			);
+			return Ok(Vec::new());
+		}
 
-		let domain = ServerName::try_from(String::from(&self.imap_server))?;
+		let mail_id_search_str = mail_ids
+			.iter()
+			.map(ToString::to_string)
+			.collect::<Vec<_>>()
+			.join(",");
 
-		tracing::trace!("Establishing a TLS connection");
-		let tls_stream = TLS_CONNECTOR
-			.connect(domain, tcp_stream)
+		tracing::trace!("Fetching all email bodies via the UIDs returned from the search");
+		let mails = session
+			.uid_fetch(&mail_id_search_str, "BODY[]")
 			.await
-			.map_err(ImapError::ConnectionFailed)?;
+			.map_err(ImapError::Other)?;
+
+		let entries = mails
+			.map(|mail| {
+				let mail = mail.map_err(ImapError::Other)?;
+
+				let body = mail
+					.body()
+					.expect("Body should always be present because we explicitly requested it");
+
+				let uid = mail.uid.expect(
+					"UIDs should always be present because we used uid_fetch().\
+					The server probably doesn't support them which isn't something ~we~ support for now",
+				);
+
+				let mail = mailparse::parse_mail(body)?;
+				let mut entry = parse(
+					&mail,
+					self.entry_id(mailbox, uid),
+					&self.filters.attachments,
+				)?;
+
+				let mut message_id = None;
+				if self.threading {
+					message_id = threading::message_id(&mail);
+					entry.reply_to = threading::parent_id(&mail);
+
+					if let Some(message_id) = &message_id {
+						entry.id = Some(message_id.clone());
+					}
+				}
+
+				Ok::<_, EmailError>((entry, message_id, uid))
+			})
+			.try_collect::<Vec<_>>()
+			.await?;
+
+		let entries = entries
+			.into_iter()
+			.map(|(entry, message_id, uid)| {
+				if let Some(message_id) = message_id {
+					self.uids_by_message_id
+						.insert(message_id, (StaticStr::from(mailbox.to_owned()), uid));
+				}
+
+				entry
+			})
+			.collect::<Vec<_>>();
+
+		assert_eq!(
+			mail_ids.len(),
+			entries.len(),
+			"The number of email IDs and the number of fetched email bodies should be the same unless aborted by an error"
+		);
 
-		Ok(Client::new(tls_stream))
+		Ok(entries)
+	}
+
+	/// Creates the ID of the entry of the email with the `uid` in the `mailbox`. See [`Email#entry-ids`]
+	fn entry_id(&self, mailbox: &str, uid: u32) -> EntryId {
+		if self.mailboxes.len() == 1 {
+			uid.into()
+		} else {
+			EntryId::try_from(format!("{mailbox}/{uid}"))
+				.expect("should never be empty since it contains the UID")
+		}
+	}
+
+	/// Splits the entry ID into the mailbox and the UID of the email. See [`Email#entry-ids`]
+	fn parse_entry_id<'a>(&'a self, id: &'a EntryId) -> Result<(&'a str, &'a str), ImapError> {
+		if self.mailboxes.len() == 1 {
+			return Ok((self.mailboxes.first(), id.as_str()));
+		}
+
+		id.as_str()
+			.rsplit_once('/')
+			.filter(|(mailbox, _)| self.mailboxes.iter().any(|m| m.as_str() == *mailbox))
+			.ok_or_else(|| ImapError::InvalidEntryId(id.clone()))
+	}
+
+	async fn client(&self) -> Result<Client<MaybeTlsStream>, ImapError> {
+		connection::connect(
+			&self.imap_server,
+			self.port,
+			self.security,
+			&self.root_certs,
+		)
+		.await
 	}
 
 	/// Creates an authenticated session with the IMAP server, passes it to the closure, and automatically logs out when the closure returns.
@@ -309,20 +561,11 @@ impl Email {
 	/// Don't call [`Session::logout()`] manually in the closure, this function will call it automatically at the end.
 	async fn with_session<F, T, E>(&mut self, f: F) -> Result<T, E>
 	where
-		F: AsyncFnOnce(&mut Email, &mut Session<TlsStream<TcpStream>>) -> Result<T, E>,
+		F: AsyncFnOnce(&mut Email, &mut Session<MaybeTlsStream>) -> Result<T, E>,
 		E: From<ImapError>,
 	{
 		let client = self.client().await?;
-
-		// authenticate and create a session
-		let mut session = match &mut self.auth {
-			Auth::GmailOAuth2(auth) => {
-				authenticate_google_oauth2(client, auth, &self.email).await?
-			}
-			Auth::Password(password) => {
-				authenticate_password(client, &self.email, password).await?
-			}
-		};
+		let mut session = login(client, &mut self.auth, &self.email).await?;
 
 		match f(self, &mut session).await {
 			Ok(t) => {
@@ -338,8 +581,12 @@ impl Email {
 	}
 }
 
-fn parse(mail: &ParsedMail<'_>, id: EntryId) -> Result<Entry, EmailError> {
-	tracing::trace!("Parsing the contents of an email with UID {id:?}");
+fn parse(
+	mail: &ParsedMail<'_>,
+	id: EntryId,
+	attachment_filter: &AttachmentFilter,
+) -> Result<Entry, mailparse::MailParseError> {
+	tracing::trace!("Parsing the contents of an email with ID {id:?}");
 	let subject = mail.headers.iter().find_map(|x| {
 		if x.get_key_ref() == "Subject" {
 			Some(x.get_value())
@@ -348,33 +595,47 @@ fn parse(mail: &ParsedMail<'_>, id: EntryId) -> Result<Entry, EmailError> {
 		}
 	});
 
-	let body = {
-		if mail.subparts.is_empty() {
-			mail
-		} else {
-			mail.subparts
-				.iter()
-				.find(|x| x.ctype.mimetype == "text/plain")
-				.unwrap_or(&mail.subparts[0])
+	let body = match body_part(mail) {
+		Some(part) if part.ctype.mimetype == "text/html" => {
+			Some(html_to_text::html_to_text(&part.get_body()?))
 		}
-		.get_body()?
+		Some(part) => Some(part.get_body()?),
+		None => None,
 	};
 
+	let media = attachments::extract(mail, attachment_filter)?;
+
 	let entry = Entry::builder()
 		.id_raw(id)
-		.msg(Message::builder().maybe_title(subject).body(body))
+		.msg(
+			Message::builder()
+				.maybe_title(subject)
+				.maybe_body(body)
+				.maybe_media(NonEmptyVec::new(media)),
+		)
 		.build();
 
 	Ok(entry)
 }
 
+/// Authenticates with the IMAP server and creates a session
+async fn login(
+	client: Client<MaybeTlsStream>,
+	auth: &mut Auth,
+	email: &str,
+) -> Result<Session<MaybeTlsStream>, ImapError> {
+	match auth {
+		Auth::GmailOAuth2(auth) => authenticate_google_oauth2(client, auth, email).await,
+		Auth::Password(password) => authenticate_password(client, email, password).await,
+	}
+}
+
 async fn authenticate_google_oauth2(
-	mut client: Client<TlsStream<TcpStream>>,
+	client: Client<MaybeTlsStream>,
 	google_auth: &mut crate::auth::Google,
 	email: &str,
-) -> Result<Session<TlsStream<TcpStream>>, ImapError> {
+) -> Result<Session<MaybeTlsStream>, ImapError> {
 	tracing::trace!("Logging into IMAP with Google OAuth2");
-	let _greeting = client.read_response().await;
 	let session = client
 		.authenticate(
 			"XOAUTH2",
@@ -416,10 +677,10 @@ async fn authenticate_google_oauth2(
 }
 
 async fn authenticate_password(
-	client: Client<TlsStream<TcpStream>>,
+	client: Client<MaybeTlsStream>,
 	email: &str,
 	password: &str,
-) -> Result<Session<TlsStream<TcpStream>>, ImapError> {
+) -> Result<Session<MaybeTlsStream>, ImapError> {
 	tracing::warn!("Logging in to IMAP with a password, this is insecure");
 
 	client
@@ -432,6 +693,9 @@ impl Debug for Email {
 	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
 		f.debug_struct("Email")
 			.field("imap_server", &self.imap_server)
+			.field("port", &self.port)
+			.field("security", &self.security)
+			.field("mailboxes", &self.mailboxes)
 			.field(
 				"auth_type",
 				match self.auth {
@@ -442,6 +706,272 @@ impl Debug for Email {
 			.field("email", &self.email)
 			.field("filters", &self.filters)
 			.field("view_mode", &self.view_mode)
-			.finish()
+			.field("threading", &self.threading)
+			.finish_non_exhaustive()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use assert_matches::assert_matches;
+
+	use super::{mock_server::MockImap, *};
+
+	const MAIL: &str = "Subject: Hello\r\nContent-Type: text/plain\r\n\r\nWorld\r\n";
+
+	fn email(server: &MockImap, mailboxes: &[&'static str], view_mode: ViewMode) -> Email {
+		Email::new_generic()
+			.imap_server("127.0.0.1")
+			.port(server.port)
+			.security(Security::Plaintext)
+			.mailboxes(mailboxes.iter().map(|&m| StaticStr::from(m)))
+			.email("user")
+			.password("password")
+			.filters(Filters::builder().build())
+			.view_mode(view_mode)
+			.call()
+	}
+
+	#[tokio::test]
+	async fn fetches_from_all_mailboxes() {
+		let server = mock_server::start(vec![
+			("INBOX", vec![(1, MAIL.to_owned())]),
+			("Work", vec![(5, MAIL.to_owned())]),
+		])
+		.await;
+
+		let mut email = email(&server, &["INBOX", "Work"], ViewMode::MarkAsRead);
+
+		let entries = email.fetch().await.unwrap();
+		let ids = entries
+			.iter()
+			.map(|entry| entry.id.as_ref().unwrap().as_str())
+			.collect::<Vec<_>>();
+
+		assert_eq!(ids, ["INBOX/1", "Work/5"]);
+		assert_eq!(entries[0].msg.title.as_deref(), Some("Hello"));
+		assert_eq!(entries[0].msg.body.as_deref().map(str::trim), Some("World"));
+
+		email
+			.mark_as_read(&EntryId::try_from("Work/5").unwrap())
+			.await
+			.unwrap();
+
+		assert_eq!(
+			server.commands_starting_with("SELECT"),
+			[r#"SELECT "Work""#]
+		);
+		assert_eq!(
+			server.commands_starting_with("UID STORE"),
+			[r"UID STORE 5 +FLAGS.SILENT (\Seen)"]
+		);
+	}
+
+	#[tokio::test]
+	async fn single_mailbox_uses_plain_uids() {
+		let server = mock_server::start(vec![("INBOX", vec![(3, MAIL.to_owned())])]).await;
+		let mut email = email(&server, &[], ViewMode::ReadOnly);
+
+		let entries = email.fetch().await.unwrap();
+		assert_eq!(entries[0].id.as_ref().map(EntryId::as_str), Some("3"));
+	}
+
+	#[tokio::test]
+	async fn sends_non_ascii_search_values_as_literals() {
+		let server = mock_server::start(vec![("INBOX", vec![(1, MAIL.to_owned())])]).await;
+		let mut email = email(&server, &[], ViewMode::ReadOnly);
+		email.filters = Filters::builder().subject("привет").build();
+
+		let entries = email.fetch().await.unwrap();
+		assert_eq!(entries.len(), 1);
+
+		assert_eq!(
+			server.commands_starting_with("UID SEARCH"),
+			["UID SEARCH CHARSET UTF-8 UNSEEN SUBJECT {12}\r\nпривет"]
+		);
+	}
+
+	#[tokio::test]
+	async fn unknown_mailbox_in_id_is_an_error() {
+		let server = mock_server::start(Vec::new()).await;
+		let mut email = email(&server, &["INBOX", "Work"], ViewMode::MarkAsRead);
+
+		assert_matches!(
+			email
+				.mark_as_read(&EntryId::try_from("Other/1").unwrap())
+				.await,
+			Err(ImapError::InvalidEntryId(_))
+		);
+	}
+
+	#[tokio::test]
+	async fn moves_using_move_if_supported_and_copy_otherwise() {
+		let server = mock_server::start(vec![("INBOX", vec![(1, MAIL.to_owned())])]).await;
+		let mut source = email(&server, &[], ViewMode::MoveTo("Processed/2024".into()));
+
+		source.mark_as_read(&EntryId::from(1)).await.unwrap();
+		assert_eq!(
+			server.commands_starting_with("UID"),
+			[r#"UID MOVE 1 "Processed/2024""#]
+		);
+
+		let server =
+			mock_server::start_with_capabilities("IMAP4rev1", vec![("INBOX", Vec::new())]).await;
+		let mut source = email(&server, &[], ViewMode::MoveTo("Processed/2024".into()));
+
+		source.mark_as_read(&EntryId::from(1)).await.unwrap();
+		assert_eq!(
+			server.commands_starting_with("UID"),
+			[
+				r#"UID COPY 1 "Processed/2024""#,
+				r"UID STORE 1 +FLAGS.SILENT (\Deleted)",
+				"UID EXPUNGE 1"
+			]
+		);
+	}
+
+	#[tokio::test]
+	async fn labels_and_marks_as_read() {
+		let server = mock_server::start(vec![("INBOX", Vec::new())]).await;
+		let mut source = email(&server, &[], ViewMode::Label(r#"Processed "ok""#.into()));
+
+		source.mark_as_read(&EntryId::from(7)).await.unwrap();
+		assert_eq!(
+			server.commands_starting_with("UID STORE"),
+			[
+				r#"UID STORE 7 +X-GM-LABELS.SILENT ("Processed \"ok\"")"#,
+				r"UID STORE 7 +FLAGS.SILENT (\Seen)"
+			]
+		);
+	}
+
+	#[tokio::test]
+	async fn threading_uses_message_ids() {
+		const REPLY: &str = concat!(
+			"Message-ID: <2@example.com>\r\n",
+			"In-Reply-To: <1@example.com>\r\n",
+			"Subject: Re: Hello\r\n",
+			"\r\n",
+			"Hi\r\n",
+		);
+
+		let server = mock_server::start(vec![(
+			"INBOX",
+			vec![(1, MAIL.to_owned()), (2, REPLY.to_owned())],
+		)])
+		.await;
+
+		let mut source = Email::new_generic()
+			.imap_server("127.0.0.1")
+			.port(server.port)
+			.security(Security::Plaintext)
+			.email("user")
+			.password("password")
+			.filters(Filters::builder().build())
+			.view_mode(ViewMode::MarkAsRead)
+			.threading(true)
+			.call();
+
+		let entries = source.fetch().await.unwrap();
+		let mut ids = entries
+			.iter()
+			.map(|entry| {
+				(
+					entry.id.as_ref().map(EntryId::as_str),
+					entry.reply_to.as_ref().map(EntryId::as_str),
+				)
+			})
+			.collect::<Vec<_>>();
+		ids.sort_unstable();
+
+		// the first mail doesn't have a Message-ID
+		assert_eq!(
+			ids,
+			[
+				(Some("1"), None),
+				(Some("<2@example.com>"), Some("<1@example.com>"))
+			]
+		);
+
+		source
+			.mark_as_read(&EntryId::try_from("<2@example.com>").unwrap())
+			.await
+			.unwrap();
+		assert_eq!(
+			server.commands_starting_with("UID STORE"),
+			[r"UID STORE 2 +FLAGS.SILENT (\Seen)"]
+		);
+
+		// not fetched during this run
+		let mut source = Email::new_generic()
+			.imap_server("127.0.0.1")
+			.port(server.port)
+			.security(Security::Plaintext)
+			.email("user")
+			.password("password")
+			.filters(Filters::builder().build())
+			.view_mode(ViewMode::MarkAsRead)
+			.threading(true)
+			.call();
+		source
+			.mark_as_read(&EntryId::try_from("<3@example.com>").unwrap())
+			.await
+			.unwrap();
+		assert_eq!(
+			server.commands_starting_with("UID SEARCH HEADER"),
+			[r#"UID SEARCH HEADER Message-ID "<3@example.com>""#]
+		);
+	}
+
+	#[test]
+	fn prefers_the_richest_alternative() {
+		const NEWSLETTER: &str = concat!(
+			"Subject: News\r\n",
+			"Content-Type: multipart/mixed; boundary=\"mixed\"\r\n",
+			"\r\n",
+			"--mixed\r\n",
+			"Content-Type: multipart/alternative; boundary=\"alt\"\r\n",
+			"\r\n",
+			"--alt\r\n",
+			"Content-Type: text/plain\r\n",
+			"\r\n",
+			"View this email in your browser\r\n",
+			"--alt\r\n",
+			"Content-Type: text/html\r\n",
+			"\r\n",
+			"<p>Read the <a href=\"https://example.com/post\">post</a></p>\r\n",
+			"--alt--\r\n",
+			"--mixed\r\n",
+			"Content-Type: text/plain\r\n",
+			"Content-Disposition: attachment; filename=\"notes.txt\"\r\n",
+			"\r\n",
+			"notes\r\n",
+			"--mixed--\r\n",
+		);
+
+		let mail = mailparse::parse_mail(NEWSLETTER.as_bytes()).unwrap();
+		let entry = parse(&mail, EntryId::from(1), &AttachmentFilter::None).unwrap();
+
+		assert_eq!(
+			entry.msg.body.as_deref(),
+			Some("Read the post (https://example.com/post)")
+		);
+	}
+
+	#[tokio::test]
+	async fn plaintext_is_refused_for_remote_servers() {
+		let mut email = Email::new_generic()
+			.imap_server("imap.example.com")
+			.security(Security::Plaintext)
+			.email("user")
+			.password("password")
+			.filters(Filters::builder().build())
+			.view_mode(ViewMode::ReadOnly)
+			.call();
+
+		assert_matches!(
+			email.fetch().await,
+			Err(EmailError::Imap(ImapError::PlaintextNotLocal(_)))
+		);
 	}
 }
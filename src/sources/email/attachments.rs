@@ -0,0 +1,228 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+
+//! This module contains the [`AttachmentFilter`] and the extraction of attachments from emails
+
+use mailparse::{DispositionType, ParsedMail};
+
+use crate::{
+	StaticStr,
+	sinks::message::{Attachment, Media},
+};
+
+/// Largest attachment that is included by default, in bytes
+pub const DEFAULT_MAX_ATTACHMENT_SIZE: usize = 20 * 1024 * 1024;
+
+/// Specifies which attachments and inline images of an email are put into [`Message::media`](`crate::sinks::message::Message::media`).
+///
+/// Doesn't include any attachments by default
+#[derive(Clone, Default, Debug)]
+pub enum AttachmentFilter {
+	/// Don't include any attachments
+	#[default]
+	None,
+
+	/// Include only attachments that match the rules
+	Matching(AttachmentRules),
+}
+
+/// Rules an attachment has to match to be included
+#[derive(bon::Builder, Clone, Debug)]
+pub struct AttachmentRules {
+	/// Include only attachments with one of these MIME types, e.g. `application/pdf`.
+	/// A type ending with `/*` matches all of its subtypes, e.g. `image/*`.
+	///
+	/// Includes attachments of all types if `None`
+	#[builder(field)]
+	pub mime_types: Option<Vec<StaticStr>>,
+
+	/// Include only attachments that are at most this large, in bytes. Defaults to [`DEFAULT_MAX_ATTACHMENT_SIZE`]
+	#[builder(default = DEFAULT_MAX_ATTACHMENT_SIZE)]
+	pub max_size: usize,
+}
+
+impl AttachmentFilter {
+	/// Checks if an attachment of the `mime_type` that is `size` bytes large should be included
+	#[must_use]
+	pub fn matches(&self, mime_type: &str, size: usize) -> bool {
+		match self {
+			Self::None => false,
+			Self::Matching(rules) => rules.matches(mime_type, size),
+		}
+	}
+}
+
+impl AttachmentRules {
+	/// Checks if an attachment of the `mime_type` that is `size` bytes large matches the rules
+	#[must_use]
+	pub fn matches(&self, mime_type: &str, size: usize) -> bool {
+		let type_matches = self.mime_types.as_ref().is_none_or(|mime_types| {
+			mime_types
+				.iter()
+				.any(|pattern| match pattern.strip_suffix("/*") {
+					Some(ty) => mime_type
+						.split_once('/')
+						.is_some_and(|(mime_ty, _)| mime_ty.eq_ignore_ascii_case(ty)),
+					None => mime_type.eq_ignore_ascii_case(pattern),
+				})
+		});
+
+		type_matches && size <= self.max_size
+	}
+}
+
+impl From<AttachmentRules> for AttachmentFilter {
+	fn from(rules: AttachmentRules) -> Self {
+		Self::Matching(rules)
+	}
+}
+
+impl<S: attachment_rules_builder::State> AttachmentRulesBuilder<S> {
+	/// Includes attachments of this MIME type, e.g. `application/pdf`, or of all of its subtypes, e.g. `image/*`.
+	/// Can be called multiple times to include several types
+	pub fn mime_type(mut self, value: impl Into<StaticStr>) -> Self {
+		self.mime_types.get_or_insert_default().push(value.into());
+		self
+	}
+}
+
+/// Checks if the part of the email is an attachment or an inline image rather than (a part of) the body
+pub(super) fn is_attachment(part: &ParsedMail<'_>) -> bool {
+	let mimetype = &part.ctype.mimetype;
+	if mimetype.starts_with("multipart/") {
+		return false;
+	}
+
+	match part.get_content_disposition().disposition {
+		DispositionType::Attachment => true,
+		_ => !mimetype.starts_with("text/"),
+	}
+}
+
+/// Extracts all attachments and inline images of the `mail` that match the `filter`
+pub(super) fn extract(
+	mail: &ParsedMail<'_>,
+	filter: &AttachmentFilter,
+) -> Result<Vec<Media>, mailparse::MailParseError> {
+	let mut media = Vec::new();
+	if matches!(filter, AttachmentFilter::None) {
+		return Ok(media);
+	}
+
+	for part in mail.parts().filter(|part| is_attachment(part)) {
+		let mime_type = part.ctype.mimetype.to_lowercase();
+		let bytes = part.get_body_raw()?;
+
+		if !filter.matches(&mime_type, bytes.len()) {
+			tracing::trace!(
+				"Skipping an attachment of type {mime_type} that is {} bytes large",
+				bytes.len()
+			);
+			continue;
+		}
+
+		let filename = part
+			.get_content_disposition()
+			.params
+			.get("filename")
+			.or_else(|| part.ctype.params.get("name"))
+			.cloned();
+
+		media.push(Media::Attachment(Attachment {
+			filename,
+			mime_type,
+			bytes,
+		}));
+	}
+
+	Ok(media)
+}
+
+#[cfg(test)]
+mod tests {
+	use assert_matches::assert_matches;
+
+	use super::*;
+
+	const MAIL: &str = concat!(
+		"Subject: Report\r\n",
+		"Content-Type: multipart/mixed; boundary=\"b\"\r\n",
+		"\r\n",
+		"--b\r\n",
+		"Content-Type: text/plain\r\n",
+		"\r\n",
+		"See attached\r\n",
+		"--b\r\n",
+		"Content-Type: application/pdf; name=\"report.pdf\"\r\n",
+		"Content-Disposition: attachment; filename=\"report.pdf\"\r\n",
+		"Content-Transfer-Encoding: base64\r\n",
+		"\r\n",
+		"JVBERi0xLjQ=\r\n",
+		"--b\r\n",
+		"Content-Type: image/png\r\n",
+		"Content-Disposition: inline\r\n",
+		"Content-ID: <logo>\r\n",
+		"\r\n",
+		"png\r\n",
+		"--b--\r\n",
+	);
+
+	#[test]
+	fn extracts_attachments_and_inline_images() {
+		let mail = mailparse::parse_mail(MAIL.as_bytes()).unwrap();
+		let all = AttachmentRules::builder().build().into();
+		let media = extract(&mail, &all).unwrap();
+
+		assert_eq!(
+			media,
+			[
+				Media::Attachment(Attachment {
+					filename: Some("report.pdf".to_owned()),
+					mime_type: "application/pdf".to_owned(),
+					bytes: b"%PDF-1.4".to_vec(),
+				}),
+				Media::Attachment(Attachment {
+					filename: None,
+					mime_type: "image/png".to_owned(),
+					bytes: b"png".to_vec(),
+				}),
+			]
+		);
+	}
+
+	#[test]
+	fn filters_by_type_and_size() {
+		let mail = mailparse::parse_mail(MAIL.as_bytes()).unwrap();
+
+		let images = AttachmentRules::builder()
+			.mime_type("image/*")
+			.build()
+			.into();
+		let media = extract(&mail, &images).unwrap();
+		assert_matches!(&media[..], [Media::Attachment(a)] if a.is_image());
+
+		let small = AttachmentRules::builder().max_size(4).build().into();
+		let media = extract(&mail, &small).unwrap();
+		assert_matches!(&media[..], [Media::Attachment(a)] if a.bytes == b"png");
+
+		let large = AttachmentRules::builder()
+			.mime_type("application/pdf")
+			.max_size(4)
+			.build()
+			.into();
+		assert!(extract(&mail, &large).unwrap().is_empty());
+	}
+
+	#[test]
+	fn includes_no_attachments_by_default() {
+		let mail = mailparse::parse_mail(MAIL.as_bytes()).unwrap();
+		assert!(
+			extract(&mail, &AttachmentFilter::default())
+				.unwrap()
+				.is_empty()
+		);
+	}
+}
@@ -10,6 +10,7 @@ use crate::{
 };
 
 /// Authentication type for IMAP
+#[derive(Clone)]
 pub enum Auth {
 	#[expect(clippy::doc_markdown, reason = "false positive")]
 	/// Google OAuth2 with full access to Gmail
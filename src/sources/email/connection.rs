@@ -0,0 +1,226 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+
+//! This module contains the [`Security`] of the IMAP connection and the logic to establish it
+
+use async_imap::Client;
+use std::{
+	io,
+	net::IpAddr,
+	pin::Pin,
+	sync::{Arc, LazyLock},
+	task::{Context, Poll},
+};
+use tokio::{
+	io::{AsyncRead, AsyncWrite, ReadBuf},
+	net::TcpStream,
+};
+use tokio_rustls::{
+	TlsConnector,
+	client::TlsStream,
+	rustls::{ClientConfig, RootCertStore, pki_types::CertificateDer, pki_types::ServerName},
+};
+
+use super::ImapError;
+
+static DEFAULT_TLS_CONNECTOR: LazyLock<TlsConnector> = LazyLock::new(|| new_tls_connector(&[]));
+
+/// Security of the connection to the IMAP server
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug)]
+pub enum Security {
+	/// Connect using TLS right away, usually on port 993
+	#[default]
+	Tls,
+
+	/// Connect unencrypted and upgrade the connection to TLS with the `STARTTLS` command, usually on port 143
+	StartTls,
+
+	/// Don't encrypt the connection at all, usually on port 143.
+	///
+	/// Only allowed when connecting to `localhost` or a loopback address, e.g. to a local IMAP proxy or bridge
+	Plaintext,
+}
+
+/// A connection to the IMAP server that may or may not be encrypted
+#[derive(Debug)]
+pub(super) enum MaybeTlsStream {
+	Tls(Box<TlsStream<TcpStream>>),
+	Plaintext(TcpStream),
+}
+
+impl Security {
+	/// The port IMAP servers usually listen on for this kind of connections
+	#[must_use]
+	pub const fn default_port(self) -> u16 {
+		match self {
+			Self::Tls => 993,
+			Self::StartTls | Self::Plaintext => 143,
+		}
+	}
+}
+
+/// Returns a TLS connector that trusts the webpki roots and the `extra_root_certs`.
+///
+/// The one without any extra root certificates is only created once and then reused
+fn tls_connector(extra_root_certs: &[CertificateDer<'static>]) -> TlsConnector {
+	if extra_root_certs.is_empty() {
+		return DEFAULT_TLS_CONNECTOR.clone();
+	}
+
+	new_tls_connector(extra_root_certs)
+}
+
+/// Creates a TLS connector that trusts the webpki roots and the `extra_root_certs`
+fn new_tls_connector(extra_root_certs: &[CertificateDer<'static>]) -> TlsConnector {
+	let mut root_cert_store = RootCertStore::empty();
+	root_cert_store.extend(webpki_roots::TLS_SERVER_ROOTS.iter().cloned());
+
+	let (_, ignored) = root_cert_store.add_parsable_certificates(extra_root_certs.iter().cloned());
+	if ignored > 0 {
+		tracing::warn!("Ignored {ignored} invalid root certificates");
+	}
+
+	let config = ClientConfig::builder()
+		.with_root_certificates(root_cert_store)
+		.with_no_client_auth();
+
+	TlsConnector::from(Arc::new(config))
+}
+
+/// Connects to the IMAP server and reads its greeting.
+///
+/// The TLS connector that trusts the webpki roots and the `extra_root_certs` is only created if the connection is encrypted
+pub(super) async fn connect(
+	server: &str,
+	port: u16,
+	security: Security,
+	extra_root_certs: &[CertificateDer<'static>],
+) -> Result<Client<MaybeTlsStream>, ImapError> {
+	if security == Security::Plaintext && !is_loopback(server) {
+		return Err(ImapError::PlaintextNotLocal(server.to_owned()));
+	}
+
+	tracing::trace!("Connecting to the IMAP server at {server}:{port} using {security:?}");
+	let tcp_stream = TcpStream::connect((server, port))
+		.await
+		.map_err(ImapError::ConnectionFailed)?;
+
+	let stream = match security {
+		Security::Tls => upgrade_to_tls(tcp_stream, server, extra_root_certs).await?,
+		Security::StartTls => {
+			let mut client = Client::new(tcp_stream);
+			read_greeting(&mut client).await?;
+
+			tracing::trace!("Upgrading the connection with STARTTLS");
+			client
+				.run_command_and_check_ok("STARTTLS", None)
+				.await
+				.map_err(ImapError::Other)?;
+
+			// the server doesn't send another greeting after STARTTLS
+			let tls_stream = upgrade_to_tls(client.into_inner(), server, extra_root_certs).await?;
+			return Ok(Client::new(tls_stream));
+		}
+		Security::Plaintext => MaybeTlsStream::Plaintext(tcp_stream),
+	};
+
+	let mut client = Client::new(stream);
+	read_greeting(&mut client).await?;
+
+	Ok(client)
+}
+
+async fn upgrade_to_tls(
+	tcp_stream: TcpStream,
+	server: &str,
+	extra_root_certs: &[CertificateDer<'static>],
+) -> Result<MaybeTlsStream, ImapError> {
+	let domain = ServerName::try_from(server.to_owned())?;
+
+	tracing::trace!("Establishing a TLS connection");
+	let tls_stream = tls_connector(extra_root_certs)
+		.connect(domain, tcp_stream)
+		.await
+		.map_err(ImapError::ConnectionFailed)?;
+
+	Ok(MaybeTlsStream::Tls(Box::new(tls_stream)))
+}
+
+async fn read_greeting<T>(client: &mut Client<T>) -> Result<(), ImapError>
+where
+	T: tokio::io::AsyncRead + tokio::io::AsyncWrite + Unpin + std::fmt::Debug + Send,
+{
+	match client.read_response().await {
+		Some(Ok(_greeting)) => Ok(()),
+		Some(Err(e)) => Err(ImapError::ConnectionFailed(e)),
+		None => Err(ImapError::ConnectionFailed(
+			io::ErrorKind::UnexpectedEof.into(),
+		)),
+	}
+}
+
+fn is_loopback(server: &str) -> bool {
+	server.eq_ignore_ascii_case("localhost")
+		|| server
+			.trim_start_matches('[')
+			.trim_end_matches(']')
+			.parse::<IpAddr>()
+			.is_ok_and(|ip| ip.is_loopback())
+}
+
+impl AsyncRead for MaybeTlsStream {
+	fn poll_read(
+		self: Pin<&mut Self>,
+		cx: &mut Context<'_>,
+		buf: &mut ReadBuf<'_>,
+	) -> Poll<io::Result<()>> {
+		match self.get_mut() {
+			Self::Tls(stream) => Pin::new(stream).poll_read(cx, buf),
+			Self::Plaintext(stream) => Pin::new(stream).poll_read(cx, buf),
+		}
+	}
+}
+
+impl AsyncWrite for MaybeTlsStream {
+	fn poll_write(
+		self: Pin<&mut Self>,
+		cx: &mut Context<'_>,
+		buf: &[u8],
+	) -> Poll<io::Result<usize>> {
+		match self.get_mut() {
+			Self::Tls(stream) => Pin::new(stream).poll_write(cx, buf),
+			Self::Plaintext(stream) => Pin::new(stream).poll_write(cx, buf),
+		}
+	}
+
+	fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+		match self.get_mut() {
+			Self::Tls(stream) => Pin::new(stream).poll_flush(cx),
+			Self::Plaintext(stream) => Pin::new(stream).poll_flush(cx),
+		}
+	}
+
+	fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+		match self.get_mut() {
+			Self::Tls(stream) => Pin::new(stream).poll_shutdown(cx),
+			Self::Plaintext(stream) => Pin::new(stream).poll_shutdown(cx),
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn plaintext_only_to_loopback() {
+		assert!(is_loopback("localhost"));
+		assert!(is_loopback("127.0.0.1"));
+		assert!(is_loopback("[::1]"));
+		assert!(!is_loopback("imap.example.com"));
+		assert!(!is_loopback("192.168.1.1"));
+	}
+}
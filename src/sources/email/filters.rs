@@ -4,11 +4,33 @@
  * file, You can obtain one at https://mozilla.org/MPL/2.0/.
  */
 
+use chrono::NaiveDate;
+#[cfg(feature = "source-gmail")]
+use mailparse::{MailHeaderMap, ParsedMail};
+
+use super::{
+	AttachmentFilter,
+	search::{SearchKey, SearchQuery},
+};
 use crate::StaticStr;
 
-/// A list of filters passed to the IMAP server
+/// A list of filters passed to the IMAP server.
+///
+/// An email has to match all of them, except for the senders where matching any one is enough
 #[derive(bon::Builder, Debug)]
 pub struct Filters {
+	/// Get emails only from any of these senders
+	#[builder(field)]
+	pub senders: Option<Vec<StaticStr>>,
+
+	/// Get emails only sent to these addresses
+	#[builder(field)]
+	pub to: Option<Vec<StaticStr>>,
+
+	/// Get emails only with these addresses in CC
+	#[builder(field)]
+	pub cc: Option<Vec<StaticStr>>,
+
 	/// Get emails only containing these strings in the subject
 	#[builder(field)]
 	pub subjects: Option<Vec<StaticStr>>,
@@ -17,12 +39,46 @@ pub struct Filters {
 	#[builder(field)]
 	pub exclude_subjects: Option<Vec<StaticStr>>,
 
-	/// Get emails only from this sender
-	#[builder(into)]
-	pub sender: Option<StaticStr>,
+	/// Get emails only containing these strings in the body
+	#[builder(field)]
+	pub bodies: Option<Vec<StaticStr>>,
+
+	/// Get emails only with these headers (name, value) containing the value
+	#[builder(field)]
+	pub headers: Option<Vec<(StaticStr, StaticStr)>>,
+
+	/// Get emails only sent on this date or later
+	pub since: Option<NaiveDate>,
+
+	/// Get emails only sent before this date
+	pub before: Option<NaiveDate>,
+
+	/// Get emails that have already been seen, too, not only the unseen ones
+	#[builder(default)]
+	pub include_seen: bool,
+
+	/// Which attachments to include in the message. None by default.
+	/// Unlike the other filters, applied locally after the emails have been fetched
+	#[builder(default, into)]
+	pub attachments: AttachmentFilter,
 }
 
 impl<S: filters_builder::State> FiltersBuilder<S> {
+	pub fn sender(mut self, value: impl Into<StaticStr>) -> Self {
+		self.senders.get_or_insert_default().push(value.into());
+		self
+	}
+
+	pub fn to(mut self, value: impl Into<StaticStr>) -> Self {
+		self.to.get_or_insert_default().push(value.into());
+		self
+	}
+
+	pub fn cc(mut self, value: impl Into<StaticStr>) -> Self {
+		self.cc.get_or_insert_default().push(value.into());
+		self
+	}
+
 	pub fn subject(mut self, value: impl Into<StaticStr>) -> Self {
 		self.subjects.get_or_insert_default().push(value.into());
 		self
@@ -34,4 +90,122 @@ impl<S: filters_builder::State> FiltersBuilder<S> {
 			.push(value.into());
 		self
 	}
+
+	pub fn body(mut self, value: impl Into<StaticStr>) -> Self {
+		self.bodies.get_or_insert_default().push(value.into());
+		self
+	}
+
+	pub fn header(mut self, name: impl Into<StaticStr>, value: impl Into<StaticStr>) -> Self {
+		self.headers
+			.get_or_insert_default()
+			.push((name.into(), value.into()));
+		self
+	}
+}
+
+impl Filters {
+	/// Creates the IMAP search query matching these filters
+	pub(super) fn imap_search(&self) -> SearchQuery {
+		let mut query = SearchQuery::default();
+
+		if !self.include_seen {
+			query.and(SearchKey::Unseen);
+		}
+
+		if let Some(senders) = SearchKey::any(
+			self.senders
+				.iter()
+				.flatten()
+				.map(|sender| SearchKey::From(sender.clone())),
+		) {
+			query.and(senders);
+		}
+
+		let keys = [
+			(&self.to, SearchKey::To as fn(StaticStr) -> SearchKey),
+			(&self.cc, SearchKey::Cc),
+			(&self.subjects, SearchKey::Subject),
+			(&self.exclude_subjects, |s| {
+				SearchKey::Not(Box::new(SearchKey::Subject(s)))
+			}),
+			(&self.bodies, SearchKey::Body),
+		];
+
+		for (values, key) in keys {
+			for value in values.iter().flatten() {
+				query.and(key(value.clone()));
+			}
+		}
+
+		for (name, value) in self.headers.iter().flatten() {
+			query.and(SearchKey::Header(name.clone(), value.clone()));
+		}
+
+		if let Some(since) = self.since {
+			query.and(SearchKey::SentSince(since));
+		}
+
+		if let Some(before) = self.before {
+			query.and(SearchKey::SentBefore(before));
+		}
+
+		query
+	}
+
+	/// Checks if the `mail` matches the [`Filters::headers`] filter
+	/// for when the server doesn't support searching by arbitrary headers.
+	///
+	/// Like in IMAP, the header value has to contain the filter value, ignoring case
+	#[cfg(feature = "source-gmail")]
+	pub(super) fn matches_headers(&self, mail: &ParsedMail<'_>) -> bool {
+		self.headers.iter().flatten().all(|(name, expected)| {
+			let expected = expected.to_lowercase();
+
+			mail.headers
+				.get_all_values(name)
+				.iter()
+				.any(|value| value.to_lowercase().contains(&expected))
+		})
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn imap_search() {
+		let filters = Filters::builder()
+			.sender("a@example.com")
+			.sender("b@example.com")
+			.to("me@example.com")
+			.exclude_subject("[spam]")
+			.header("List-Id", "dev")
+			.before(NaiveDate::from_ymd_opt(2025, 12, 31).unwrap())
+			.include_seen(true)
+			.build();
+
+		assert_eq!(
+			filters.imap_search().to_string(),
+			r#"OR (FROM "a@example.com") (FROM "b@example.com") TO "me@example.com" NOT SUBJECT "[spam]" HEADER "List-Id" "dev" SENTBEFORE 31-Dec-2025"#
+		);
+
+		assert_eq!(
+			Filters::builder().build().imap_search().to_string(),
+			"UNSEEN"
+		);
+	}
+
+	#[cfg(feature = "source-gmail")]
+	#[test]
+	fn matches_headers() {
+		let mail =
+			mailparse::parse_mail(b"List-Id: Dev List <dev.example.com>\r\n\r\nbody").unwrap();
+
+		let filters = |value| Filters::builder().header("list-id", value).build();
+		assert!(filters("DEV.example").matches_headers(&mail));
+		assert!(!filters("users").matches_headers(&mail));
+		assert!(Filters::builder().build().matches_headers(&mail));
+	}
 }
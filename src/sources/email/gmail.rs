@@ -0,0 +1,799 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+
+//! This module contains the [`Gmail`] source that uses the Gmail REST API instead of IMAP
+
+use base64::{
+	Engine, alphabet,
+	engine::{DecodePaddingMode, GeneralPurpose, GeneralPurposeConfig},
+};
+use reqwest::{Method, StatusCode};
+use serde::{Deserialize, de::DeserializeOwned};
+use serde_json::json;
+use std::{
+	collections::{HashMap, HashSet},
+	fmt::{self, Debug, Write as _},
+};
+use url::Url;
+
+use super::{Filters, ViewMode, parse, threading};
+use crate::{
+	StaticStr,
+	auth::{Google as GoogleAuth, google::GoogleOAuth2Error as GoogleAuthError},
+	entry::{Entry, EntryId},
+	error::Error,
+	read_filter::MarkAsRead,
+	sources::{
+		Fetch, Source,
+		http::{HttpClient, HttpError, Json, Request, send_request},
+	},
+};
+
+/// URL of the Gmail REST API for the authenticated user
+pub const DEFAULT_GMAIL_API_URL: &str = "https://gmail.googleapis.com/gmail/v1/users/me/";
+
+/// Search query used if [`Gmail::query`] isn't set
+const DEFAULT_QUERY: &str = "in:inbox";
+
+const UNREAD_LABEL: &str = "UNREAD";
+const INBOX_LABEL: &str = "INBOX";
+
+/// Gmail encodes raw emails as base64url, with or without padding
+const BASE64_URL: GeneralPurpose = GeneralPurpose::new(
+	&alphabet::URL_SAFE,
+	GeneralPurposeConfig::new().with_decode_padding_mode(DecodePaddingMode::Indifferent),
+);
+
+#[expect(clippy::doc_markdown, reason = "false positive")]
+/// Email source that uses the Gmail REST API instead of IMAP, authenticating with [`Google OAuth2`](`GoogleAuth`).
+///
+/// Fetches all unread emails matching the [`Gmail::query`] and the [`Filters`]
+/// and parses them the same way as [`Email`](`super::Email`) does, including attachments and threading.
+///
+/// # Incremental sync
+/// The first fetch downloads all matching emails. After that, the history of the mailbox is used
+/// to only download the matching emails that have arrived since the previous fetch, as well as the ones
+/// that have been fetched before but haven't been marked as read yet, e.g. because sending them has failed.
+/// The mailbox isn't searched at all if there are none of either.
+/// If the history is no longer available, all matching emails are downloaded again.
+///
+/// # Entry IDs
+/// The ID of an entry is the Gmail ID of the email, or its `Message-ID` if [`Gmail::threading`] is enabled.
+/// See [`Email#entry-ids`](`super::Email#entry-ids`)
+///
+/// # View modes
+/// [`ViewMode::MarkAsRead`] removes the `UNREAD` label and [`ViewMode::Delete`] moves the emails to the trash.
+/// [`ViewMode::MoveTo`] adds the label and removes `INBOX`, and [`ViewMode::Label`] just adds the label,
+/// both also marking the emails as read. Labels are referred to by their names, e.g. `Processed`
+///
+/// ```
+/// # use fetcher::{auth::Google, sources::email::{Filters, Gmail, ViewMode}};
+/// let gmail = Gmail::builder()
+///     .auth(Google::new("client_id", "client_secret", "refresh_token"))
+///     .query("label:work newer_than:2d")
+///     .filters(Filters::builder().sender("ci@example.com").build())
+///     .view_mode(ViewMode::Label("Processed".into()))
+///     .build();
+/// ```
+#[derive(bon::Builder)]
+pub struct Gmail {
+	/// Gmail search query, e.g. `from:github.com label:work newer_than:2d`. Searches the inbox if `None`.
+	///
+	/// Only unread emails are fetched regardless of the query
+	#[builder(into)]
+	pub query: Option<StaticStr>,
+
+	/// Search filters, added to the query
+	pub filters: Filters,
+
+	/// What to do with the read emails
+	pub view_mode: ViewMode,
+
+	/// Use the `Message-ID` as the entry ID and thread replies. See [`Email#entry-ids`](`super::Email#entry-ids`)
+	#[builder(default)]
+	pub threading: bool,
+
+	/// URL of the Gmail REST API. Defaults to [`DEFAULT_GMAIL_API_URL`]
+	#[builder(default = Url::parse(DEFAULT_GMAIL_API_URL).expect("default API URL should be valid"))]
+	pub api_url: Url,
+
+	/// HTTP client to send the requests with. Uses the [default client](`HttpClient::shared_default`) if `None`
+	pub client: Option<HttpClient>,
+
+	/// Google OAuth2 authenticator with access to Gmail
+	auth: GoogleAuth,
+
+	/// ID of the last change to the mailbox seen by the previous fetch
+	#[builder(skip)]
+	history_id: Option<String>,
+
+	/// Gmail IDs of the fetched emails that haven't been marked as read yet
+	#[builder(skip)]
+	unmarked: HashSet<String>,
+
+	/// Gmail IDs of the fetched emails by their `Message-ID`s if [`Gmail::threading`] is enabled
+	#[builder(skip)]
+	ids_by_message_id: HashMap<EntryId, String>,
+
+	/// IDs of the labels by their names
+	#[builder(skip)]
+	label_ids: HashMap<StaticStr, String>,
+}
+
+#[expect(missing_docs, reason = "error message is self-documenting")]
+#[derive(thiserror::Error, Debug)]
+pub enum GmailError {
+	#[error("Can't authenticate with Google")]
+	GoogleAuth(#[from] GoogleAuthError),
+
+	#[error("HTTP error")]
+	Http(#[from] HttpError),
+
+	#[error("API URL {0} can't be a base URL")]
+	InvalidApiUrl(Url),
+
+	#[error("Invalid response from the Gmail API: {1:?}")]
+	BadResponse(#[source] serde_json::Error, String),
+
+	#[error("Invalid base64 contents of the email {0:?}")]
+	BadBase64(String, #[source] base64::DecodeError),
+
+	#[error("Error parsing email")]
+	Parse(#[from] mailparse::MailParseError),
+
+	#[error("Label {0:?} doesn't exist")]
+	LabelNotFound(StaticStr),
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Profile {
+	history_id: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct MessageList {
+	#[serde(default)]
+	messages: Vec<MessageRef>,
+	next_page_token: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct MessageRef {
+	id: String,
+}
+
+#[derive(Deserialize)]
+struct RawMessage {
+	raw: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct HistoryList {
+	#[serde(default)]
+	history: Vec<History>,
+	next_page_token: Option<String>,
+	history_id: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct History {
+	#[serde(default)]
+	messages_added: Vec<MessageAdded>,
+}
+
+#[derive(Deserialize)]
+struct MessageAdded {
+	message: MessageRef,
+}
+
+#[derive(Deserialize)]
+struct LabelList {
+	#[serde(default)]
+	labels: Vec<Label>,
+}
+
+#[derive(Deserialize)]
+struct Label {
+	id: String,
+	name: String,
+}
+
+impl Fetch for Gmail {
+	type Err = GmailError;
+
+	/// Fetches all unread emails matching the query and the filters,
+	/// or only the ones that have arrived since the last fetch or haven't been marked as read yet
+	///
+	/// # Errors
+	/// This function may error if the authentication failed, the network connection is down,
+	/// the API returns an error or a garbage response, or an email can't be parsed
+	async fn fetch(&mut self) -> Result<Vec<Entry>, Self::Err> {
+		let (added, history_id) = match self.history_id.clone() {
+			Some(start) => self.messages_added_since(&start).await?,
+			None => (None, self.current_history_id().await?),
+		};
+
+		let ids = match added {
+			Some(added) if added.is_empty() && self.unmarked.is_empty() => {
+				tracing::debug!("No new emails have arrived since the last fetch");
+				Vec::new()
+			}
+			Some(added) => self
+				.search()
+				.await?
+				.into_iter()
+				.filter(|id| added.contains(id) || self.unmarked.contains(id))
+				.collect(),
+			None => self.search().await?,
+		};
+
+		// forget about the emails that don't match anymore, e.g. the ones that have been read elsewhere
+		self.unmarked.retain(|id| ids.contains(id));
+
+		if !ids.is_empty() {
+			tracing::info!("Got {} unread filtered mails", ids.len());
+		}
+
+		let mut entries = Vec::with_capacity(ids.len());
+
+		// the search returns the newest emails first
+		for id in ids.into_iter().rev() {
+			if let Some(entry) = self.get_message(id.clone()).await? {
+				self.unmarked.insert(id);
+				entries.push(entry);
+			}
+		}
+
+		self.history_id = Some(history_id);
+		Ok(entries)
+	}
+}
+
+impl MarkAsRead for Gmail {
+	type Err = GmailError;
+
+	async fn mark_as_read(&mut self, id: &EntryId) -> Result<(), Self::Err> {
+		if let ViewMode::ReadOnly = self.view_mode {
+			let gmail_id = self
+				.ids_by_message_id
+				.remove(id)
+				.unwrap_or_else(|| id.as_str().to_owned());
+
+			self.unmarked.remove(&gmail_id);
+			return Ok(());
+		}
+
+		let gmail_id = if threading::is_message_id(id) {
+			match self.ids_by_message_id.get(id).cloned() {
+				Some(gmail_id) => Some(gmail_id),
+				None => self.find_by_message_id(id).await?,
+			}
+		} else {
+			Some(id.as_str().to_owned())
+		};
+
+		let Some(gmail_id) = gmail_id else {
+			tracing::warn!(
+				"Email with Message-ID {id:?} not found, it might have already been deleted"
+			);
+			return Ok(());
+		};
+
+		match &self.view_mode {
+			ViewMode::MarkAsRead => {
+				self.modify(&gmail_id, None, &[UNREAD_LABEL]).await?;
+				tracing::debug!("Marked email {gmail_id} as read");
+			}
+			ViewMode::Delete => {
+				self.call::<Json>(Method::POST, &["messages", &gmail_id, "trash"], &[], None)
+					.await?;
+				tracing::debug!("Moved email {gmail_id} to the trash");
+			}
+			ViewMode::MoveTo(label) => {
+				let label = label.clone();
+				self.modify(&gmail_id, Some(&label), &[UNREAD_LABEL, INBOX_LABEL])
+					.await?;
+				tracing::debug!("Moved email {gmail_id} to {label}");
+			}
+			ViewMode::Label(label) => {
+				let label = label.clone();
+				self.modify(&gmail_id, Some(&label), &[UNREAD_LABEL])
+					.await?;
+				tracing::debug!("Labeled email {gmail_id} as {label}");
+			}
+			ViewMode::ReadOnly => unreachable!(),
+		}
+
+		self.ids_by_message_id.remove(id);
+		self.unmarked.remove(&gmail_id);
+		Ok(())
+	}
+
+	async fn set_read_only(&mut self) {
+		self.view_mode = ViewMode::ReadOnly;
+	}
+}
+
+impl Source for Gmail {}
+
+impl Gmail {
+	/// Creates the Gmail search query out of [`Gmail::query`] and [`Gmail::filters`].
+	///
+	/// Gmail can't search by arbitrary headers, so [`Filters::headers`] are checked after the emails have been downloaded
+	fn search_query(&self) -> String {
+		let filters = &self.filters;
+		let mut query = String::new();
+
+		if !filters.include_seen {
+			query.push_str("is:unread ");
+		}
+
+		query.push_str(self.query.as_deref().unwrap_or(DEFAULT_QUERY));
+
+		match filters.senders.as_deref() {
+			None | Some([]) => (),
+			Some([sender]) => _ = write!(query, " from:{}", quote(sender)),
+			// braces match any of the terms inside
+			Some(senders) => {
+				let senders = senders
+					.iter()
+					.map(|sender| format!("from:{}", quote(sender)))
+					.collect::<Vec<_>>();
+
+				_ = write!(query, " {{{}}}", senders.join(" "));
+			}
+		}
+
+		let terms = [
+			("to:", &filters.to),
+			("cc:", &filters.cc),
+			("subject:", &filters.subjects),
+			("-subject:", &filters.exclude_subjects),
+			("", &filters.bodies),
+		];
+
+		for (operator, values) in terms {
+			for value in values.iter().flatten() {
+				_ = write!(query, " {operator}{}", quote(value));
+			}
+		}
+
+		if let Some(since) = filters.since {
+			_ = write!(query, " after:{}", since.format("%Y/%m/%d"));
+		}
+
+		if let Some(before) = filters.before {
+			_ = write!(query, " before:{}", before.format("%Y/%m/%d"));
+		}
+
+		query
+	}
+
+	/// Returns the IDs of all unread emails matching the query, the newest first
+	async fn search(&mut self) -> Result<Vec<String>, GmailError> {
+		let search_query = self.search_query();
+		tracing::debug!("Searching for all emails that match the query {search_query:?}");
+
+		let mut ids = Vec::new();
+		let mut page_token = None;
+
+		loop {
+			let mut query = vec![("q", search_query.clone())];
+			if let Some(page_token) = page_token.take() {
+				query.push(("pageToken", page_token));
+			}
+
+			let page: MessageList = self.call(Method::GET, &["messages"], &query, None).await?;
+			ids.extend(page.messages.into_iter().map(|message| message.id));
+
+			match page.next_page_token {
+				Some(next) => page_token = Some(next),
+				None => return Ok(ids),
+			}
+		}
+	}
+
+	/// Returns the IDs of the emails that have arrived since the `start` history ID,
+	/// or `None` if that part of the history is no longer available, as well as the current history ID
+	async fn messages_added_since(
+		&mut self,
+		start: &str,
+	) -> Result<(Option<HashSet<String>>, String), GmailError> {
+		let mut added = HashSet::new();
+		let mut page_token = None;
+
+		loop {
+			let mut query = vec![
+				("startHistoryId", start.to_owned()),
+				("historyTypes", "messageAdded".to_owned()),
+			];
+			if let Some(page_token) = page_token.take() {
+				query.push(("pageToken", page_token));
+			}
+
+			let page: HistoryList = match self.call(Method::GET, &["history"], &query, None).await {
+				Ok(page) => page,
+				Err(GmailError::Http(HttpError::UnsuccessfulStatus {
+					status: StatusCode::NOT_FOUND,
+					..
+				})) => {
+					tracing::warn!(
+						"History since {start} is no longer available, fetching all emails again"
+					);
+					return Ok((None, self.current_history_id().await?));
+				}
+				Err(e) => return Err(e),
+			};
+
+			added.extend(
+				page.history
+					.into_iter()
+					.flat_map(|history| history.messages_added)
+					.map(|added| added.message.id),
+			);
+
+			match page.next_page_token {
+				Some(next) => page_token = Some(next),
+				None => return Ok((Some(added), page.history_id)),
+			}
+		}
+	}
+
+	async fn current_history_id(&mut self) -> Result<String, GmailError> {
+		let profile: Profile = self.call(Method::GET, &["profile"], &[], None).await?;
+		Ok(profile.history_id)
+	}
+
+	/// Downloads and parses the email with the Gmail `id`. Returns `None` if it doesn't match the [`Filters::headers`]
+	async fn get_message(&mut self, id: String) -> Result<Option<Entry>, GmailError> {
+		let message: RawMessage = self
+			.call(
+				Method::GET,
+				&["messages", &id],
+				&[("format", "raw".to_owned())],
+				None,
+			)
+			.await?;
+
+		let raw = BASE64_URL
+			.decode(&message.raw)
+			.map_err(|e| GmailError::BadBase64(id.clone(), e))?;
+
+		let mail = mailparse::parse_mail(&raw)?;
+		if !self.filters.matches_headers(&mail) {
+			tracing::debug!("Skipping email {id} since its headers don't match the filters");
+			return Ok(None);
+		}
+
+		let entry_id =
+			EntryId::try_from(id.as_str()).expect("Gmail IDs of emails should never be empty");
+		let mut entry = parse(&mail, entry_id, &self.filters.attachments)?;
+
+		if self.threading {
+			entry.reply_to = threading::parent_id(&mail);
+
+			if let Some(message_id) = threading::message_id(&mail) {
+				entry.id = Some(message_id.clone());
+				self.ids_by_message_id.insert(message_id, id);
+			}
+		}
+
+		Ok(Some(entry))
+	}
+
+	/// Searches for the email with the `message_id` and returns its Gmail ID
+	async fn find_by_message_id(
+		&mut self,
+		message_id: &EntryId,
+	) -> Result<Option<String>, GmailError> {
+		tracing::debug!("Searching for the email with Message-ID {message_id:?}");
+
+		let query = format!(
+			"rfc822msgid:{}",
+			message_id
+				.as_str()
+				.trim_start_matches('<')
+				.trim_end_matches('>')
+		);
+
+		let list: MessageList = self
+			.call(Method::GET, &["messages"], &[("q", query)], None)
+			.await?;
+
+		Ok(list.messages.into_iter().next().map(|message| message.id))
+	}
+
+	/// Adds the label with the name `add` to the email and removes the labels with the IDs `remove`
+	async fn modify(
+		&mut self,
+		id: &str,
+		add: Option<&StaticStr>,
+		remove: &[&str],
+	) -> Result<(), GmailError> {
+		let add = match add {
+			Some(name) => vec![self.label_id(name).await?],
+			None => Vec::new(),
+		};
+
+		let body = json!({
+			"addLabelIds": add,
+			"removeLabelIds": remove,
+		});
+
+		self.call::<Json>(Method::POST, &["messages", id, "modify"], &[], Some(body))
+			.await?;
+
+		Ok(())
+	}
+
+	/// Returns the ID of the label with the `name`
+	async fn label_id(&mut self, name: &StaticStr) -> Result<String, GmailError> {
+		if let Some(id) = self.label_ids.get(name) {
+			return Ok(id.clone());
+		}
+
+		let list: LabelList = self.call(Method::GET, &["labels"], &[], None).await?;
+		self.label_ids = list
+			.labels
+			.into_iter()
+			.map(|label| (StaticStr::from(label.name), label.id))
+			.collect();
+
+		self.label_ids
+			.get(name)
+			.cloned()
+			.ok_or_else(|| GmailError::LabelNotFound(name.clone()))
+	}
+
+	/// Sends a request to the API endpoint at `path` and parses the JSON response
+	async fn call<T: DeserializeOwned>(
+		&mut self,
+		method: Method,
+		path: &[&str],
+		query: &[(&'static str, String)],
+		body: Option<Json>,
+	) -> Result<T, GmailError> {
+		let client = match &self.client {
+			Some(client) => client.clone(),
+			None => HttpClient::shared_default()?,
+		};
+
+		let mut url = self.api_url.clone();
+		url.path_segments_mut()
+			.map_err(|()| GmailError::InvalidApiUrl(self.api_url.clone()))?
+			.pop_if_empty()
+			.extend(path);
+
+		let mut request = Request::builder()
+			.method(method)
+			.bearer_auth(self.auth.access_token().await?.to_owned());
+		for (key, value) in query {
+			request = request.query(*key, value.clone());
+		}
+
+		let request = match body {
+			Some(body) => request.json(body).build(),
+			None => request.build(),
+		};
+
+		let response = send_request(&client, &request, &url).await?;
+		serde_json::from_str(&response).map_err(|e| GmailError::BadResponse(e, response))
+	}
+}
+
+/// Quotes a term of a Gmail search query. Gmail doesn't support escaping quotes, so they are removed
+fn quote(s: &str) -> String {
+	format!("\"{}\"", s.replace('"', ""))
+}
+
+impl Error for GmailError {
+	fn is_network_related(&self) -> Option<&dyn Error> {
+		match self {
+			Self::GoogleAuth(e) if e.is_network_related().is_some() => Some(self),
+			Self::Http(e) if e.is_network_related().is_some() => Some(self),
+			_ => None,
+		}
+	}
+
+	fn retry_after(&self) -> Option<std::time::Duration> {
+		match self {
+			Self::Http(e) => e.retry_after(),
+			_ => None,
+		}
+	}
+}
+
+impl Debug for Gmail {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.debug_struct("Gmail")
+			.field("query", &self.query)
+			.field("filters", &self.filters)
+			.field("view_mode", &self.view_mode)
+			.field("threading", &self.threading)
+			.field("api_url", &self.api_url.as_str())
+			.field("history_id", &self.history_id)
+			.field("unmarked", &self.unmarked)
+			.finish_non_exhaustive()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::sources::http::mock_server::{self, Request, Response};
+
+	fn gmail(api_url: Url, view_mode: ViewMode) -> Gmail {
+		Gmail::builder()
+			.auth(GoogleAuth::with_access_token("token"))
+			.api_url(api_url)
+			.filters(Filters::builder().sender("alice@example.com").build())
+			.view_mode(view_mode)
+			.build()
+	}
+
+	fn raw_message(id: &str) -> String {
+		let mail = format!("Message-ID: <{id}@example.com>\r\nSubject: Mail {id}\r\n\r\nHello\r\n");
+		format!(
+			r#"{{ "id": "{id}", "raw": "{}" }}"#,
+			BASE64_URL.encode(mail)
+		)
+	}
+
+	fn respond(request: &Request) -> Response {
+		let query = &request.target;
+
+		match (request.method.as_str(), request.path()) {
+			("GET", "/profile") => Response::ok(r#"{ "historyId": "100" }"#),
+			("GET", "/history") if query.contains("startHistoryId=100&") => Response::ok(
+				r#"{ "historyId": "101", "history": [
+					{ "messagesAdded": [{ "message": { "id": "m3" } }, { "message": { "id": "m4" } }] }
+				] }"#,
+			),
+			("GET", "/history") if query.contains("startHistoryId=101&") => {
+				Response::ok(r#"{ "historyId": "101" }"#)
+			}
+			("GET", "/history") => Response::new(404, r#"{ "error": { "code": 404 } }"#),
+			("GET", "/messages") => {
+				Response::ok(r#"{ "messages": [{ "id": "m3" }, { "id": "m2" }, { "id": "m1" }] }"#)
+			}
+			("GET", "/labels") => Response::ok(
+				r#"{ "labels": [{ "id": "INBOX", "name": "INBOX" }, { "id": "Label_1", "name": "Processed" }] }"#,
+			),
+			("POST", "/messages/m1/modify") => Response::ok("{}"),
+			("GET", path) if path.starts_with("/messages/") => {
+				Response::ok(raw_message(path.trim_start_matches("/messages/")))
+			}
+			_ => Response::new(400, "{}"),
+		}
+	}
+
+	fn ids(entries: &[Entry]) -> Vec<&str> {
+		entries
+			.iter()
+			.map(|entry| entry.id.as_ref().unwrap().as_str())
+			.collect()
+	}
+
+	async fn mark_all_as_read(gmail: &mut Gmail, entries: &[Entry]) {
+		for entry in entries {
+			gmail
+				.mark_as_read(entry.id.as_ref().unwrap())
+				.await
+				.unwrap();
+		}
+	}
+
+	#[tokio::test]
+	async fn downloads_only_new_emails_after_the_first_fetch() {
+		let server = mock_server::start(respond).await;
+		let requests = &server.requests;
+		let mut gmail = gmail(server.url.clone(), ViewMode::ReadOnly);
+
+		let entries = gmail.fetch().await.unwrap();
+		assert_eq!(ids(&entries), ["m1", "m2", "m3"]);
+		assert_eq!(entries[0].msg.title.as_deref(), Some("Mail m1"));
+
+		let search = server.requests()[1].target.clone();
+		let search = server.url.join(&search).unwrap();
+		assert_eq!(
+			search.query_pairs().collect::<Vec<_>>(),
+			[(
+				"q".into(),
+				r#"is:unread in:inbox from:"alice@example.com""#.into()
+			)]
+		);
+
+		mark_all_as_read(&mut gmail, &entries).await;
+
+		requests.lock().unwrap().clear();
+		let entries = gmail.fetch().await.unwrap();
+		assert_eq!(ids(&entries), ["m3"], "m4 doesn't match the query");
+		mark_all_as_read(&mut gmail, &entries).await;
+
+		requests.lock().unwrap().clear();
+		let entries = gmail.fetch().await.unwrap();
+		assert!(entries.is_empty());
+
+		assert_eq!(
+			server.requests().len(),
+			1,
+			"shouldn't search if nothing is new"
+		);
+	}
+
+	#[tokio::test]
+	async fn fetches_unmarked_emails_again() {
+		let server = mock_server::start(respond).await;
+		let mut gmail = gmail(server.url.clone(), ViewMode::MarkAsRead);
+
+		let entries = gmail.fetch().await.unwrap();
+		assert_eq!(ids(&entries), ["m1", "m2", "m3"]);
+		mark_all_as_read(&mut gmail, &entries[..1]).await;
+
+		let entries = gmail.fetch().await.unwrap();
+		assert_eq!(ids(&entries), ["m2", "m3"]);
+
+		let entries = gmail.fetch().await.unwrap();
+		assert_eq!(
+			ids(&entries),
+			["m2", "m3"],
+			"nothing is new but m2 and m3 are still unread"
+		);
+	}
+
+	#[tokio::test]
+	async fn fetches_everything_again_if_history_is_gone() {
+		let server = mock_server::start(respond).await;
+		let mut gmail = gmail(server.url.clone(), ViewMode::ReadOnly);
+		gmail.history_id = Some("1".to_owned());
+
+		let entries = gmail.fetch().await.unwrap();
+		assert_eq!(ids(&entries), ["m1", "m2", "m3"]);
+		assert_eq!(gmail.history_id.as_deref(), Some("100"));
+	}
+
+	#[tokio::test]
+	async fn labels_and_marks_as_read_threaded_emails() {
+		let server = mock_server::start(respond).await;
+		let mut gmail = gmail(server.url.clone(), ViewMode::Label("Processed".into()));
+		gmail.threading = true;
+
+		let entries = gmail.fetch().await.unwrap();
+		assert_eq!(ids(&entries)[0], "<m1@example.com>");
+
+		gmail
+			.mark_as_read(entries[0].id.as_ref().unwrap())
+			.await
+			.unwrap();
+
+		assert_eq!(
+			server.requests().last().unwrap().to_string(),
+			r#"POST /messages/m1/modify {"addLabelIds":["Label_1"],"removeLabelIds":["UNREAD"]}"#
+		);
+	}
+
+	#[test]
+	fn search_query_from_filters() {
+		let mut gmail = gmail(Url::parse("http://localhost/").unwrap(), ViewMode::ReadOnly);
+		gmail.filters = Filters::builder()
+			.sender("a@example.com")
+			.sender("b@example.com")
+			.exclude_subject(r#"say "hi""#)
+			.body("invoice")
+			.since(chrono::NaiveDate::from_ymd_opt(2025, 1, 2).unwrap())
+			.include_seen(true)
+			.build();
+
+		assert_eq!(
+			gmail.search_query(),
+			r#"in:inbox {from:"a@example.com" from:"b@example.com"} -subject:"say hi" "invoice" after:2025/01/02"#
+		);
+	}
+}
@@ -0,0 +1,269 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+
+//! This module contains the conversion of HTML email bodies to readable plain text
+
+use scraper::{ElementRef, Html, Node};
+
+/// Elements that are separated from the surrounding text with an empty line
+const PARAGRAPHS: &[&str] = &[
+	"p",
+	"h1",
+	"h2",
+	"h3",
+	"h4",
+	"h5",
+	"h6",
+	"ul",
+	"ol",
+	"blockquote",
+	"pre",
+	"table",
+	"hr",
+];
+
+/// Elements that start on a new line
+const LINES: &[&str] = &[
+	"div", "li", "tr", "section", "article", "header", "footer", "dl", "dt", "dd", "center",
+];
+
+/// Elements whose contents are never shown
+const HIDDEN: &[&str] = &["head", "title", "style", "script", "template"];
+
+/// Converts the HTML document to plain text, keeping paragraphs, line breaks, lists, and links.
+///
+/// Links are kept in parentheses after their text, e.g. `Release notes (https://example.com/notes)`
+pub(super) fn html_to_text(html: &str) -> String {
+	let dom = Html::parse_document(html);
+
+	let mut writer = TextWriter::default();
+	writer.write_children(dom.root_element());
+
+	writer.text
+}
+
+#[derive(Default)]
+struct TextWriter {
+	text: String,
+
+	/// Newlines to put before the next text
+	newlines: usize,
+
+	/// Whether to put a space before the next text
+	space: bool,
+
+	/// Lists the current element is in. `None` for an unordered list, or the number of the next item of an ordered list
+	lists: Vec<Option<usize>>,
+
+	/// Whether the current element is inside a `<pre>`
+	preformatted: bool,
+}
+
+impl TextWriter {
+	fn write_children(&mut self, element: ElementRef<'_>) {
+		for child in element.children() {
+			if let Node::Text(text) = child.value() {
+				self.write_text(text);
+			} else if let Some(child) = ElementRef::wrap(child) {
+				self.write_element(child);
+			}
+		}
+	}
+
+	fn write_element(&mut self, element: ElementRef<'_>) {
+		let name = element.value().name();
+
+		if HIDDEN.contains(&name) {
+			return;
+		}
+
+		match name {
+			"br" => {
+				self.newlines += 1;
+				self.space = false;
+				return;
+			}
+			"hr" => {
+				self.block(2);
+				self.write_word("---");
+				self.block(2);
+				return;
+			}
+			_ => (),
+		}
+
+		// nested lists are a part of their list item and not a separate paragraph
+		let is_nested_list = matches!(name, "ul" | "ol") && !self.lists.is_empty();
+		let is_paragraph = PARAGRAPHS.contains(&name) && !is_nested_list;
+		if is_paragraph {
+			self.block(2);
+		} else if LINES.contains(&name) || is_nested_list {
+			self.block(1);
+		}
+
+		match name {
+			"ul" => self.lists.push(None),
+			"ol" => self.lists.push(Some(1)),
+			"li" => self.write_list_marker(),
+			"pre" => self.preformatted = true,
+			_ => (),
+		}
+
+		self.write_children(element);
+
+		match name {
+			"ul" | "ol" => {
+				self.lists.pop();
+			}
+			"pre" => self.preformatted = false,
+			"a" => self.write_link(element),
+			"td" | "th" => self.space = true,
+			_ => (),
+		}
+
+		if is_paragraph {
+			self.block(2);
+		} else if LINES.contains(&name) || is_nested_list {
+			self.block(1);
+		}
+	}
+
+	fn write_text(&mut self, text: &str) {
+		if self.preformatted {
+			self.flush_breaks();
+			self.text.push_str(text);
+			return;
+		}
+
+		if text.starts_with(char::is_whitespace) {
+			self.space = true;
+		}
+
+		let mut words = text.split_whitespace().peekable();
+		while let Some(word) = words.next() {
+			self.write_word(word);
+
+			if words.peek().is_some() {
+				self.space = true;
+			}
+		}
+
+		if text.ends_with(char::is_whitespace) {
+			self.space = true;
+		}
+	}
+
+	fn write_word(&mut self, word: &str) {
+		if !self.flush_breaks() && self.space && !self.text.is_empty() {
+			self.text.push(' ');
+		}
+
+		self.space = false;
+		self.text.push_str(word);
+	}
+
+	fn write_list_marker(&mut self) {
+		let depth = self.lists.len().saturating_sub(1);
+		let marker = match self.lists.last_mut() {
+			Some(Some(next)) => {
+				let marker = format!("{next}.");
+				*next += 1;
+				marker
+			}
+			Some(None) | None => "-".to_owned(),
+		};
+
+		self.flush_breaks();
+		self.text.push_str(&"  ".repeat(depth));
+		self.write_word(&marker);
+		self.space = true;
+	}
+
+	/// Puts the link after its text unless they are the same
+	fn write_link(&mut self, element: ElementRef<'_>) {
+		let Some(href) = element.value().attr("href").map(str::trim) else {
+			return;
+		};
+
+		if !(href.starts_with("http://") || href.starts_with("https://")) {
+			return;
+		}
+
+		let text = element.text().map(str::trim).collect::<String>();
+
+		if text.is_empty() {
+			self.space = true;
+			self.write_word(href);
+		} else if text != href {
+			self.space = true;
+			self.write_word(&format!("({href})"));
+		}
+	}
+
+	/// Requests at least `newlines` newlines before the next text
+	fn block(&mut self, newlines: usize) {
+		self.newlines = self.newlines.max(newlines);
+		self.space = false;
+	}
+
+	/// Writes the requested newlines if there's any text before them. Returns whether any have been written
+	fn flush_breaks(&mut self) -> bool {
+		let newlines = std::mem::take(&mut self.newlines);
+		if newlines == 0 || self.text.is_empty() {
+			return false;
+		}
+
+		let text_newlines = self.text.len() - self.text.trim_end_matches('\n').len();
+		for _ in text_newlines..newlines.min(2) {
+			self.text.push('\n');
+		}
+
+		true
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn keeps_paragraphs_lists_and_links() {
+		let html = r#"
+			<html>
+				<head><title>Newsletter</title><style>p { color: red; }</style></head>
+				<body>
+					<h1>News   of the&nbsp;week</h1>
+					<p>Hello <b>everyone</b>,<br>here's what happened:</p>
+					<ul>
+						<li>A <a href="https://example.com/release">new release</a></li>
+						<li>Docs at <a href="https://example.com/docs">https://example.com/docs</a></li>
+					</ul>
+					<ol><li>First</li><li>Second</li></ol>
+					<div>Bye</div>
+				</body>
+			</html>
+		"#;
+
+		assert_eq!(
+			html_to_text(html),
+			"News of the week\n\n\
+			Hello everyone,\n\
+			here's what happened:\n\n\
+			- A new release (https://example.com/release)\n\
+			- Docs at https://example.com/docs\n\n\
+			1. First\n\
+			2. Second\n\n\
+			Bye"
+		);
+	}
+
+	#[test]
+	fn nested_lists_are_indented() {
+		let html = "<ul><li>Outer<ul><li>Inner</li></ul></li></ul>";
+
+		assert_eq!(html_to_text(html), "- Outer\n  - Inner");
+	}
+}
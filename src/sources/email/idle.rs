@@ -0,0 +1,355 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+
+//! This module contains the [`Idle`] trigger that uses IMAP IDLE to wait for new emails
+
+use async_imap::{Session, extensions::idle::IdleResponse};
+use futures::future::select_all;
+use std::time::Duration;
+use tokio_rustls::rustls::pki_types::CertificateDer;
+
+use super::{Auth, Email, ImapError, Security, connection::MaybeTlsStream, login};
+use crate::{
+	StaticStr,
+	cancellation_token::{CancellationToken, cancel_wait},
+	job::trigger::{Trigger, TriggerResult},
+};
+
+/// How long to IDLE before re-issuing the command.
+///
+/// RFC 2177 recommends to re-IDLE at least every 29 minutes since servers may log out inactive clients after 30 minutes
+pub const DEFAULT_REIDLE_EVERY: Duration = Duration::from_mins(29);
+
+/// How long to wait before reconnecting after the connection has been lost
+pub const DEFAULT_RECONNECT_AFTER: Duration = Duration::from_mins(1);
+
+/// A [`Trigger`] that uses IMAP IDLE to re-trigger the job as soon as a new email arrives in any of the mailboxes of an [`Email`] source.
+///
+/// Created with [`Email::idle`]. Each mailbox is watched using its own connection.
+///
+/// If the connection is lost, e.g. because the server dropped it, it's re-established after [`Idle::reconnect_after`] has passed.
+/// Emails that have arrived in the meantime re-trigger the job right after reconnecting.
+///
+/// Since it's unknown which emails the job has already fetched before the first wait,
+/// the job is re-triggered right away the first time if the mailbox isn't empty.
+/// Other errors, e.g. failed authentication, are returned from [`Trigger::wait`].
+pub struct Idle {
+	/// How often to re-issue the IDLE command to keep the connection alive. Defaults to [`DEFAULT_REIDLE_EVERY`]
+	pub reidle_every: Duration,
+
+	/// How long to wait before reconnecting after the connection has been lost. Defaults to [`DEFAULT_RECONNECT_AFTER`]
+	pub reconnect_after: Duration,
+
+	/// Stop the job when signalled, even while waiting for a new email
+	pub cancel_token: Option<CancellationToken>,
+
+	server: Server,
+	watchers: Vec<Watcher>,
+}
+
+/// The IMAP server and the login to use
+struct Server {
+	address: StaticStr,
+	port: u16,
+	security: Security,
+	root_certs: Vec<CertificateDer<'static>>,
+	email: StaticStr,
+}
+
+/// Waits for new emails in a single mailbox
+struct Watcher {
+	mailbox: StaticStr,
+	auth: Auth,
+
+	/// The UID the next email in the mailbox is going to get. `None` if the mailbox hasn't been examined yet,
+	/// in which case every email in it might be new
+	uid_next: Option<u32>,
+}
+
+impl Email {
+	/// Creates an [`Idle`] trigger that uses the same server, login, and mailboxes as this source
+	#[must_use]
+	pub fn idle(&self) -> Idle {
+		Idle {
+			reidle_every: DEFAULT_REIDLE_EVERY,
+			reconnect_after: DEFAULT_RECONNECT_AFTER,
+			cancel_token: None,
+			server: Server {
+				address: self.imap_server.clone(),
+				port: self.port,
+				security: self.security,
+				root_certs: self.root_certs.clone(),
+				email: self.email.clone(),
+			},
+			watchers: self
+				.mailboxes
+				.iter()
+				.map(|mailbox| Watcher {
+					mailbox: mailbox.clone(),
+					auth: self.auth.clone(),
+					uid_next: None,
+				})
+				.collect(),
+		}
+	}
+}
+
+impl Idle {
+	/// Sets the [`Idle::cancel_token`], usually to the one of the job
+	#[must_use]
+	pub fn with_cancel_token(mut self, cancel_token: CancellationToken) -> Self {
+		self.cancel_token = Some(cancel_token);
+		self
+	}
+}
+
+impl Trigger for Idle {
+	type Err = ImapError;
+
+	async fn wait(&mut self) -> Result<TriggerResult, Self::Err> {
+		let Self {
+			reidle_every,
+			reconnect_after,
+			cancel_token,
+			server,
+			watchers,
+		} = self;
+
+		let watch_all = select_all(
+			watchers
+				.iter_mut()
+				.map(|watcher| Box::pin(watcher.watch(server, *reidle_every, *reconnect_after))),
+		);
+
+		tokio::select! {
+			(res, _, _) = watch_all => res.map(|()| TriggerResult::Resume),
+			() = cancel_wait(cancel_token.as_mut()) => {
+				tracing::debug!("Stopped waiting for new emails since the job has been cancelled");
+				Ok(TriggerResult::Stop)
+			}
+		}
+	}
+
+	fn twice_as_duration(&self) -> Duration {
+		self.reidle_every * 2
+	}
+}
+
+impl Watcher {
+	/// Waits until a new email arrives, reconnecting if the connection has been lost
+	async fn watch(
+		&mut self,
+		server: &Server,
+		reidle_every: Duration,
+		reconnect_after: Duration,
+	) -> Result<(), ImapError> {
+		loop {
+			match self.wait_for_new_email(server, reidle_every).await {
+				Ok(()) => return Ok(()),
+				Err(e) if is_connection_lost(&e) => {
+					tracing::warn!(
+						"Lost the connection while waiting for new emails in {}, reconnecting in {}s: {e}",
+						self.mailbox,
+						reconnect_after.as_secs()
+					);
+					tokio::time::sleep(reconnect_after).await;
+				}
+				Err(e) => return Err(e),
+			}
+		}
+	}
+
+	async fn wait_for_new_email(
+		&mut self,
+		server: &Server,
+		reidle_every: Duration,
+	) -> Result<(), ImapError> {
+		let client = super::connection::connect(
+			&server.address,
+			server.port,
+			server.security,
+			&server.root_certs,
+		)
+		.await?;
+		let mut session = login(client, &mut self.auth, &server.email).await?;
+
+		if !session.capabilities().await?.has_str("IDLE") {
+			_ = session.logout().await;
+			return Err(ImapError::IdleUnsupported);
+		}
+
+		// an email might have arrived after the job has fetched but before we were connected
+		if self.examine(&mut session).await? {
+			tracing::debug!(
+				"New emails have arrived in {} while not idling",
+				self.mailbox
+			);
+			_ = session.logout().await;
+			return Ok(());
+		}
+
+		loop {
+			tracing::trace!("Idling in {}", self.mailbox);
+
+			let mut handle = session.idle();
+			handle.init().await?;
+
+			let response = {
+				// dropping the stop source would interrupt the wait right away
+				let (wait, _stop_source) = handle.wait_with_timeout(reidle_every);
+				wait.await?
+			};
+
+			session = handle.done().await?;
+
+			match response {
+				// the mailbox has changed but not neccessarily because of a new email, e.g. a flag might have been set instead
+				IdleResponse::NewData(_) => {
+					if self.examine(&mut session).await? {
+						tracing::debug!("New emails have arrived in {}", self.mailbox);
+						_ = session.logout().await;
+						return Ok(());
+					}
+				}
+				IdleResponse::Timeout | IdleResponse::ManualInterrupt => {
+					tracing::trace!("Re-issuing IDLE in {}", self.mailbox);
+				}
+			}
+		}
+	}
+
+	/// Examines the mailbox and returns whether new emails have arrived since it was last examined
+	async fn examine(&mut self, session: &mut Session<MaybeTlsStream>) -> Result<bool, ImapError> {
+		let mailbox = session.examine(&*self.mailbox).await?;
+		let prev_uid_next = std::mem::replace(&mut self.uid_next, mailbox.uid_next);
+
+		Ok(match prev_uid_next {
+			Some(_) => prev_uid_next != mailbox.uid_next,
+			None => mailbox.exists > 0,
+		})
+	}
+}
+
+/// Checks if the error happened because the connection has been lost or couldn't be established
+fn is_connection_lost(e: &ImapError) -> bool {
+	matches!(
+		e,
+		ImapError::ConnectionFailed(_)
+			| ImapError::Other(
+				async_imap::error::Error::Io(_) | async_imap::error::Error::ConnectionLost
+			)
+	)
+}
+
+impl std::fmt::Debug for Idle {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		f.debug_struct("Idle")
+			.field("reidle_every", &self.reidle_every)
+			.field("reconnect_after", &self.reconnect_after)
+			.field("cancel_token", &self.cancel_token)
+			.field("imap_server", &self.server.address)
+			.field(
+				"mailboxes",
+				&self
+					.watchers
+					.iter()
+					.map(|watcher| &watcher.mailbox)
+					.collect::<Vec<_>>(),
+			)
+			.finish_non_exhaustive()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use assert_matches::assert_matches;
+
+	use super::*;
+	use crate::sources::email::{Filters, ViewMode, mock_server};
+
+	const MAIL: &str = "Subject: Hello\r\n\r\nWorld\r\n";
+
+	fn idle(port: u16) -> Idle {
+		let mut idle = Email::new_generic()
+			.imap_server("127.0.0.1")
+			.port(port)
+			.security(Security::Plaintext)
+			.email("user")
+			.password("password")
+			.filters(Filters::builder().build())
+			.view_mode(ViewMode::ReadOnly)
+			.call()
+			.idle();
+
+		idle.reconnect_after = Duration::from_millis(10);
+		idle
+	}
+
+	#[tokio::test]
+	async fn resumes_on_new_email() {
+		let server = mock_server::start(vec![("INBOX", Vec::new())]).await;
+		let mut idle = idle(server.port);
+
+		let wait = tokio::spawn(async move { idle.wait().await });
+
+		server.wait_for_command("IDLE").await;
+		server.deliver("INBOX", (2, MAIL.to_owned()));
+
+		let res = tokio::time::timeout(Duration::from_secs(5), wait)
+			.await
+			.unwrap()
+			.unwrap();
+		assert_matches!(res, Ok(TriggerResult::Resume));
+	}
+
+	#[tokio::test]
+	async fn resumes_right_away_the_first_time_if_mailbox_isnt_empty() {
+		let server = mock_server::start(vec![("INBOX", vec![(1, MAIL.to_owned())])]).await;
+		let mut idle = idle(server.port);
+
+		let res = tokio::time::timeout(Duration::from_secs(5), idle.wait())
+			.await
+			.unwrap();
+		assert_matches!(res, Ok(TriggerResult::Resume));
+		assert!(server.commands_starting_with("IDLE").is_empty());
+	}
+
+	#[tokio::test]
+	async fn reconnects_and_catches_up_after_connection_loss() {
+		let server = mock_server::start(vec![("INBOX", Vec::new())]).await;
+		let mut idle = idle(server.port);
+
+		let wait = tokio::spawn(async move { idle.wait().await });
+
+		server.wait_for_command("IDLE").await;
+		server.deliver_silently("INBOX", (2, MAIL.to_owned()));
+		server.drop_connections();
+
+		let res = tokio::time::timeout(Duration::from_secs(5), wait)
+			.await
+			.unwrap()
+			.unwrap();
+		assert_matches!(res, Ok(TriggerResult::Resume));
+	}
+
+	#[tokio::test]
+	async fn stops_when_cancelled() {
+		let server = mock_server::start(vec![("INBOX", Vec::new())]).await;
+		let (token, tx) = CancellationToken::new();
+		let mut idle = idle(server.port).with_cancel_token(token);
+
+		let wait = tokio::spawn(async move { idle.wait().await });
+
+		server.wait_for_command("IDLE").await;
+		tx.send(()).unwrap();
+
+		let res = tokio::time::timeout(Duration::from_secs(5), wait)
+			.await
+			.unwrap()
+			.unwrap();
+		assert_matches!(res, Ok(TriggerResult::Stop));
+	}
+}
@@ -0,0 +1,322 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+
+//! A minimal plaintext IMAP server used to test the [`Email`](`super::Email`) source.
+//!
+//! It doesn't evaluate search queries and returns all mails of the mailbox instead,
+//! but it remembers all commands it has received so that tests can check them.
+
+use std::{
+	fmt::Write as _,
+	sync::{Arc, Mutex},
+	time::Duration,
+};
+use tokio::{
+	io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+	net::{TcpListener, TcpStream},
+	sync::broadcast,
+};
+
+/// A mailbox with its mails, each with a UID and the raw contents
+pub(super) type Mailbox = (&'static str, Vec<(u32, String)>);
+
+pub(super) struct MockImap {
+	/// Port the server listens on
+	pub(super) port: u16,
+
+	/// All commands the server has received, without their tags
+	pub(super) commands: Arc<Mutex<Vec<String>>>,
+
+	mailboxes: Arc<Mutex<Vec<Mailbox>>>,
+	events: broadcast::Sender<Event>,
+}
+
+/// Something that happened outside of a connection that it should react to
+#[derive(Clone, Copy, Debug)]
+enum Event {
+	/// A new mail has been delivered to the mailbox
+	Delivered(&'static str),
+
+	/// The server has dropped all connections
+	Dropped,
+}
+
+/// Capabilities the server advertises by default
+const DEFAULT_CAPABILITIES: &str = "IMAP4rev1 IDLE MOVE";
+
+/// Starts the server in the background
+pub(super) async fn start(mailboxes: Vec<Mailbox>) -> MockImap {
+	start_with_capabilities(DEFAULT_CAPABILITIES, mailboxes).await
+}
+
+/// Starts the server in the background that advertises only the `capabilities`
+pub(super) async fn start_with_capabilities(
+	capabilities: &'static str,
+	mailboxes: Vec<Mailbox>,
+) -> MockImap {
+	let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+	let port = listener.local_addr().unwrap().port();
+
+	let mailboxes = Arc::new(Mutex::new(mailboxes));
+	let commands = Arc::new(Mutex::new(Vec::new()));
+	let (events, _) = broadcast::channel(16);
+
+	tokio::spawn({
+		let mailboxes = Arc::clone(&mailboxes);
+		let commands = Arc::clone(&commands);
+		let events = events.clone();
+
+		async move {
+			loop {
+				let (stream, _) = listener.accept().await.unwrap();
+				tokio::spawn(handle(
+					stream,
+					capabilities,
+					Arc::clone(&mailboxes),
+					Arc::clone(&commands),
+					events.subscribe(),
+				));
+			}
+		}
+	});
+
+	MockImap {
+		port,
+		commands,
+		mailboxes,
+		events,
+	}
+}
+
+impl MockImap {
+	/// Returns all received commands that start with `prefix`
+	pub(super) fn commands_starting_with(&self, prefix: &str) -> Vec<String> {
+		self.commands
+			.lock()
+			.unwrap()
+			.iter()
+			.filter(|cmd| cmd.starts_with(prefix))
+			.cloned()
+			.collect()
+	}
+
+	/// Waits until a command that starts with `prefix` has been received
+	pub(super) async fn wait_for_command(&self, prefix: &str) {
+		while self.commands_starting_with(prefix).is_empty() {
+			tokio::time::sleep(Duration::from_millis(10)).await;
+		}
+	}
+
+	/// Adds the `mail` to the `mailbox` and notifies all connections that are idling in it
+	pub(super) fn deliver(&self, mailbox: &'static str, mail: (u32, String)) {
+		self.deliver_silently(mailbox, mail);
+		_ = self.events.send(Event::Delivered(mailbox));
+	}
+
+	/// Adds the `mail` to the `mailbox` without notifying anyone
+	pub(super) fn deliver_silently(&self, mailbox: &'static str, mail: (u32, String)) {
+		self.mailboxes
+			.lock()
+			.unwrap()
+			.iter_mut()
+			.find(|(name, _)| *name == mailbox)
+			.unwrap()
+			.1
+			.push(mail);
+	}
+
+	/// Closes all open connections
+	pub(super) fn drop_connections(&self) {
+		_ = self.events.send(Event::Dropped);
+	}
+}
+
+async fn handle(
+	stream: TcpStream,
+	capabilities: &'static str,
+	mailboxes: Arc<Mutex<Vec<Mailbox>>>,
+	commands: Arc<Mutex<Vec<String>>>,
+	mut events: broadcast::Receiver<Event>,
+) {
+	let (read, mut write) = stream.into_split();
+	let mut lines = BufReader::new(read).lines();
+
+	write
+		.write_all(format!("* OK [CAPABILITY {capabilities}] mock ready\r\n").as_bytes())
+		.await
+		.unwrap();
+
+	let mut selected: Option<&'static str> = None;
+
+	// tag of the IDLE command if currently idling
+	let mut idling: Option<String> = None;
+
+	loop {
+		let line = tokio::select! {
+			line = lines.next_line() => match line {
+				Ok(Some(line)) => line,
+				_ => return,
+			},
+			event = events.recv() => {
+				match event {
+					Ok(Event::Delivered(mailbox)) if idling.is_some() && selected == Some(mailbox) => {
+						let exists = mails_in(&mailboxes, mailbox).len();
+						if write.write_all(format!("* {exists} EXISTS\r\n").as_bytes()).await.is_err() {
+							return;
+						}
+					}
+					Ok(Event::Dropped) => return,
+					_ => (),
+				}
+
+				continue;
+			}
+		};
+
+		// wait for the rest of the command after each literal, keeping it after the line break
+		let mut line = line;
+		while line.ends_with('}') && line.contains('{') {
+			if write.write_all(b"+ ready for literal\r\n").await.is_err() {
+				return;
+			}
+
+			match lines.next_line().await {
+				Ok(Some(rest)) => line = format!("{line}\r\n{rest}"),
+				_ => return,
+			}
+		}
+
+		if line == "DONE" {
+			commands.lock().unwrap().push(line);
+
+			if let Some(tag) = idling.take()
+				&& write
+					.write_all(format!("{tag} OK idle done\r\n").as_bytes())
+					.await
+					.is_err()
+			{
+				return;
+			}
+
+			continue;
+		}
+
+		let (tag, cmd) = line.split_once(' ').unwrap_or((&line, ""));
+		commands.lock().unwrap().push(cmd.to_owned());
+
+		let response = respond(
+			tag,
+			cmd,
+			capabilities,
+			&mut selected,
+			&mut idling,
+			&mailboxes,
+		);
+		if write.write_all(response.as_bytes()).await.is_err() {
+			return;
+		}
+
+		if cmd.to_uppercase().starts_with("LOGOUT") {
+			return;
+		}
+	}
+}
+
+/// Executes the command and returns the response to it
+fn respond(
+	tag: &str,
+	cmd: &str,
+	capabilities: &str,
+	selected: &mut Option<&'static str>,
+	idling: &mut Option<String>,
+	mailboxes: &Mutex<Vec<Mailbox>>,
+) -> String {
+	let upper = cmd.to_uppercase();
+
+	if upper.starts_with("SELECT") || upper.starts_with("EXAMINE") {
+		let name = cmd
+			.split_once(' ')
+			.map(|(_, name)| name.trim_matches('"'))
+			.unwrap_or_default();
+
+		*selected = mailboxes
+			.lock()
+			.unwrap()
+			.iter()
+			.find(|(mailbox, _)| *mailbox == name)
+			.map(|(mailbox, _)| *mailbox);
+
+		match *selected {
+			Some(mailbox) => {
+				let mails = mails_in(mailboxes, mailbox);
+				let uid_next = mails.iter().map(|(uid, _)| uid + 1).max().unwrap_or(1);
+
+				format!(
+					"* {} EXISTS\r\n* OK [UIDNEXT {uid_next}] next uid\r\n{tag} OK selected\r\n",
+					mails.len()
+				)
+			}
+			None => format!("{tag} NO no such mailbox\r\n"),
+		}
+	} else if upper.starts_with("CAPABILITY") {
+		format!("* CAPABILITY {capabilities}\r\n{tag} OK done\r\n")
+	} else if upper.starts_with("IDLE") {
+		*idling = Some(tag.to_owned());
+		"+ idling\r\n".to_owned()
+	} else if upper.starts_with("UID SEARCH") {
+		let uids = selected
+			.map(|mailbox| mails_in(mailboxes, mailbox))
+			.unwrap_or_default()
+			.iter()
+			.map(|(uid, _)| uid.to_string())
+			.collect::<Vec<_>>()
+			.join(" ");
+
+		format!("* SEARCH {uids}\r\n{tag} OK done\r\n")
+	} else if upper.starts_with("UID FETCH") {
+		let set = cmd.split(' ').nth(2).unwrap_or_default();
+		let requested = set.split(',').filter_map(|uid| uid.parse::<u32>().ok());
+		let mails = selected
+			.map(|mailbox| mails_in(mailboxes, mailbox))
+			.unwrap_or_default();
+
+		let mut response = String::new();
+		for uid in requested {
+			let Some((seq, (_, body))) = mails
+				.iter()
+				.enumerate()
+				.find(|(_, (mail_uid, _))| *mail_uid == uid)
+			else {
+				continue;
+			};
+
+			_ = write!(
+				response,
+				"* {} FETCH (UID {uid} BODY[] {{{}}}\r\n{body})\r\n",
+				seq + 1,
+				body.len()
+			);
+		}
+
+		_ = write!(response, "{tag} OK done\r\n");
+		response
+	} else if upper.starts_with("LOGOUT") {
+		format!("* BYE\r\n{tag} OK bye\r\n")
+	} else {
+		format!("{tag} OK done\r\n")
+	}
+}
+
+/// Returns a copy of all mails in the `mailbox`
+fn mails_in(mailboxes: &Mutex<Vec<Mailbox>>, mailbox: &str) -> Vec<(u32, String)> {
+	mailboxes
+		.lock()
+		.unwrap()
+		.iter()
+		.find(|(name, _)| *name == mailbox)
+		.map(|(_, mails)| mails.clone())
+		.unwrap_or_default()
+}
@@ -0,0 +1,239 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+
+//! A typed builder of IMAP `SEARCH` queries, see RFC 3501, section 6.4.4
+
+use async_imap::{
+	Session,
+	error::{Error as ImapError, Result as ImapResult},
+	imap_proto::{MailboxDatum, Response, Status},
+	types::Uid,
+};
+use chrono::NaiveDate;
+use std::{
+	collections::HashSet,
+	fmt::{self, Display},
+};
+
+use super::{connection::MaybeTlsStream, quote};
+use crate::StaticStr;
+
+/// A single search key. All keys of a [`SearchQuery`] must match
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub(super) enum SearchKey {
+	All,
+	Unseen,
+	From(StaticStr),
+	To(StaticStr),
+	Cc(StaticStr),
+	Subject(StaticStr),
+	Body(StaticStr),
+	Header(StaticStr, StaticStr),
+	SentSince(NaiveDate),
+	SentBefore(NaiveDate),
+	Not(Box<SearchKey>),
+	Or(Box<SearchKey>, Box<SearchKey>),
+}
+
+/// A list of search keys that all must match
+#[derive(Default, Debug)]
+pub(super) struct SearchQuery {
+	keys: Vec<SearchKey>,
+}
+
+impl SearchKey {
+	/// Matches if any of the `keys` matches. Returns `None` if there are none
+	pub(super) fn any(keys: impl IntoIterator<Item = Self>) -> Option<Self> {
+		let mut keys = keys.into_iter().collect::<Vec<_>>();
+		let last = keys.pop()?;
+
+		// IMAP OR takes exactly two keys, so nest them: OR a (OR b c)
+		Some(
+			keys.into_iter()
+				.rev()
+				.fold(last, |acc, key| Self::Or(Box::new(key), Box::new(acc))),
+		)
+	}
+
+	fn is_ascii(&self) -> bool {
+		match self {
+			Self::All | Self::Unseen | Self::SentSince(_) | Self::SentBefore(_) => true,
+			Self::From(s) | Self::To(s) | Self::Cc(s) | Self::Subject(s) | Self::Body(s) => {
+				s.is_ascii()
+			}
+			Self::Header(name, value) => name.is_ascii() && value.is_ascii(),
+			Self::Not(key) => key.is_ascii(),
+			Self::Or(a, b) => a.is_ascii() && b.is_ascii(),
+		}
+	}
+}
+
+impl SearchQuery {
+	/// Adds a key that must match
+	pub(super) fn and(&mut self, key: SearchKey) -> &mut Self {
+		self.keys.push(key);
+		self
+	}
+}
+
+impl Display for SearchKey {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		// dates look like 1-Feb-1994
+		const DATE_FORMAT: &str = "%-d-%b-%Y";
+
+		match self {
+			Self::All => f.write_str("ALL"),
+			Self::Unseen => f.write_str("UNSEEN"),
+			Self::From(s) => write!(f, "FROM {}", string(s)),
+			Self::To(s) => write!(f, "TO {}", string(s)),
+			Self::Cc(s) => write!(f, "CC {}", string(s)),
+			Self::Subject(s) => write!(f, "SUBJECT {}", string(s)),
+			Self::Body(s) => write!(f, "BODY {}", string(s)),
+			Self::Header(name, value) => write!(f, "HEADER {} {}", string(name), string(value)),
+			Self::SentSince(date) => write!(f, "SENTSINCE {}", date.format(DATE_FORMAT)),
+			Self::SentBefore(date) => write!(f, "SENTBEFORE {}", date.format(DATE_FORMAT)),
+			Self::Not(key) => write!(f, "NOT {key}"),
+			Self::Or(a, b) => write!(f, "OR ({a}) ({b})"),
+		}
+	}
+}
+
+impl Display for SearchQuery {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		// non-ASCII strings are sent as literals in this charset
+		if !self.keys.iter().all(SearchKey::is_ascii) {
+			f.write_str("CHARSET UTF-8 ")?;
+		}
+
+		if self.keys.is_empty() {
+			return SearchKey::All.fmt(f);
+		}
+
+		for (i, key) in self.keys.iter().enumerate() {
+			if i > 0 {
+				f.write_str(" ")?;
+			}
+
+			key.fmt(f)?;
+		}
+
+		Ok(())
+	}
+}
+
+/// Formats the string as a quoted string if it's ASCII, or as a literal otherwise since quoted strings can't contain 8-bit characters.
+///
+/// Line breaks are replaced with spaces in both cases
+fn string(s: &str) -> String {
+	if s.is_ascii() {
+		return quote(s);
+	}
+
+	let s = s.replace(['\r', '\n'], " ");
+	format!("{{{}}}\r\n{s}", s.len())
+}
+
+/// Runs `UID SEARCH` with the `query`, sending each literal it contains once the server is ready for it
+pub(super) async fn uid_search(
+	session: &mut Session<MaybeTlsStream>,
+	query: &str,
+) -> ImapResult<HashSet<Uid>> {
+	// line breaks only ever end the length of a literal since they are replaced in the strings themselves
+	let mut parts = query.split("\r\n");
+	let first = parts.next().unwrap_or_default();
+
+	let tag = session.run_command(format!("UID SEARCH {first}")).await?;
+
+	for part in parts {
+		wait_for_continuation(session).await?;
+		session.run_command_untagged(part).await?;
+	}
+
+	let mut uids = HashSet::new();
+	while let Some(response) = session.read_response().await {
+		let response = response?;
+
+		match response.parsed() {
+			Response::MailboxData(MailboxDatum::Search(found)) => uids.extend(found),
+			Response::Done {
+				tag: done_tag,
+				status,
+				information,
+				..
+			} if *done_tag == tag => {
+				let information = information.as_deref().unwrap_or_default().to_owned();
+
+				return match status {
+					Status::Ok => Ok(uids),
+					Status::No => Err(ImapError::No(information)),
+					_ => Err(ImapError::Bad(information)),
+				};
+			}
+			_ => (),
+		}
+	}
+
+	Err(ImapError::ConnectionLost)
+}
+
+/// Waits for the server to ask for the rest of the command after a literal's length
+async fn wait_for_continuation(session: &mut Session<MaybeTlsStream>) -> ImapResult<()> {
+	let response = session
+		.read_response()
+		.await
+		.ok_or(ImapError::ConnectionLost)??;
+
+	match response.parsed() {
+		Response::Continue { .. } => Ok(()),
+		Response::Done { information, .. } => Err(ImapError::Bad(
+			information.as_deref().unwrap_or_default().to_owned(),
+		)),
+		other => Err(ImapError::Bad(format!(
+			"expected a continuation request, got {other:?}"
+		))),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn formats_and_escapes() {
+		let mut query = SearchQuery::default();
+		query
+			.and(SearchKey::Unseen)
+			.and(
+				SearchKey::any(
+					["a@example.com", "b@example.com", "c@example.com"]
+						.map(|sender| SearchKey::From(sender.into())),
+				)
+				.unwrap(),
+			)
+			.and(SearchKey::Subject(r#"say "hi" \o/"#.into()))
+			.and(SearchKey::Not(Box::new(SearchKey::Body("spam".into()))))
+			.and(SearchKey::SentSince(
+				NaiveDate::from_ymd_opt(2025, 3, 7).unwrap(),
+			));
+
+		assert_eq!(
+			query.to_string(),
+			r#"UNSEEN OR (FROM "a@example.com") (OR (FROM "b@example.com") (FROM "c@example.com")) SUBJECT "say \"hi\" \\o/" NOT BODY "spam" SENTSINCE 7-Mar-2025"#
+		);
+	}
+
+	#[test]
+	fn empty_and_utf8() {
+		assert_eq!(SearchQuery::default().to_string(), "ALL");
+
+		let mut query = SearchQuery::default();
+		query.and(SearchKey::Header("X-Tag".into(), "привет".into()));
+		assert_eq!(
+			query.to_string(),
+			"CHARSET UTF-8 HEADER \"X-Tag\" {12}\r\nпривет"
+		);
+	}
+}
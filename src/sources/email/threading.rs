@@ -0,0 +1,103 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+
+//! This module contains the parsing of the headers used to thread emails,
+//! i.e. `Message-ID`, `In-Reply-To`, and `References`
+
+use mailparse::{MailHeaderMap, ParsedMail};
+
+use crate::entry::EntryId;
+
+/// Returns the `Message-ID` of the email
+pub(super) fn message_id(mail: &ParsedMail<'_>) -> Option<EntryId> {
+	let header = mail.headers.get_first_value("Message-ID")?;
+	msg_ids(&header).next().and_then(EntryId::new)
+}
+
+/// Returns the `Message-ID` of the email this email is replying to.
+///
+/// That's the `In-Reply-To` header or the last message ID in the `References` header if the former is missing
+pub(super) fn parent_id(mail: &ParsedMail<'_>) -> Option<EntryId> {
+	if let Some(in_reply_to) = mail.headers.get_first_value("In-Reply-To")
+		&& let Some(parent) = msg_ids(&in_reply_to).next()
+	{
+		return EntryId::new(parent);
+	}
+
+	let references = mail.headers.get_first_value("References")?;
+	msg_ids(&references).last().and_then(EntryId::new)
+}
+
+/// Checks if the entry ID is a `Message-ID` rather than a UID
+pub(super) fn is_message_id(id: &EntryId) -> bool {
+	id.as_str().starts_with('<')
+}
+
+/// Splits a list of message IDs, e.g. `<a@example.com> <b@example.com>`, ignoring everything outside of angle brackets
+fn msg_ids(header: &str) -> impl Iterator<Item = String> {
+	header.split('<').skip(1).filter_map(|s| {
+		let (id, _) = s.split_once('>')?;
+		let id = id.trim();
+
+		(!id.is_empty()).then(|| format!("<{id}>"))
+	})
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn mail(headers: &str) -> Vec<u8> {
+		format!("{headers}Subject: Re: Hi\r\n\r\nbody\r\n").into_bytes()
+	}
+
+	fn ids(mail: &[u8]) -> (Option<String>, Option<String>) {
+		let mail = mailparse::parse_mail(mail).unwrap();
+
+		(
+			message_id(&mail).map(|id| id.as_str().to_owned()),
+			parent_id(&mail).map(|id| id.as_str().to_owned()),
+		)
+	}
+
+	#[test]
+	fn in_reply_to_is_preferred() {
+		let mail = mail(concat!(
+			"Message-ID: <3@example.com>\r\n",
+			"In-Reply-To: <2@example.com>\r\n",
+			"References: <1@example.com>\r\n <2@example.com>\r\n",
+		));
+
+		assert_eq!(
+			ids(&mail),
+			(
+				Some("<3@example.com>".to_owned()),
+				Some("<2@example.com>".to_owned())
+			)
+		);
+	}
+
+	#[test]
+	fn last_reference_is_the_parent() {
+		let mail = mail(concat!(
+			"Message-ID: <3@example.com> (comment)\r\n",
+			"References: <1@example.com> <2@example.com>\r\n",
+		));
+
+		assert_eq!(
+			ids(&mail),
+			(
+				Some("<3@example.com>".to_owned()),
+				Some("<2@example.com>".to_owned())
+			)
+		);
+	}
+
+	#[test]
+	fn missing_headers() {
+		assert_eq!(ids(&mail("")), (None, None));
+	}
+}
@@ -4,6 +4,8 @@
  * file, You can obtain one at https://mozilla.org/MPL/2.0/.
  */
 
+use crate::StaticStr;
+
 /// A view mode for the IMAP connection
 #[derive(Debug)]
 pub enum ViewMode {
@@ -14,4 +16,9 @@ pub enum ViewMode {
 	/// Delete the read ones
 	/// In Gmail this normally marks them as archived, unless changed in the settings
 	Delete,
+	/// Move the read ones to this mailbox, e.g. `Processed`.
+	/// Uses the `MOVE` command if the server supports it and copies the emails and deletes the originals otherwise
+	MoveTo(StaticStr),
+	/// Add this Gmail label to the read ones and mark them as read. Only supported by Gmail
+	Label(StaticStr),
 }
@@ -10,6 +10,7 @@ pub use crate::exec::ExecError;
 
 use crate::{
 	error::{Error, error_trait::BoxErrorWrapper},
+	external_save::ExternalSaveError,
 	read_filter::mark_as_read::MarkAsReadError,
 };
 
@@ -19,10 +20,22 @@ use super::http::HttpError;
 #[cfg(feature = "source-email")]
 use super::email::{EmailError, ImapError};
 
+#[cfg(feature = "source-gmail")]
+use super::email::GmailError;
+
+#[cfg(feature = "source-calendar")]
+use super::calendar::CalendarError;
+
+#[cfg(feature = "source-sqlite")]
+use super::sqlite::SqliteError;
+
+#[cfg(feature = "record-replay")]
+use super::record_replay::FixtureError;
+
 #[cfg(feature = "source-reddit")]
 use {super::reddit::RedditError, roux::util::RouxError};
 
-use std::{convert::Infallible, error::Error as StdError, path::PathBuf};
+use std::{convert::Infallible, error::Error as StdError, path::PathBuf, time::Duration};
 
 #[expect(missing_docs, reason = "error message is self-documenting")]
 #[derive(thiserror::Error, Debug)]
@@ -33,6 +46,9 @@ pub enum SourceError {
 	#[error("Can't read file {}", .1.to_string_lossy())]
 	File(#[source] std::io::Error, PathBuf),
 
+	#[error("Failed to save the state of the source")]
+	ExternalSave(#[from] ExternalSaveError),
+
 	#[error("Exec error")]
 	Exec(#[from] ExecError),
 
@@ -44,10 +60,26 @@ pub enum SourceError {
 	#[error("Email error")]
 	Email(#[from] Box<EmailError>),
 
+	#[cfg(feature = "source-gmail")]
+	#[error("Gmail error")]
+	Gmail(#[from] GmailError),
+
 	#[cfg(feature = "source-reddit")]
 	#[error("Reddit error")]
 	Reddit(#[from] RedditError),
 
+	#[cfg(feature = "source-calendar")]
+	#[error("Google Calendar error")]
+	Calendar(#[from] CalendarError),
+
+	#[cfg(feature = "source-sqlite")]
+	#[error("SQLite error")]
+	Sqlite(#[from] SqliteError),
+
+	#[cfg(feature = "record-replay")]
+	#[error("Failed to record or replay a fixture")]
+	Fixture(#[from] FixtureError),
+
 	#[error(transparent)]
 	Other(#[from] Box<dyn Error>),
 }
@@ -58,18 +90,35 @@ impl Error for SourceError {
 		match self {
 			Self::MarkAsRead(e) if e.is_network_related().is_some() => Some(self),
 			#[cfg(feature = "source-http")]
-			Self::Http(_) => Some(self),
+			Self::Http(e) if e.is_network_related().is_some() => Some(self),
 			#[cfg(feature = "source-email")]
 			Self::Email(email_err) => match &**email_err {
 				EmailError::Imap(ImapError::ConnectionFailed(_)) => Some(self),
 				_ => None,
 			},
+			#[cfg(feature = "source-gmail")]
+			Self::Gmail(e) if e.is_network_related().is_some() => Some(self),
 			#[cfg(feature = "source-reddit")]
 			Self::Reddit(RedditError::Reddit(RouxError::Network(_))) => Some(self),
+			#[cfg(feature = "source-calendar")]
+			Self::Calendar(e) if e.is_network_related().is_some() => Some(self),
 			Self::Other(other_err) if other_err.is_network_related().is_some() => Some(self),
 			_ => None,
 		}
 	}
+
+	fn retry_after(&self) -> Option<Duration> {
+		match self {
+			#[cfg(feature = "source-http")]
+			Self::Http(e) => e.retry_after(),
+			#[cfg(feature = "source-gmail")]
+			Self::Gmail(e) => e.retry_after(),
+			#[cfg(feature = "source-calendar")]
+			Self::Calendar(e) => e.retry_after(),
+			Self::Other(e) => e.retry_after(),
+			_ => None,
+		}
+	}
 }
 
 #[cfg(feature = "source-email")]
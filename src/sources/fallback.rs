@@ -0,0 +1,405 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+
+//! This module contains the [`Fallback`] source wrapper
+
+use futures::{Stream, StreamExt, future, stream};
+use std::sync::{Arc, Mutex, PoisonError};
+
+use super::{Fetch, Source, error::SourceError};
+use crate::{
+	entry::{Entry, EntryId},
+	error::ErrorChainDisplay,
+	maybe_send::{MaybeSend, MaybeSendSync},
+	read_filter::MarkAsRead,
+};
+
+/// A batch of entries or an error along with the number of the source it came from
+type NumberedBatch = (usize, Result<Vec<Entry>, SourceError>);
+
+/// Tries to fetch from each of the sources in order until one of them succeeds.
+///
+/// Useful for feeds that have mirrors, or for a JSON API with an HTML page as a fallback.
+/// The sources can be either a tuple of different [`Fetch`] implementations or a [`Vec`] of the same one.
+/// As all sources are supposed to provide the same data, they share a single read filter that [`MarkAsRead`] calls are forwarded to.
+///
+/// If all sources fail, the error of the last one is returned.
+///
+/// If all sources support [fetching in batches](`Fetch::fetch_batches`), so does this wrapper.
+/// The next source is only tried if the previous one fails before returning a single batch,
+/// after that its errors are returned as is
+///
+/// ```
+/// # use fetcher::{sources::{Fetch, Fallback}, read_filter::NotPresent};
+/// # async fn f() {
+/// let mut source = Fallback::new(
+///     vec!["primary".to_owned(), "mirror".to_owned()],
+///     NotPresent::new(),
+/// );
+/// source.fetch().await.unwrap();
+/// assert_eq!(source.served_by(), Some(0));
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct Fallback<T, RF> {
+	/// The sources to try, in order
+	pub sources: T,
+
+	/// The read filter shared by all sources
+	pub rf: RF,
+
+	served_by: Arc<Mutex<Option<usize>>>,
+}
+
+impl<T, RF> Fallback<T, RF> {
+	/// Creates a new [`Fallback`] that tries the `sources` in order and marks entries as read using the `rf` read filter
+	pub fn new(sources: T, rf: RF) -> Self {
+		Self {
+			sources,
+			rf,
+			served_by: Arc::new(Mutex::new(None)),
+		}
+	}
+
+	/// Returns the index of the source that served the entries during the last fetch,
+	/// or `None` if nothing has been fetched yet or all sources have failed
+	#[must_use]
+	pub fn served_by(&self) -> Option<usize> {
+		*self
+			.served_by
+			.lock()
+			.unwrap_or_else(PoisonError::into_inner)
+	}
+}
+
+/// Records which source has succeeded, if any
+fn set_served_by(served_by: &Mutex<Option<usize>>, source_num: Option<usize>) {
+	match source_num {
+		Some(0) => tracing::trace!("Fetched from the primary source"),
+		Some(source_num) => tracing::info!("Fetched from the fallback source #{source_num}"),
+		None => tracing::warn!("All sources have failed"),
+	}
+
+	*served_by.lock().expect("poisoned") = source_num;
+}
+
+/// Logs the error of the source that failed
+fn log_failure(source_num: usize, e: &SourceError) {
+	tracing::warn!(
+		"Source #{source_num} failed, trying the next one: {}",
+		ErrorChainDisplay(e)
+	);
+}
+
+/// Returns the batches of the first of the `sources` that doesn't fail before returning a single batch
+fn first_successful<S>(sources: Vec<S>) -> impl Stream<Item = NumberedBatch> + MaybeSend
+where
+	S: Stream<Item = NumberedBatch> + MaybeSend,
+{
+	stream::once(async move {
+		let mut sources = sources.into_iter().peekable();
+
+		while let Some(source) = sources.next() {
+			let mut source = Box::pin(source);
+
+			match source.next().await {
+				Some((source_num, Err(e))) if sources.peek().is_some() => {
+					log_failure(source_num, &e);
+				}
+				first => return Some(stream::iter(first).chain(source)),
+			}
+		}
+
+		None
+	})
+	.filter_map(future::ready)
+	.flatten()
+}
+
+/// Returns the batches of the `primary` source, or of the `fallback` if the primary one fails before returning a single batch
+fn or_fallback<P, F>(primary: P, fallback: F) -> impl Stream<Item = NumberedBatch> + MaybeSend
+where
+	P: Stream<Item = NumberedBatch> + MaybeSend,
+	F: Stream<Item = NumberedBatch> + MaybeSend,
+{
+	stream::once(async move {
+		let mut primary = Box::pin(primary);
+
+		match primary.next().await {
+			Some((source_num, Err(e))) => {
+				log_failure(source_num, &e);
+				fallback.left_stream()
+			}
+			first => stream::iter(first).chain(primary).right_stream(),
+		}
+	})
+	.flatten()
+}
+
+/// Records which source the batches have come from to `served_by`
+fn record_served_by<S>(
+	batches: S,
+	served_by: Arc<Mutex<Option<usize>>>,
+) -> impl Stream<Item = Result<Vec<Entry>, SourceError>> + MaybeSend
+where
+	S: Stream<Item = NumberedBatch> + MaybeSend,
+{
+	*served_by.lock().expect("poisoned") = None;
+
+	batches.then(move |(source_num, res)| {
+		let is_new_source = *served_by.lock().expect("poisoned") != Some(source_num);
+		if res.is_ok() && is_new_source {
+			set_served_by(&served_by, Some(source_num));
+		}
+
+		future::ready(res)
+	})
+}
+
+/// Expands into nested [`or_fallback`] calls for all the streams
+macro_rules! or_fallback {
+	($last:ident) => {
+		$last
+	};
+	($first:ident $($rest:ident)+) => {
+		or_fallback($first, or_fallback!($($rest)+))
+	};
+}
+
+impl<F, RF> Fetch for Fallback<Vec<F>, RF>
+where
+	F: Fetch,
+	RF: MaybeSendSync,
+{
+	type Err = SourceError;
+
+	async fn fetch(&mut self) -> Result<Vec<Entry>, Self::Err> {
+		let mut last_err = None;
+
+		for (source_num, source) in self.sources.iter_mut().enumerate() {
+			match source.fetch().await.map_err(Into::into) {
+				Ok(entries) => {
+					set_served_by(&self.served_by, Some(source_num));
+					return Ok(entries);
+				}
+				Err(e) => {
+					log_failure(source_num, &e);
+					last_err = Some(e);
+				}
+			}
+		}
+
+		set_served_by(&self.served_by, None);
+
+		match last_err {
+			Some(e) => Err(e),
+			// an empty list of sources doesn't fail, it just doesn't have anything to fetch
+			None => Ok(Vec::new()),
+		}
+	}
+
+	fn fetch_batches(
+		&mut self,
+	) -> Option<impl Stream<Item = Result<Vec<Entry>, Self::Err>> + MaybeSend + use<F, RF>> {
+		let sources = self
+			.sources
+			.iter_mut()
+			.enumerate()
+			.map(|(source_num, source)| {
+				let batches = source.fetch_batches()?;
+				Some(batches.map(move |res| (source_num, res.map_err(Into::into))))
+			})
+			.collect::<Option<Vec<_>>>()?;
+
+		Some(record_served_by(
+			first_successful(sources),
+			Arc::clone(&self.served_by),
+		))
+	}
+}
+
+macro_rules! impl_fetch_for_fallback_tuples {
+	($($type_name:ident)+) => {
+		impl<$($type_name),+, RF> Fetch for Fallback<($($type_name,)+), RF>
+		where
+			$($type_name: Fetch),+,
+			RF: MaybeSendSync,
+		{
+			type Err = SourceError;
+
+			#[expect(non_snake_case, reason = "it's fine to re-use the names to make calling the macro easier")]
+			async fn fetch(&mut self) -> Result<Vec<Entry>, Self::Err> {
+				let mut source_num = 0;
+				let mut errors = Vec::new();
+				let ($($type_name,)+) = &mut self.sources;
+				$(
+					match $type_name.fetch().await.map_err(Into::<SourceError>::into) {
+						Ok(entries) => {
+							set_served_by(&self.served_by, Some(source_num));
+							return Ok(entries);
+						}
+						Err(e) => {
+							log_failure(source_num, &e);
+							errors.push(e);
+						}
+					}
+
+					#[allow(unused_assignments, reason = "last iteration won't use it, it's fine")]
+					{
+						source_num += 1;
+					}
+				)+
+
+				set_served_by(&self.served_by, None);
+				Err(errors.pop().expect("all sources should have failed by now"))
+			}
+
+			#[expect(non_snake_case, reason = "it's fine to re-use the names to make calling the macro easier")]
+			fn fetch_batches(
+				&mut self,
+			) -> Option<impl Stream<Item = Result<Vec<Entry>, Self::Err>> + MaybeSend + use<$($type_name),+, RF>> {
+				let mut source_num = 0;
+				let ($($type_name,)+) = &mut self.sources;
+				$(
+					let num = source_num;
+					let $type_name = $type_name
+						.fetch_batches()?
+						.map(move |res| (num, res.map_err(Into::<SourceError>::into)));
+
+					#[allow(unused_assignments, reason = "last iteration won't use it, it's fine")]
+					{
+						source_num += 1;
+					}
+				)+
+
+				Some(record_served_by(
+					or_fallback!($($type_name)+),
+					Arc::clone(&self.served_by),
+				))
+			}
+		}
+	}
+}
+
+impl_fetch_for_fallback_tuples!(A1);
+impl_fetch_for_fallback_tuples!(A1 A2);
+impl_fetch_for_fallback_tuples!(A1 A2 A3);
+impl_fetch_for_fallback_tuples!(A1 A2 A3 A4);
+impl_fetch_for_fallback_tuples!(A1 A2 A3 A4 A5);
+impl_fetch_for_fallback_tuples!(A1 A2 A3 A4 A5 A6);
+impl_fetch_for_fallback_tuples!(A1 A2 A3 A4 A5 A6 A7);
+impl_fetch_for_fallback_tuples!(A1 A2 A3 A4 A5 A6 A7 A8);
+impl_fetch_for_fallback_tuples!(A1 A2 A3 A4 A5 A6 A7 A8 A9);
+impl_fetch_for_fallback_tuples!(A1 A2 A3 A4 A5 A6 A7 A8 A9 A10);
+impl_fetch_for_fallback_tuples!(A1 A2 A3 A4 A5 A6 A7 A8 A9 A10 A11);
+impl_fetch_for_fallback_tuples!(A1 A2 A3 A4 A5 A6 A7 A8 A9 A10 A11 A12);
+
+impl<T, RF> MarkAsRead for Fallback<T, RF>
+where
+	T: MaybeSendSync,
+	RF: MarkAsRead,
+{
+	type Err = RF::Err;
+
+	async fn mark_as_read(&mut self, id: &EntryId) -> Result<(), Self::Err> {
+		self.rf.mark_as_read(id).await
+	}
+
+	#[expect(
+		clippy::semicolon_if_nothing_returned,
+		reason = "just forwards the method call, should return the same value"
+	)]
+	async fn set_read_only(&mut self) {
+		self.rf.set_read_only().await
+	}
+}
+
+impl<T, RF> Source for Fallback<T, RF>
+where
+	Self: Fetch + MarkAsRead,
+	T: MaybeSendSync,
+	RF: MarkAsRead,
+{
+}
+
+#[cfg(test)]
+mod tests {
+	use assert_matches::assert_matches;
+	use futures::TryStreamExt;
+	use std::{num::NonZeroUsize, time::Duration};
+
+	use super::*;
+	use crate::{
+		read_filter::NotPresent,
+		sources::FetchStream,
+		test_utils::{Entries, Failing, TestError, contents},
+	};
+
+	#[tokio::test]
+	async fn uses_first_successful_source() {
+		let mut source = Fallback::new(
+			(Failing(Duration::ZERO), "mirror".to_owned(), ()),
+			NotPresent::new(),
+		);
+
+		let entries = source.fetch().await.unwrap();
+		assert_eq!(entries.len(), 1);
+		assert_eq!(entries[0].raw_contents.as_deref(), Some("mirror"));
+		assert_eq!(source.served_by(), Some(1));
+
+		let mut source = Fallback::new(vec![Failing(Duration::ZERO), Failing(Duration::ZERO)], ());
+		assert_matches!(source.fetch().await, Err(SourceError::Other(_)));
+		assert_eq!(source.served_by(), None);
+	}
+
+	#[tokio::test]
+	async fn falls_back_to_batches_of_next_source() {
+		let mut source = Fallback::new(
+			(
+				Entries(vec![Err(TestError)]).batched(NonZeroUsize::MIN),
+				Entries(vec![Ok("mirror")]).batched(NonZeroUsize::MIN),
+			),
+			(),
+		);
+
+		let batches = source
+			.fetch_batches()
+			.unwrap()
+			.try_collect::<Vec<_>>()
+			.await
+			.unwrap();
+		assert_eq!(contents(&batches.concat()), ["mirror"]);
+		assert_eq!(source.served_by(), Some(1));
+
+		let mut source = Fallback::new(
+			vec![
+				Entries(vec![Ok("primary"), Err(TestError)]).batched(NonZeroUsize::MIN),
+				Entries(vec![Ok("mirror")]).batched(NonZeroUsize::MIN),
+			],
+			(),
+		);
+
+		let batches = source.fetch_batches().unwrap().collect::<Vec<_>>().await;
+		assert_matches!(
+			&*batches,
+			[Ok(_), Err(SourceError::Other(_))],
+			"shouldn't fall back after the first batch"
+		);
+		assert_eq!(source.served_by(), Some(0));
+	}
+
+	#[tokio::test]
+	async fn marks_as_read_in_shared_read_filter() {
+		let mut source = Fallback::new(
+			(Failing(Duration::ZERO), "mirror".to_owned()),
+			NotPresent::new(),
+		);
+		let id = EntryId::try_from("id").unwrap();
+
+		source.mark_as_read(&id).await.unwrap();
+		assert!(!source.rf.is_unread(&id));
+	}
+}
@@ -6,7 +6,11 @@
 
 //! Local file source
 //!
-//! This module contains [`File`] source
+//! This module contains [`File`] source and its [`Tail`] mode
+
+mod tail;
+
+pub use self::tail::{Tail, TailState};
 
 use std::path::PathBuf;
 use tokio::fs;
@@ -15,6 +19,8 @@ use super::{Fetch, error::SourceError};
 use crate::entry::Entry;
 
 /// File source. Reads contents of a file and puts them into [`raw_contents`](`crate::entry::Entry::raw_contents`)
+///
+/// Use [`File::tail`] to only get the lines appended to the file since the last fetch instead
 #[derive(Debug)]
 pub struct File {
 	/// Path of the file
@@ -0,0 +1,288 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+
+//! This module contains the [`Tail`] mode of the [`File`] source
+
+use serde::{Deserialize, Serialize};
+use std::{convert::Infallible, fs::Metadata, io::SeekFrom, path::PathBuf};
+use tokio::{
+	fs,
+	io::{AsyncReadExt, AsyncSeekExt},
+};
+
+use super::File;
+use crate::{
+	entry::Entry,
+	external_save::ExternalSave,
+	sources::{Fetch, error::SourceError},
+};
+
+/// Tail mode of the [`File`] source. Emits an [`Entry`] per line appended to the file since the last fetch, like `tail -f`.
+///
+/// The line is put into [`raw_contents`](`crate::entry::Entry::raw_contents`). Empty lines are skipped.
+/// A line is only emitted once it's complete, i.e. once the newline character has been written, too.
+///
+/// If the file gets smaller than the last read position, it's assumed to have been truncated and is read again from the start.
+/// If the file gets replaced with another one (i.e. its inode changes), e.g. by a log rotation, the new file is read from the start.
+/// Lines written to the old file after the last fetch but before the rotation are lost.
+pub struct Tail<E = Infallible> {
+	/// Path of the file
+	pub path: PathBuf,
+
+	/// `None` if the file hasn't been seen yet
+	state: Option<TailState>,
+	external_save: Option<E>,
+}
+
+/// Position in the file that has been read up to
+#[derive(Serialize, Deserialize, PartialEq, Eq, Clone, Default, Debug)]
+pub struct TailState {
+	/// Offset in bytes right after the last read line
+	pub offset: u64,
+
+	/// Inode of the file, used to detect when the file has been replaced. Always `None` on non-Unix platforms
+	pub inode: Option<u64>,
+}
+
+impl File {
+	/// Switches to the [`Tail`] mode, starting at the current end of the file.
+	///
+	/// The read position is only kept in memory and is thus lost when the program is restarted.
+	/// Use [`File::tail_with_external_save`] to preserve it across restarts.
+	#[must_use]
+	pub fn tail(self) -> Tail {
+		Tail {
+			path: self.path,
+			state: None,
+			external_save: None,
+		}
+	}
+
+	/// Switches to the [`Tail`] mode, starting at the previously saved `state`, or at the current end of the file if it's `None`.
+	///
+	/// [`ExternalSave::save_source_state`] is called with the path as the key and the new [`TailState`] every time new lines have been read.
+	///
+	/// # Note
+	/// The state is updated as soon as the lines are read.
+	/// If the rest of the pipeline fails afterwards, these lines won't be emitted again.
+	pub fn tail_with_external_save<E>(self, state: Option<TailState>, external_save: E) -> Tail<E>
+	where
+		E: ExternalSave,
+	{
+		Tail {
+			path: self.path,
+			state,
+			external_save: Some(external_save),
+		}
+	}
+}
+
+impl<E> Tail<E> {
+	/// Returns the position in the file that has been read up to, if the file has been seen yet
+	#[must_use]
+	pub fn state(&self) -> Option<&TailState> {
+		self.state.as_ref()
+	}
+}
+
+impl<E> Fetch for Tail<E>
+where
+	E: ExternalSave,
+{
+	type Err = SourceError;
+
+	/// Read the lines appended to the file since the last fetch, returning an entry per line
+	#[tracing::instrument(skip_all, fields(path = %self.path.display()))]
+	async fn fetch(&mut self) -> Result<Vec<Entry>, SourceError> {
+		let mut file = fs::File::open(&self.path)
+			.await
+			.map_err(|e| SourceError::File(e, self.path.clone()))?;
+
+		let metadata = file
+			.metadata()
+			.await
+			.map_err(|e| SourceError::File(e, self.path.clone()))?;
+
+		let inode = inode(&metadata);
+		let len = metadata.len();
+
+		let Some(state) = &self.state else {
+			tracing::debug!("Starting at the end of the file at {len}");
+			self.update_state(TailState { offset: len, inode }).await?;
+
+			return Ok(Vec::new());
+		};
+
+		let offset = if state.inode != inode {
+			tracing::debug!("File has been replaced, reading it from the start");
+			0
+		} else if len < state.offset {
+			tracing::debug!("File has been truncated, reading it from the start");
+			0
+		} else {
+			state.offset
+		};
+
+		let mut appended = Vec::new();
+		file.seek(SeekFrom::Start(offset))
+			.await
+			.map_err(|e| SourceError::File(e, self.path.clone()))?;
+		file.read_to_end(&mut appended)
+			.await
+			.map_err(|e| SourceError::File(e, self.path.clone()))?;
+
+		// leave the last incomplete line for the next fetch
+		let complete_len = appended
+			.iter()
+			.rposition(|&b| b == b'\n')
+			.map_or(0, |newline_pos| newline_pos + 1);
+		appended.truncate(complete_len);
+
+		let entries = appended
+			.split(|&b| b == b'\n')
+			.map(|line| String::from_utf8_lossy(line).trim_end().to_owned())
+			.filter(|line| !line.is_empty())
+			.map(|line| Entry::builder().raw_contents(line).build())
+			.collect::<Vec<_>>();
+
+		tracing::debug!("Read {} new lines", entries.len());
+
+		self.update_state(TailState {
+			offset: offset + complete_len as u64,
+			inode,
+		})
+		.await?;
+
+		Ok(entries)
+	}
+}
+
+impl<E> Tail<E>
+where
+	E: ExternalSave,
+{
+	/// Replaces the state and saves it externally if it has changed
+	async fn update_state(&mut self, state: TailState) -> Result<(), SourceError> {
+		if self.state.as_ref() == Some(&state) {
+			return Ok(());
+		}
+
+		tracing::trace!("Tail state changed to {state:?}");
+		let state = self.state.insert(state);
+
+		if let Some(external_save) = &mut self.external_save {
+			external_save
+				.save_source_state(&self.path.to_string_lossy(), state)
+				.await?;
+		}
+
+		Ok(())
+	}
+}
+
+#[cfg(unix)]
+#[expect(
+	clippy::unnecessary_wraps,
+	reason = "inodes aren't available on other platforms"
+)]
+fn inode(metadata: &Metadata) -> Option<u64> {
+	use std::os::unix::fs::MetadataExt;
+
+	Some(metadata.ino())
+}
+
+#[cfg(not(unix))]
+fn inode(_metadata: &Metadata) -> Option<u64> {
+	None
+}
+
+impl<E> std::fmt::Debug for Tail<E> {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		f.debug_struct("Tail")
+			.field("path", &self.path)
+			.field("state", &self.state)
+			.finish_non_exhaustive()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::test_utils::{SourceStates, TempDir};
+
+	async fn append(path: &PathBuf, text: &str) {
+		use tokio::io::AsyncWriteExt;
+
+		let mut file = fs::OpenOptions::new()
+			.append(true)
+			.create(true)
+			.open(path)
+			.await
+			.unwrap();
+		file.write_all(text.as_bytes()).await.unwrap();
+	}
+
+	async fn fetch_lines<E: ExternalSave>(tail: &mut Tail<E>) -> Vec<String> {
+		tail.fetch()
+			.await
+			.unwrap()
+			.into_iter()
+			.map(|entry| entry.raw_contents.unwrap())
+			.collect()
+	}
+
+	#[tokio::test]
+	async fn emits_only_appended_complete_lines() {
+		let dir = TempDir::new("tail-emits_only_appended_complete_lines");
+		let path = dir.path().join("log");
+		append(&path, "old line\n").await;
+
+		let mut tail = File { path: path.clone() }.tail();
+		assert!(fetch_lines(&mut tail).await.is_empty());
+
+		append(&path, "first\n\nsecond\nthi").await;
+		assert_eq!(fetch_lines(&mut tail).await, ["first", "second"]);
+
+		append(&path, "rd\n").await;
+		assert_eq!(fetch_lines(&mut tail).await, ["third"]);
+		assert!(fetch_lines(&mut tail).await.is_empty());
+	}
+
+	#[tokio::test]
+	async fn handles_truncation_and_rotation() {
+		let dir = TempDir::new("tail-handles_truncation_and_rotation");
+		let path = dir.path().join("log");
+		append(&path, "a long line that is going to be truncated\n").await;
+
+		let mut tail = File { path: path.clone() }.tail();
+		fetch_lines(&mut tail).await;
+
+		fs::write(&path, "truncated\n").await.unwrap();
+		assert_eq!(fetch_lines(&mut tail).await, ["truncated"]);
+
+		fs::rename(&path, dir.path().join("log.1")).await.unwrap();
+		append(&path, "rotated\n").await;
+		assert_eq!(fetch_lines(&mut tail).await, ["rotated"]);
+	}
+
+	#[tokio::test]
+	async fn state_is_saved_and_restored() {
+		let dir = TempDir::new("tail-state_is_saved_and_restored");
+		let path = dir.path().join("log");
+		let states = SourceStates::default();
+
+		let mut tail = File { path: path.clone() }.tail_with_external_save(None, states.clone());
+		append(&path, "before start\n").await;
+		fetch_lines(&mut tail).await;
+		drop(tail);
+
+		append(&path, "while stopped\n").await;
+
+		let saved = states.get(&path.to_string_lossy()).unwrap();
+		let mut tail = File { path: path.clone() }.tail_with_external_save(Some(saved), states);
+		assert_eq!(fetch_lines(&mut tail).await, ["while stopped"]);
+	}
+}
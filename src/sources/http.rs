@@ -6,32 +6,85 @@
 
 //! HTTP source
 //!
-//! This module contains the [`Http`] struct, that is a source as well as a transform
+//! This module contains the [`Http`] struct, that is a source as well as a transform,
+//! and the [`HttpClient`] it uses to send requests
 
-pub use reqwest;
+mod client;
+mod pagination;
+mod request;
 
-use crate::{entry::Entry, sinks::message::Message};
+#[cfg(test)]
+pub(crate) mod mock_server;
 
-use once_cell::sync::OnceCell;
-use reqwest::Client;
-use std::{convert::identity, fmt::Debug, time::Duration};
+pub use self::{
+	client::HttpClient,
+	pagination::{NextPage, Pagination, PaginationError, StopCondition, StopWhenRead},
+	request::{Auth, Body, Request},
+};
+pub use reqwest::{self, Method};
+
+use crate::{
+	entry::Entry,
+	error::Error,
+	external_save::{ExternalSave, ExternalSaveError},
+	safe_slice::SafeSliceUntilExt,
+	sinks::message::Message,
+};
+
+use chrono::{DateTime, Utc};
+use reqwest::{
+	RequestBuilder, Response, StatusCode,
+	header::{ETAG, HeaderMap, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED, RETRY_AFTER},
+};
+use serde::{Deserialize, Serialize};
+use std::{convert::Infallible, fmt::Debug, time::Duration};
 use url::Url;
 
 use super::Fetch;
 
-const USER_AGENT: &str =
-	"Mozilla/5.0 (Windows NT 10.0; Win64; x64; rv:96.0) Gecko/20100101 Firefox/96.0";
-
-pub(crate) static CLIENT: OnceCell<reqwest::Client> = OnceCell::new();
+/// Max length of the response body kept in [`HttpError::UnsuccessfulStatus`]
+const BODY_SNIPPET_LEN: usize = 512;
 
 pub use serde_json::Value as Json;
 
 /// A source that fetches from the [`URL`](`url`)
-pub struct Http {
+///
+/// If conditional requests are enabled (see [`Http::with_conditional_get`]),
+/// the `ETag` and `Last-Modified` headers of the last response are remembered
+/// and sent back in the `If-None-Match` and `If-Modified-Since` headers.
+/// If the server responds with `304 Not Modified`, no entries are returned.
+///
+/// If pagination is enabled (see [`Http::with_pagination`]),
+/// the next pages are followed as well and each page becomes its own entry.
+pub struct Http<E = Infallible, S = ()> {
 	/// The URL to fetch from
 	pub url: Url,
 	request: Request,
-	client: reqwest::Client,
+	client: HttpClient,
+
+	/// `None` if conditional requests are disabled
+	conditional: Option<ConditionalGet<E>>,
+
+	/// `None` if pagination is disabled
+	pagination: Option<Pagination<S>>,
+}
+
+/// Cache validators of the last response.
+///
+/// They are sent back to the server to only get the page if it has changed since the last fetch.
+#[derive(Serialize, Deserialize, PartialEq, Eq, Clone, Default, Debug)]
+pub struct Validators {
+	/// Value of the `ETag` header of the last response
+	pub etag: Option<String>,
+
+	/// Value of the `Last-Modified` header of the last response
+	pub last_modified: Option<String>,
+}
+
+#[derive(Debug)]
+struct ConditionalGet<E> {
+	validators: Validators,
+	external_save: Option<E>,
 }
 
 #[expect(missing_docs, reason = "error message is self-documenting")]
@@ -46,30 +99,45 @@ pub enum HttpError {
 	#[error("Can't send an HTTP request to {1:?}")]
 	BadRequest(#[source] reqwest::Error, String),
 
+	#[error("{url:?} responded with HTTP status {status}: {body:?}")]
+	UnsuccessfulStatus {
+		/// Status code of the response
+		status: StatusCode,
+
+		/// The beginning of the response body
+		body: String,
+
+		/// Value of the `Retry-After` header, if present
+		retry_after: Option<Duration>,
+
+		/// The URL the request was sent to
+		url: String,
+	},
+
 	#[error("Not a valid URL")]
 	InvalidUrl(#[from] url::ParseError),
-}
 
-/// HTTP request type
-#[derive(Debug)]
-pub enum Request {
-	/// HTTP GET request
-	Get,
+	#[error("Failed to save cache validators")]
+	ExternalSave(#[from] ExternalSaveError),
 
-	/// HTTP POST request, contaning a JSON payload
-	Post(Json),
+	#[error("Failed to follow the pages")]
+	Pagination(#[from] PaginationError),
 }
 
 impl Http {
-	/// Create a new HTTP client that sends GET requests
+	/// Create a new HTTP source that sends GET requests using the [default client](`HttpClient::shared_default`)
 	///
 	/// # Errors
 	/// This method fails if TLS couldn't be initialized
 	pub fn new_get(url: impl TryInto<Url, Error = url::ParseError>) -> Result<Self, HttpError> {
-		Self::new_with_client_config(url.try_into()?, Request::Get, identity)
+		Ok(Self::new_with_client(
+			url.try_into()?,
+			Request::get(),
+			HttpClient::shared_default()?,
+		))
 	}
 
-	/// Create a new HTTP client that sends POST requests
+	/// Create a new HTTP source that sends POST requests using the [default client](`HttpClient::shared_default`)
 	///
 	/// # Errors
 	/// This method fails if body isn't valid JSON or TLS couldn't be initialized
@@ -77,17 +145,18 @@ impl Http {
 		url: impl TryInto<Url, Error = url::ParseError>,
 		body: &str,
 	) -> Result<Self, HttpError> {
-		Self::new_with_client_config(
+		Ok(Self::new_with_client(
 			url.try_into()?,
-			Request::Post(serde_json::from_str(body)?),
-			identity,
-		)
+			Request::post_json(serde_json::from_str(body)?),
+			HttpClient::shared_default()?,
+		))
 	}
 
-	/// Creates a new HTTP client with a closure that gets passed a [`reqwest::ClientBuilder`]
-	/// to allow more configuration from the caller (e.g. setting headers & cookie stores).
+	/// Creates a new HTTP source with its own client,
+	/// configured with a closure that gets passed a [`reqwest::ClientBuilder`]
+	/// to allow more configuration from the caller (e.g. setting proxies, headers & cookie stores).
 	///
-	/// This is the most general constructor for [`Http`] and allows the most freedom.
+	/// See [`HttpClient::with_config`]. Use [`Http::new_with_client`] to share the client between several sources.
 	///
 	/// # Errors
 	/// This method fails if TLS couldn't be initialized
@@ -99,72 +168,331 @@ impl Http {
 	where
 		F: FnOnce(reqwest::ClientBuilder) -> reqwest::ClientBuilder,
 	{
-		let client = CLIENT
-			.get_or_try_init(|| {
-				let builder = reqwest::ClientBuilder::new().timeout(Duration::from_secs(30));
-
-				builder_config(builder)
-					.build()
-					.map_err(HttpError::TlsInitFailed)
-			})?
-			.clone();
+		Ok(Self::new_with_client(
+			url,
+			request,
+			HttpClient::with_config(builder_config)?,
+		))
+	}
 
-		Ok(Self {
+	/// Creates a new HTTP source that uses the provided `client` to send requests.
+	///
+	/// This is the most general constructor for [`Http`] and allows the most freedom.
+	#[must_use]
+	pub fn new_with_client(url: Url, request: Request, client: HttpClient) -> Self {
+		Self {
 			url,
 			request,
 			client,
-		})
+			conditional: None,
+			pagination: None,
+		}
+	}
+}
+
+#[bon::bon]
+impl Http {
+	/// Creates a new [`Http`] source using the builder syntax.
+	///
+	/// Uses the [default client](`HttpClient::shared_default`) if `client` isn't set.
+	///
+	/// # Example
+	/// ```
+	/// # use fetcher::sources::http::{Http, Request, Method};
+	/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+	/// let http = Http::builder()
+	///     .url("https://example.com/api/items")
+	///     .request(
+	///         Request::builder()
+	///             .method(Method::PATCH)
+	///             .form([("state", "closed")])
+	///             .basic_auth("user", Some("password".into()))
+	///             .build(),
+	///     )
+	///     .build()?;
+	/// # Ok(())
+	/// # }
+	/// ```
+	///
+	/// # Errors
+	/// This method fails if `url` isn't a valid URL
+	/// or if `client` isn't set and TLS couldn't be initialized for the default client
+	#[builder]
+	pub fn new(
+		/// The URL to send the request to
+		url: &str,
+
+		/// The request to send. Defaults to a plain `GET` request
+		#[builder(default)]
+		request: Request,
+
+		/// The client used to send the request
+		client: Option<HttpClient>,
+	) -> Result<Self, HttpError> {
+		let client = match client {
+			Some(client) => client,
+			None => HttpClient::shared_default()?,
+		};
+
+		Ok(Self::new_with_client(Url::parse(url)?, request, client))
 	}
 }
 
-impl Fetch for Http {
+impl<E, S> Http<E, S> {
+	/// Returns the request that is sent every time the source is fetched
+	#[must_use]
+	pub fn request(&self) -> &Request {
+		&self.request
+	}
+
+	/// Enables conditional requests using the `ETag` and `Last-Modified` validators of the last response.
+	///
+	/// The validators are only kept in memory and are thus lost when the program is restarted.
+	/// Use [`Http::with_conditional_get_external_save`] to preserve them across restarts.
+	#[must_use]
+	pub fn with_conditional_get(self) -> Self {
+		Self {
+			conditional: Some(ConditionalGet {
+				validators: Validators::default(),
+				external_save: None,
+			}),
+			..self
+		}
+	}
+
+	/// Enables conditional requests using the `ETag` and `Last-Modified` validators of the last response,
+	/// starting with the previously saved `validators`.
+	///
+	/// [`ExternalSave::save_source_state`] is called with the URL as the key and the new [`Validators`] every time they change.
+	///
+	/// # Note
+	/// The validators are updated as soon as the page is fetched.
+	/// If the rest of the pipeline fails afterwards, the page won't be processed again until it changes on the server.
+	pub fn with_conditional_get_external_save<Ext>(
+		self,
+		validators: Validators,
+		external_save: Ext,
+	) -> Http<Ext, S>
+	where
+		Ext: ExternalSave,
+	{
+		Http {
+			url: self.url,
+			request: self.request,
+			client: self.client,
+			conditional: Some(ConditionalGet {
+				validators,
+				external_save: Some(external_save),
+			}),
+			pagination: self.pagination,
+		}
+	}
+
+	/// Enables following the next pages of a paginated listing.
+	///
+	/// Only the first page is requested conditionally if conditional requests are enabled.
+	/// See [`Pagination`]
+	pub fn with_pagination<NewS>(self, pagination: Pagination<NewS>) -> Http<E, NewS>
+	where
+		NewS: StopCondition,
+	{
+		Http {
+			url: self.url,
+			request: self.request,
+			client: self.client,
+			conditional: self.conditional,
+			pagination: Some(pagination),
+		}
+	}
+
+	/// Returns the cache validators of the last response, if conditional requests are enabled
+	#[must_use]
+	pub fn validators(&self) -> Option<&Validators> {
+		self.conditional.as_ref().map(|cond| &cond.validators)
+	}
+}
+
+impl<E, S> Fetch for Http<E, S>
+where
+	E: ExternalSave,
+	S: StopCondition,
+{
 	type Err = HttpError;
 
 	/// Send a request to the [`URL`](`self.url`) and return the result in the [`Entry.raw_contents`] field
+	///
+	/// Returns no entries if conditional requests are enabled and the page hasn't changed since the last fetch.
+	/// Returns an entry per page if pagination is enabled
 	#[tracing::instrument(skip_all)]
 	async fn fetch(&mut self) -> Result<Vec<Entry>, Self::Err> {
 		tracing::debug!("Sending an HTTP request");
 
-		let page = send_request(&self.client, &self.request, &self.url).await?;
+		let request = self.request.to_reqwest(&self.client, &self.url);
+		let request = match &self.conditional {
+			Some(cond) => cond.validators.apply(request),
+			None => request,
+		};
+
+		let response = send(request, &self.url).await?;
+
+		if response.status() == StatusCode::NOT_MODIFIED {
+			tracing::debug!("Page hasn't been modified since the last fetch");
+			return Ok(Vec::new());
+		}
+
+		if let Some(cond) = &mut self.conditional {
+			cond.update(&self.url, response.headers()).await?;
+		}
+
+		let Some(pagination) = &mut self.pagination else {
+			let page = text(response, &self.url).await?;
+			return Ok(vec![page_entry(page, &self.url)]);
+		};
+
+		let mut url = self.url.clone();
+		let mut response = response;
+		let mut pages = Vec::new();
+
+		loop {
+			let headers = response.headers().clone();
+			let page = text(response, &url).await?;
+			let entry = page_entry(page, &url);
+			let should_stop = pagination.stop_condition.should_stop(&entry).await?;
+			pages.push(entry);
+
+			if should_stop {
+				break;
+			}
 
-		let entry = Entry::builder()
-			.raw_contents(page)
-			.msg(Message::builder().link(self.url.as_str().to_owned()))
-			.build();
+			if pages.len() >= pagination.max_pages {
+				tracing::debug!("Reached the max number of pages {}", pagination.max_pages);
+				break;
+			}
 
-		Ok(vec![entry])
+			let page = pages
+				.last()
+				.and_then(|entry| entry.raw_contents.as_deref())
+				.unwrap_or_default();
+			let Some(next_url) = pagination
+				.next_page
+				.find(&url, &headers, page, pages.len())?
+			else {
+				tracing::debug!("No more pages after {url}");
+				break;
+			};
+
+			// the request may contain credentials and other private data that shouldn't be sent anywhere else
+			if next_url.origin() != self.url.origin() {
+				tracing::warn!(
+					"Not following the next page at {next_url} since it's on a different origin than {}",
+					self.url
+				);
+				break;
+			}
+
+			tracing::debug!("Following the next page at {next_url}");
+			let request = next_page_request(&self.request, &self.client, &next_url);
+			response = match send(request, &next_url).await {
+				Ok(response) => response,
+				// the page number was just a guess, there's no page with that number
+				Err(HttpError::UnsuccessfulStatus {
+					status: StatusCode::NOT_FOUND,
+					..
+				}) if matches!(pagination.next_page, NextPage::Template { .. }) => {
+					tracing::debug!("No more pages after {url}, {next_url} doesn't exist");
+					break;
+				}
+				Err(e) => return Err(e),
+			};
+			url = next_url;
+		}
+
+		Ok(pages)
 	}
 }
 
+/// Creates an entry containing the contents of the page at the `url`
+fn page_entry(page: String, url: &Url) -> Entry {
+	Entry::builder()
+		.raw_contents(page)
+		.msg(Message::builder().link(url.as_str().to_owned()))
+		.build()
+}
+
+/// Creates the request for the next page at the `url`.
+///
+/// Query parameters of the `request` that the `url` already contains are skipped
+/// since the URL of the next page usually already carries the whole query
+fn next_page_request(request: &Request, client: &HttpClient, url: &Url) -> RequestBuilder {
+	let mut request = request.clone();
+	request.query.retain(|(key, _)| {
+		!url.query_pairs()
+			.any(|(present, _)| present == key.as_str())
+	});
+
+	request.to_reqwest(client, url)
+}
+
+/// Sends the `request` to the `url` and returns the text of the response
+#[cfg(any(
+	feature = "action-http",
+	feature = "source-calendar",
+	feature = "source-gmail"
+))]
 pub(crate) async fn send_request(
-	client: &Client,
+	client: &HttpClient,
 	request: &Request,
 	url: &Url,
 ) -> Result<String, HttpError> {
-	let request = match request {
-		Request::Get => {
-			tracing::trace!("Making an HTTP GET request to {:?}", url.as_str());
-
-			client.get(url.as_str())
-		}
-		Request::Post(json) => {
-			tracing::trace!(
-				"Making an HTTP POST request to {:?} with {:#?}",
-				url.as_str(),
-				json
-			);
-
-			client.post(url.as_str()).json(json)
-		}
-	};
+	let response = send(request.to_reqwest(client, url), url).await?;
+	text(response, url).await
+}
 
+/// Sends the request and returns the response if it's successful (or not modified)
+async fn send(request: RequestBuilder, url: &Url) -> Result<Response, HttpError> {
 	let response = request
-		// TODO: move this to builder config and allow the user to override it. There's ClientBuilder::user_agent() I believe
-		.header(reqwest::header::USER_AGENT, USER_AGENT)
 		.send()
 		.await
 		.map_err(|e| HttpError::BadRequest(e, url.to_string()))?;
 
+	let status = response.status();
+	if status.is_success() || status == StatusCode::NOT_MODIFIED {
+		return Ok(response);
+	}
+
+	tracing::debug!("Got an unsuccessful HTTP status {status}");
+	let retry_after = parse_retry_after(response.headers());
+
+	// the body is only used for the error message, so it's fine if it can't be read
+	let body = response
+		.text()
+		.await
+		.map(|body| body.pretty_slice_until(BODY_SNIPPET_LEN).into_owned())
+		.unwrap_or_default();
+
+	Err(HttpError::UnsuccessfulStatus {
+		status,
+		body,
+		retry_after,
+		url: url.to_string(),
+	})
+}
+
+/// Parses the value of the `Retry-After` header which is either a number of seconds or an HTTP date
+fn parse_retry_after(headers: &HeaderMap) -> Option<Duration> {
+	let value = headers.get(RETRY_AFTER)?.to_str().ok()?.trim();
+
+	if let Ok(secs) = value.parse::<u64>() {
+		return Some(Duration::from_secs(secs));
+	}
+
+	let date = DateTime::parse_from_rfc2822(value).ok()?;
+
+	// the date is in the past, no need to wait
+	(date.with_timezone(&Utc) - Utc::now()).to_std().ok()
+}
+
+async fn text(response: Response, url: &Url) -> Result<String, HttpError> {
 	tracing::trace!("Getting text body of the response");
 	response
 		.text()
@@ -172,11 +500,451 @@ pub(crate) async fn send_request(
 		.map_err(|e| HttpError::BadRequest(e, url.to_string()))
 }
 
-impl Debug for Http {
+impl Error for HttpError {
+	fn is_network_related(&self) -> Option<&dyn Error> {
+		match self {
+			Self::BadRequest(..) => Some(self),
+			Self::UnsuccessfulStatus { status, .. }
+				if status.is_server_error() || *status == StatusCode::TOO_MANY_REQUESTS =>
+			{
+				Some(self)
+			}
+			Self::Pagination(e) if e.is_network_related().is_some() => Some(self),
+			_ => None,
+		}
+	}
+
+	fn retry_after(&self) -> Option<Duration> {
+		match self {
+			Self::UnsuccessfulStatus { retry_after, .. } => *retry_after,
+			_ => None,
+		}
+	}
+}
+
+impl Validators {
+	/// Extracts the validators from the headers of a response
+	#[must_use]
+	pub fn from_headers(headers: &HeaderMap) -> Self {
+		let get = |name| {
+			headers
+				.get(name)
+				.and_then(|value| value.to_str().ok())
+				.map(ToOwned::to_owned)
+		};
+
+		Self {
+			etag: get(ETAG),
+			last_modified: get(LAST_MODIFIED),
+		}
+	}
+
+	fn apply(&self, mut request: RequestBuilder) -> RequestBuilder {
+		if let Some(etag) = &self.etag {
+			tracing::trace!("Sending If-None-Match: {etag:?}");
+			request = request.header(IF_NONE_MATCH, etag);
+		}
+
+		if let Some(last_modified) = &self.last_modified {
+			tracing::trace!("Sending If-Modified-Since: {last_modified:?}");
+			request = request.header(IF_MODIFIED_SINCE, last_modified);
+		}
+
+		request
+	}
+}
+
+impl<E> ConditionalGet<E>
+where
+	E: ExternalSave,
+{
+	/// Replaces the validators with the ones from the response and saves them externally under the `url` key if they've changed
+	async fn update(&mut self, url: &Url, headers: &HeaderMap) -> Result<(), HttpError> {
+		let validators = Validators::from_headers(headers);
+		if validators == self.validators {
+			return Ok(());
+		}
+
+		tracing::debug!("Cache validators changed to {validators:?}");
+		self.validators = validators;
+
+		if let Some(external_save) = &mut self.external_save {
+			external_save
+				.save_source_state(url.as_str(), &self.validators)
+				.await?;
+		}
+
+		Ok(())
+	}
+}
+
+impl<E, S> Debug for Http<E, S>
+where
+	S: Debug,
+{
 	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
 		f.debug_struct("Http")
 			.field("url", &self.url.as_str())
 			.field("request", &self.request)
+			.field("validators", &self.validators())
+			.field("pagination", &self.pagination)
 			.finish_non_exhaustive()
 	}
 }
+
+#[cfg(test)]
+mod tests {
+	use assert_matches::assert_matches;
+
+	use super::{
+		mock_server::{self, Response},
+		*,
+	};
+
+	const ETAG_VALUE: &str = r#""v1""#;
+
+	/// Responds with `304 Not Modified` if the request contains the expected `If-None-Match` header
+	fn respond_with_etag(request: &mock_server::Request) -> Response {
+		if request.header("if-none-match") == Some(ETAG_VALUE) {
+			Response::new(304, "")
+		} else {
+			Response::ok("hello").header("ETag", ETAG_VALUE)
+		}
+	}
+
+	#[tokio::test]
+	async fn conditional_get_returns_nothing_if_not_modified() {
+		let server = mock_server::start(respond_with_etag).await;
+		let mut http = Http::new_get(server.url.as_str())
+			.unwrap()
+			.with_conditional_get();
+
+		let entries = http.fetch().await.unwrap();
+		assert_eq!(entries.len(), 1);
+		assert_eq!(entries[0].raw_contents.as_deref(), Some("hello"));
+		assert_eq!(http.validators().unwrap().etag.as_deref(), Some(ETAG_VALUE));
+
+		let entries = http.fetch().await.unwrap();
+		assert!(entries.is_empty(), "page should've not been modified");
+	}
+
+	#[tokio::test]
+	async fn no_conditional_get_by_default() {
+		let server = mock_server::start(respond_with_etag).await;
+		let mut http = Http::new_get(server.url.as_str()).unwrap();
+
+		assert_eq!(http.fetch().await.unwrap().len(), 1);
+		assert_eq!(http.fetch().await.unwrap().len(), 1);
+	}
+
+	#[tokio::test]
+	async fn unsuccessful_status_is_an_error() {
+		let server =
+			mock_server::start(|_| Response::new(503, "maintenance").header("Retry-After", "7"))
+				.await;
+
+		let err = Http::new_get(server.url.as_str())
+			.unwrap()
+			.fetch()
+			.await
+			.unwrap_err();
+
+		assert_matches!(
+			&err,
+			HttpError::UnsuccessfulStatus {
+				status: StatusCode::SERVICE_UNAVAILABLE,
+				body,
+				..
+			} if body == "maintenance"
+		);
+		assert!(err.is_network_related().is_some());
+		assert_eq!(err.retry_after(), Some(Duration::from_secs(7)));
+	}
+
+	#[tokio::test]
+	async fn client_error_is_not_network_related() {
+		let server = mock_server::start(|_| Response::new(404, "")).await;
+
+		let err = Http::new_get(server.url.as_str())
+			.unwrap()
+			.fetch()
+			.await
+			.unwrap_err();
+
+		assert_matches!(
+			err,
+			HttpError::UnsuccessfulStatus {
+				status: StatusCode::NOT_FOUND,
+				..
+			}
+		);
+		assert!(err.is_network_related().is_none());
+	}
+
+	#[tokio::test]
+	async fn client_config_is_per_source() {
+		/// Responds with the value of the `x-test` header
+		fn echo_header(request: &mock_server::Request) -> Response {
+			Response::ok(request.header("x-test").unwrap_or_default())
+		}
+
+		let server = mock_server::start(echo_header).await;
+
+		let http_with_header = |value: &'static str| {
+			Http::new_with_client_config(server.url.clone(), Request::get(), |builder| {
+				let mut headers = HeaderMap::new();
+				headers.insert("x-test", value.parse().unwrap());
+				builder.default_headers(headers)
+			})
+			.unwrap()
+		};
+
+		let mut first = http_with_header("first");
+		let mut second = http_with_header("second");
+
+		let first_page = first.fetch().await.unwrap().remove(0).raw_contents;
+		let second_page = second.fetch().await.unwrap().remove(0).raw_contents;
+
+		assert_eq!(first_page.as_deref(), Some("first"));
+		assert_eq!(second_page.as_deref(), Some("second"));
+	}
+
+	#[tokio::test]
+	async fn request_is_built_from_all_parts() {
+		let server = mock_server::start(|_| Response::ok("")).await;
+
+		let mut http = Http::builder()
+			.url(server.url.as_str())
+			.request(
+				Request::builder()
+					.method(Method::PUT)
+					.form([("state", "open"), ("label", "a b")])
+					.header("X-Test", "value")
+					.query("page", "2")
+					.bearer_auth("secret")
+					.build(),
+			)
+			.build()
+			.unwrap();
+
+		http.fetch().await.unwrap();
+		let request = server.requests().remove(0);
+
+		assert_eq!(request.to_string(), "PUT /?page=2 state=open&label=a+b");
+		assert_eq!(request.header("x-test"), Some("value"));
+		assert_eq!(request.header("authorization"), Some("Bearer secret"));
+		assert_eq!(
+			request.header("content-type"),
+			Some("application/x-www-form-urlencoded")
+		);
+	}
+
+	/// Responds to `/?page=N` with the items `N0 N1` and a link to the next page up to page 3.
+	/// Pages after the 3rd one don't exist
+	fn respond_with_pages(request: &mock_server::Request) -> Response {
+		let page = request.target.split("page=").nth(1).map_or(1, |num| {
+			num.split('&').next().unwrap().parse::<u32>().unwrap()
+		});
+
+		let response = Response::ok(format!("{page}0 {page}1"));
+		match page {
+			..3 => response.header("Link", format!("</?page={}>; rel=\"next\"", page + 1)),
+			3 => response,
+			_ => Response::new(404, ""),
+		}
+	}
+
+	#[tokio::test]
+	async fn pagination_follows_link_header() {
+		let server = mock_server::start(respond_with_pages).await;
+		let url = &server.url;
+		let mut http = Http::new_get(url.as_str())
+			.unwrap()
+			.with_pagination(Pagination::new(NextPage::LinkHeader, 10));
+
+		let pages = http.fetch().await.unwrap();
+		let contents = pages
+			.iter()
+			.map(|page| page.raw_contents.as_deref().unwrap())
+			.collect::<Vec<_>>();
+
+		assert_eq!(contents, ["10 11", "20 21", "30 31"]);
+		assert_eq!(
+			pages[2].msg.link.as_deref(),
+			Some(url.join("?page=3").unwrap().as_str())
+		);
+	}
+
+	#[tokio::test]
+	async fn pagination_stops_at_max_pages() {
+		let server = mock_server::start(respond_with_pages).await;
+		let url = &server.url;
+		let mut http = Http::new_get(url.join("?page=1").unwrap().as_str())
+			.unwrap()
+			.with_pagination(Pagination::new(
+				NextPage::Template {
+					template: "?page={page}".into(),
+					first_page: 1,
+				},
+				2,
+			));
+
+		assert_eq!(http.fetch().await.unwrap().len(), 2);
+	}
+
+	#[tokio::test]
+	async fn pagination_doesnt_look_for_next_page_after_the_last_one() {
+		let server = mock_server::start(|_| Response::ok("not json")).await;
+		let mut http = Http::new_get(server.url.as_str())
+			.unwrap()
+			.with_pagination(Pagination::new(NextPage::JsonPointer("/next".into()), 1));
+
+		assert_eq!(http.fetch().await.unwrap().len(), 1);
+	}
+
+	#[tokio::test]
+	async fn pagination_template_stops_at_missing_page() {
+		let server = mock_server::start(respond_with_pages).await;
+		let url = &server.url;
+		let mut http = Http::new_get(url.join("?page=1").unwrap().as_str())
+			.unwrap()
+			.with_pagination(Pagination::new(
+				NextPage::Template {
+					template: "?page={page}".into(),
+					first_page: 1,
+				},
+				10,
+			));
+
+		assert_eq!(http.fetch().await.unwrap().len(), 3);
+	}
+
+	#[tokio::test]
+	async fn pagination_doesnt_repeat_query() {
+		let server = mock_server::start(respond_with_pages).await;
+		let mut http = Http::builder()
+			.url(server.url.as_str())
+			.request(
+				Request::builder()
+					.query("page", "1")
+					.query("key", "value")
+					.build(),
+			)
+			.build()
+			.unwrap()
+			.with_pagination(Pagination::new(NextPage::LinkHeader, 2));
+
+		http.fetch().await.unwrap();
+		let targets = server
+			.requests()
+			.into_iter()
+			.map(|request| request.target)
+			.collect::<Vec<_>>();
+
+		assert_eq!(targets, ["/?page=1&key=value", "/?page=2&key=value"]);
+	}
+
+	#[tokio::test]
+	async fn query_is_appended_to_the_url_as_is() {
+		let server = mock_server::start(|_| Response::ok("")).await;
+		let mut http = Http::builder()
+			.url(server.url.join("?tag=a").unwrap().as_str())
+			.request(Request::builder().query("tag", "b").build())
+			.build()
+			.unwrap();
+
+		http.fetch().await.unwrap();
+
+		assert_eq!(server.requests().remove(0).target, "/?tag=a&tag=b");
+	}
+
+	#[tokio::test]
+	async fn pagination_doesnt_follow_other_origins() {
+		let server = mock_server::start(|_| {
+			Response::ok("").header("Link", "<http://example.com/?page=2>; rel=\"next\"")
+		})
+		.await;
+		let mut http = Http::builder()
+			.url(server.url.as_str())
+			.request(Request::builder().bearer_auth("secret").build())
+			.build()
+			.unwrap()
+			.with_pagination(Pagination::new(NextPage::LinkHeader, 10));
+
+		assert_eq!(http.fetch().await.unwrap().len(), 1);
+		assert_eq!(server.requests().len(), 1);
+	}
+
+	#[tokio::test]
+	async fn pagination_stops_when_page_is_read() {
+		use crate::{
+			actions::transforms::{
+				Transform,
+				result::{TransformResult, TransformedEntry},
+			},
+			entry::EntryId,
+			read_filter::{MarkAsRead, NotPresent},
+		};
+
+		/// Splits the page into items with whitespace separated IDs
+		struct SplitIds;
+
+		impl Transform for SplitIds {
+			type Err = Infallible;
+
+			async fn transform_entry(
+				&mut self,
+				entry: Entry,
+			) -> Result<Vec<TransformedEntry>, Self::Err> {
+				Ok(entry
+					.raw_contents
+					.unwrap_or_default()
+					.split_whitespace()
+					.map(|id| TransformedEntry {
+						id: TransformResult::New(EntryId::try_from(id).unwrap()),
+						..Default::default()
+					})
+					.collect())
+			}
+		}
+
+		let mut read_filter = NotPresent::new();
+		for id in ["20", "21"] {
+			read_filter
+				.mark_as_read(&EntryId::try_from(id).unwrap())
+				.await
+				.unwrap();
+		}
+
+		let server = mock_server::start(respond_with_pages).await;
+		let url = &server.url;
+		let mut http = Http::new_get(url.as_str()).unwrap().with_pagination(
+			Pagination::new(NextPage::LinkHeader, 10).stop_when_read(SplitIds, read_filter),
+		);
+
+		assert_eq!(
+			http.fetch().await.unwrap().len(),
+			2,
+			"the 3rd page should've not been fetched after the read 2nd one"
+		);
+	}
+
+	#[test]
+	fn retry_after_http_date() {
+		let mut headers = HeaderMap::new();
+
+		let in_an_hour = (Utc::now() + chrono::Duration::hours(1)).to_rfc2822();
+		headers.insert(RETRY_AFTER, in_an_hour.parse().unwrap());
+		let retry_after = parse_retry_after(&headers).unwrap().as_secs();
+		assert!(
+			(3590..=3600).contains(&retry_after),
+			"should be about an hour, got {retry_after}s"
+		);
+
+		headers.insert(
+			RETRY_AFTER,
+			"Wed, 21 Oct 2015 07:28:00 GMT".parse().unwrap(),
+		);
+		assert_eq!(parse_retry_after(&headers), None);
+	}
+}
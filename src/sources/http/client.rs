@@ -0,0 +1,108 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+
+//! This module contains the [`HttpClient`] handle that can be shared between HTTP sources and transforms
+
+use std::time::Duration;
+
+use once_cell::sync::OnceCell;
+use reqwest::ClientBuilder;
+
+use super::HttpError;
+
+static DEFAULT_CLIENT: OnceCell<HttpClient> = OnceCell::new();
+
+/// A handle to an HTTP client.
+///
+/// It's cheap to clone and all clones share the same connection pool, configuration, and cookie store (if enabled).
+/// This makes it possible to use the same client in several [`Http`](`super::Http`) sources
+/// and [`Http`](`crate::actions::transforms::Http`) transforms,
+/// or to use different clients (e.g. with different proxies or timeouts) for different sources.
+///
+/// # Default client
+///
+/// The sources and transforms that aren't provided a client explicitly use the [default client](`HttpClient::shared_default`).
+/// It is created once when it's first used and is then shared between all of them.
+/// It uses a timeout of [30 seconds](`HttpClient::DEFAULT_TIMEOUT`), the [default user agent](`HttpClient::DEFAULT_USER_AGENT`),
+/// supports gzip compression, and doesn't store cookies.
+#[derive(Clone, Debug)]
+pub struct HttpClient(reqwest::Client);
+
+impl HttpClient {
+	/// Timeout of the requests sent by the default client
+	pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+
+	/// User agent sent by the default client.
+	///
+	/// It can be overriden for all requests sent by a client with [`reqwest::ClientBuilder::user_agent`]
+	/// or for a single request with [`Request::headers`](`super::Request::headers`)
+	pub const DEFAULT_USER_AGENT: &str = concat!("fetcher/", env!("CARGO_PKG_VERSION"));
+
+	/// Creates a new client with the default configuration.
+	///
+	/// Unlike [`HttpClient::shared_default`], this client doesn't share anything with other clients.
+	///
+	/// # Errors
+	/// This method fails if TLS couldn't be initialized
+	pub fn new() -> Result<Self, HttpError> {
+		Self::with_config(|builder| builder)
+	}
+
+	/// Creates a new client with a closure that gets passed a [`reqwest::ClientBuilder`]
+	/// with the default configuration already applied,
+	/// to allow more configuration from the caller (e.g. setting proxies, headers & cookie stores).
+	///
+	/// # Example
+	/// ```
+	/// # use fetcher::sources::http::HttpClient;
+	/// # use std::time::Duration;
+	/// let client = HttpClient::with_config(|builder| {
+	///     builder
+	///         .timeout(Duration::from_secs(5))
+	///         .cookie_store(true)
+	/// })
+	/// .expect("TLS should be available");
+	/// ```
+	///
+	/// # Errors
+	/// This method fails if TLS couldn't be initialized
+	pub fn with_config<F>(builder_config: F) -> Result<Self, HttpError>
+	where
+		F: FnOnce(ClientBuilder) -> ClientBuilder,
+	{
+		let builder = ClientBuilder::new()
+			.timeout(Self::DEFAULT_TIMEOUT)
+			.user_agent(Self::DEFAULT_USER_AGENT);
+
+		builder_config(builder)
+			.build()
+			.map(Self)
+			.map_err(HttpError::TlsInitFailed)
+	}
+
+	/// Returns a handle to the default client that is shared between all sources and transforms
+	/// that haven't been provided a client explicitly.
+	///
+	/// See [the type level docs](`HttpClient#default-client`) for more info.
+	///
+	/// # Errors
+	/// This method fails if TLS couldn't be initialized
+	pub fn shared_default() -> Result<Self, HttpError> {
+		DEFAULT_CLIENT.get_or_try_init(Self::new).cloned()
+	}
+
+	/// Returns the underlying [`reqwest::Client`]
+	#[must_use]
+	pub fn as_reqwest(&self) -> &reqwest::Client {
+		&self.0
+	}
+}
+
+impl From<reqwest::Client> for HttpClient {
+	fn from(client: reqwest::Client) -> Self {
+		Self(client)
+	}
+}
@@ -0,0 +1,202 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+
+//! A tiny HTTP/1.1 server to test sources that talk to HTTP APIs against
+
+use std::{
+	fmt::{self, Display},
+	sync::{Arc, Mutex},
+};
+use tokio::{
+	io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader},
+	net::TcpListener,
+};
+use url::Url;
+
+/// A request received by the server
+#[derive(Clone, Debug)]
+pub struct Request {
+	/// e.g. `GET`
+	pub method: String,
+
+	/// The path and the query, e.g. `/messages?q=...`
+	pub target: String,
+
+	/// Headers with lowercased names
+	pub headers: Vec<(String, String)>,
+
+	pub body: String,
+}
+
+/// A response to send back
+#[derive(Clone, Debug)]
+pub struct Response {
+	pub status: u16,
+	pub headers: Vec<(String, String)>,
+	pub body: String,
+}
+
+/// A running server. Stops when the test's runtime shuts down
+pub struct MockServer {
+	/// URL of the root of the server
+	pub url: Url,
+
+	/// All requests received so far, in order
+	pub requests: Arc<Mutex<Vec<Request>>>,
+}
+
+/// Starts a server that responds to every request with the response returned by `respond`
+pub async fn start(respond: fn(&Request) -> Response) -> MockServer {
+	let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+	let addr = listener.local_addr().unwrap();
+	let requests = Arc::new(Mutex::new(Vec::new()));
+
+	tokio::spawn({
+		let requests = Arc::clone(&requests);
+
+		async move {
+			loop {
+				let (stream, _) = listener.accept().await.unwrap();
+				let mut stream = BufReader::new(stream);
+
+				let request = read_request(&mut stream).await;
+				let response = respond(&request);
+				requests.lock().unwrap().push(request);
+
+				stream
+					.write_all(response.to_string().as_bytes())
+					.await
+					.unwrap();
+			}
+		}
+	});
+
+	MockServer {
+		url: Url::parse(&format!("http://{addr}/")).unwrap(),
+		requests,
+	}
+}
+
+impl MockServer {
+	/// Returns a copy of all requests received so far
+	pub fn requests(&self) -> Vec<Request> {
+		self.requests.lock().unwrap().clone()
+	}
+}
+
+async fn read_request<R>(stream: &mut R) -> Request
+where
+	R: AsyncBufReadExt + Unpin,
+{
+	let mut request_line = String::new();
+	stream.read_line(&mut request_line).await.unwrap();
+
+	let mut request_line = request_line.split_whitespace();
+	let method = request_line.next().unwrap().to_owned();
+	let target = request_line.next().unwrap().to_owned();
+
+	let mut headers = Vec::new();
+	loop {
+		let mut header = String::new();
+		stream.read_line(&mut header).await.unwrap();
+
+		let header = header.trim_end();
+		if header.is_empty() {
+			break;
+		}
+
+		let (name, value) = header.split_once(':').unwrap();
+		headers.push((name.to_lowercase(), value.trim().to_owned()));
+	}
+
+	let mut request = Request {
+		method,
+		target,
+		headers,
+		body: String::new(),
+	};
+
+	let content_length = request
+		.header("content-length")
+		.map_or(0, |len| len.parse().unwrap());
+
+	let mut body = vec![0; content_length];
+	stream.read_exact(&mut body).await.unwrap();
+	request.body = String::from_utf8(body).unwrap();
+
+	request
+}
+
+impl Request {
+	/// Returns the value of the header with the lowercase `name`
+	pub fn header(&self, name: &str) -> Option<&str> {
+		self.headers
+			.iter()
+			.find(|(header, _)| header == name)
+			.map(|(_, value)| value.as_str())
+	}
+
+	/// Returns the target without the query
+	pub fn path(&self) -> &str {
+		self.target
+			.split_once('?')
+			.map_or(&self.target, |(path, _)| path)
+	}
+}
+
+impl Response {
+	/// Creates a response with the `status` and the `body`
+	pub fn new(status: u16, body: impl Into<String>) -> Self {
+		Self {
+			status,
+			headers: Vec::new(),
+			body: body.into(),
+		}
+	}
+
+	/// Creates a `200 OK` response with the `body`
+	pub fn ok(body: impl Into<String>) -> Self {
+		Self::new(200, body)
+	}
+
+	/// Adds a header
+	#[must_use]
+	pub fn header(mut self, name: &str, value: impl Into<String>) -> Self {
+		self.headers.push((name.to_owned(), value.into()));
+		self
+	}
+}
+
+/// Formats as the method, the target, and the body if there is one, e.g. `POST /messages {}`
+impl Display for Request {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(f, "{} {}", self.method, self.target)?;
+
+		if !self.body.is_empty() {
+			write!(f, " {}", self.body)?;
+		}
+
+		Ok(())
+	}
+}
+
+/// Formats as the raw HTTP response
+impl Display for Response {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(f, "HTTP/1.1 {} Mock\r\n", self.status)?;
+
+		for (name, value) in &self.headers {
+			write!(f, "{name}: {value}\r\n")?;
+		}
+
+		write!(
+			f,
+			"Content-Length: {}\r\nConnection: close\r\n\r\n{}",
+			self.body.len(),
+			self.body
+		)
+	}
+}
@@ -0,0 +1,359 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+
+//! This module contains the [`Pagination`] of the [`Http`](`super::Http`) source
+//! and the [`NextPage`] strategies it uses to find the next page
+
+use reqwest::header::{HeaderMap, LINK};
+use url::Url;
+
+use super::Json;
+use crate::{
+	StaticStr,
+	actions::{
+		filters::{Filter, FilterableEntries, error::FilterError},
+		transforms::{Transform, error::TransformError, transform_old_entry_into_new_entries},
+	},
+	entry::Entry,
+	error::Error,
+	maybe_send::{MaybeSend, MaybeSendSync},
+};
+
+/// Placeholder of the page number in [`NextPage::Template`]
+const PAGE_PLACEHOLDER: &str = "{page}";
+
+/// Configuration of how the [`Http`](`super::Http`) source follows the pages of a paginated listing.
+///
+/// Every fetched page becomes its own [`Entry`].
+/// Pages are fetched until either there's no next page, [`max_pages`](`Pagination::max_pages`) pages have been fetched,
+/// or the [stop condition](`StopCondition`) says there's no need to go further, e.g. when a page contains only read items.
+///
+/// Next pages on a different origin (scheme, host, and port) than the URL of the source are never followed
+/// to avoid sending the credentials, headers, and body of the [`Request`](`super::Request`) to a third party
+///
+/// # Example
+/// ```
+/// # use fetcher::sources::http::{Http, Pagination, NextPage};
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// let http = Http::new_get("https://example.com/news?page=1")?.with_pagination(Pagination::new(
+///     NextPage::Template {
+///         template: "https://example.com/news?page={page}".into(),
+///         first_page: 1,
+///     },
+///     5,
+/// ));
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct Pagination<S = ()> {
+	/// How to find the URL of the next page
+	pub next_page: NextPage,
+
+	/// Max number of pages to fetch, including the first one. The first page is always fetched
+	pub max_pages: usize,
+
+	/// When to stop following the next pages early
+	pub stop_condition: S,
+}
+
+/// Where to find the URL of the next page.
+///
+/// Relative URLs are resolved against the URL of the current page
+#[derive(Clone, Debug)]
+pub enum NextPage {
+	/// The URL marked with `rel="next"` in the `Link` header of the response, see [RFC 8288](https://www.rfc-editor.org/rfc/rfc8288)
+	LinkHeader,
+
+	/// The `href` attribute of the first HTML element matching the CSS selector, e.g. `a.next`
+	#[cfg(feature = "action-html")]
+	Selector(scraper::Selector),
+
+	/// The string at the [JSON pointer](https://www.rfc-editor.org/rfc/rfc6901) in the JSON response, e.g. `/links/next`.
+	///
+	/// A missing value, `null`, or an empty string means there are no more pages
+	JsonPointer(StaticStr),
+
+	/// A URL template that contains a `{page}` placeholder, e.g. `https://example.com/news?page={page}`.
+	///
+	/// The URL of the source is used as page number `first_page`,
+	/// the placeholder is then replaced with `first_page + 1`, `first_page + 2`, and so on.
+	/// A `404 Not Found` response to one of these means there are no more pages
+	Template {
+		/// The URL template
+		template: StaticStr,
+
+		/// Number of the first page, i.e. the page the URL of the source points to
+		first_page: usize,
+	},
+}
+
+/// Decides whether to stop following the next pages after a page has been fetched.
+///
+/// `()` never stops early
+pub trait StopCondition: MaybeSendSync {
+	/// Returns true if no more pages should be fetched after the `page`
+	///
+	/// # Errors
+	/// Refer to implementator's docs.
+	fn should_stop(
+		&mut self,
+		page: &Entry,
+	) -> impl Future<Output = Result<bool, PaginationError>> + MaybeSend;
+}
+
+/// Stops following the next pages once a page contains only items the read filter already knows about,
+/// since all pages after it have most likely already been read, too.
+///
+/// The items are parsed from the page with the [`Transform`] that is used later in the pipeline anyways (e.g. a JSON or an HTML parser)
+/// and passed to the [`Filter`], which is usually a clone of the [`ReadFilter`](`crate::read_filter::ReadFilter`) attached to the source.
+/// A page with no items at all also stops the pagination.
+#[derive(Debug)]
+pub struct StopWhenRead<T, RF> {
+	/// Parser of the items of a page
+	pub parser: T,
+
+	/// The read filter that removes all already read items
+	pub read_filter: RF,
+}
+
+#[expect(missing_docs, reason = "error message is self-documenting")]
+#[derive(thiserror::Error, Debug)]
+pub enum PaginationError {
+	#[error("Page isn't valid JSON")]
+	NotJson(#[source] serde_json::Error),
+
+	#[error("Next page URL {0:?} is invalid")]
+	InvalidNextPageUrl(String, #[source] url::ParseError),
+
+	#[error("Failed to parse the items of the page")]
+	ItemParsing(#[source] Box<TransformError>),
+
+	#[error("Failed to filter out the already read items of the page")]
+	ReadFilter(#[source] FilterError),
+}
+
+impl Pagination {
+	/// Creates a new [`Pagination`] that follows up to `max_pages` pages using the `next_page` strategy and never stops early
+	#[must_use]
+	pub fn new(next_page: NextPage, max_pages: usize) -> Self {
+		Self {
+			next_page,
+			max_pages,
+			stop_condition: (),
+		}
+	}
+}
+
+impl<S> Pagination<S> {
+	/// Stops following the next pages once a page contains only items the `read_filter` already knows about.
+	///
+	/// See [`StopWhenRead`]
+	pub fn stop_when_read<T, RF>(
+		self,
+		parser: T,
+		read_filter: RF,
+	) -> Pagination<StopWhenRead<T, RF>>
+	where
+		T: Transform,
+		RF: Filter,
+	{
+		Pagination {
+			next_page: self.next_page,
+			max_pages: self.max_pages,
+			stop_condition: StopWhenRead {
+				parser,
+				read_filter,
+			},
+		}
+	}
+}
+
+impl NextPage {
+	/// Finds the URL of the page after the `current` one.
+	///
+	/// `pages_fetched` is the number of pages fetched so far, including the `current` one
+	pub(crate) fn find(
+		&self,
+		current: &Url,
+		headers: &HeaderMap,
+		page: &str,
+		pages_fetched: usize,
+	) -> Result<Option<Url>, PaginationError> {
+		let next = match self {
+			Self::LinkHeader => headers
+				.get_all(LINK)
+				.iter()
+				.filter_map(|value| value.to_str().ok())
+				.find_map(find_next_in_link_header)
+				.map(ToOwned::to_owned),
+			#[cfg(feature = "action-html")]
+			Self::Selector(selector) => scraper::Html::parse_document(page)
+				.select(selector)
+				.next()
+				.and_then(|elem| elem.attr("href"))
+				.map(|href| href.trim().to_owned()),
+			Self::JsonPointer(pointer) => serde_json::from_str::<Json>(page)
+				.map_err(PaginationError::NotJson)?
+				.pointer(pointer)
+				.and_then(Json::as_str)
+				.map(ToOwned::to_owned),
+			Self::Template {
+				template,
+				first_page,
+			} => Some(template.replace(PAGE_PLACEHOLDER, &(first_page + pages_fetched).to_string())),
+		};
+
+		let Some(next) = next.filter(|next| !next.is_empty()) else {
+			return Ok(None);
+		};
+
+		current
+			.join(&next)
+			.map(Some)
+			.map_err(|e| PaginationError::InvalidNextPageUrl(next, e))
+	}
+}
+
+/// Returns the URL of the link with `rel="next"` in the value of a `Link` header, e.g.
+/// `<https://example.com/?page=2>; rel="next", <https://example.com/?page=5>; rel="last"`
+fn find_next_in_link_header(value: &str) -> Option<&str> {
+	// commas are allowed inside of the <URL> itself
+	let mut in_url = false;
+	let mut links = value.split(move |c| {
+		match c {
+			'<' => in_url = true,
+			'>' => in_url = false,
+			_ => (),
+		}
+
+		c == ',' && !in_url
+	});
+
+	links.find_map(|link| {
+		let mut parts = link.split(';');
+		let url = parts.next()?.trim().strip_prefix('<')?.strip_suffix('>')?;
+
+		let is_next = parts.any(|param| {
+			let Some((name, rels)) = param.split_once('=') else {
+				return false;
+			};
+
+			// rel may contain several space separated relation types
+			name.trim().eq_ignore_ascii_case("rel")
+				&& rels
+					.trim()
+					.trim_matches('"')
+					.split_whitespace()
+					.any(|rel| rel.eq_ignore_ascii_case("next"))
+		});
+
+		is_next.then_some(url)
+	})
+}
+
+impl StopCondition for () {
+	async fn should_stop(&mut self, _page: &Entry) -> Result<bool, PaginationError> {
+		Ok(false)
+	}
+}
+
+impl<T, RF> StopCondition for StopWhenRead<T, RF>
+where
+	T: Transform,
+	RF: Filter,
+{
+	async fn should_stop(&mut self, page: &Entry) -> Result<bool, PaginationError> {
+		let mut items = transform_old_entry_into_new_entries(&mut self.parser, page.clone())
+			.await
+			.map_err(|e| PaginationError::ItemParsing(Box::new(e)))?;
+
+		self.read_filter
+			.filter(FilterableEntries::new(&mut items))
+			.await
+			.map_err(|e| PaginationError::ReadFilter(e.into()))?;
+
+		if items.is_empty() {
+			tracing::debug!("Page contains no unread items, not following the next pages");
+			return Ok(true);
+		}
+
+		Ok(false)
+	}
+}
+
+impl Error for PaginationError {
+	fn is_network_related(&self) -> Option<&dyn Error> {
+		match self {
+			Self::ItemParsing(e) if e.is_network_related().is_some() => Some(self),
+			Self::ReadFilter(e) if e.is_network_related().is_some() => Some(self),
+			_ => None,
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn next_link_is_found_among_others() {
+		assert_eq!(
+			find_next_in_link_header(
+				r#"<https://example.com/?page=1>; rel="first", <https://example.com/?page=3>; title="Next"; rel="next""#
+			),
+			Some("https://example.com/?page=3")
+		);
+		assert_eq!(
+			find_next_in_link_header("</items?after=42>; rel=\"prefetch next\""),
+			Some("/items?after=42")
+		);
+		assert_eq!(
+			find_next_in_link_header(
+				r#"<https://example.com/?ids=1,2>; rel="prev", <https://example.com/?ids=3,4>; rel="next""#
+			),
+			Some("https://example.com/?ids=3,4")
+		);
+		assert_eq!(
+			find_next_in_link_header(r#"<https://example.com/?page=1>; rel="prev""#),
+			None
+		);
+	}
+
+	#[test]
+	fn template_counts_from_first_page() {
+		let next_page = NextPage::Template {
+			template: "news?page={page}".into(),
+			first_page: 1,
+		};
+		let current = Url::parse("https://example.com/news?page=1").unwrap();
+
+		let next = next_page.find(&current, &HeaderMap::new(), "", 1).unwrap();
+		assert_eq!(
+			next.as_ref().map(Url::as_str),
+			Some("https://example.com/news?page=2")
+		);
+	}
+
+	#[test]
+	fn json_pointer_null_means_last_page() {
+		let next_page = NextPage::JsonPointer("/next".into());
+		let current = Url::parse("https://example.com/api/items").unwrap();
+
+		let next = next_page
+			.find(&current, &HeaderMap::new(), r#"{"next": "?cursor=abc"}"#, 1)
+			.unwrap();
+		assert_eq!(
+			next.as_ref().map(Url::as_str),
+			Some("https://example.com/api/items?cursor=abc")
+		);
+
+		let next = next_page
+			.find(&current, &HeaderMap::new(), r#"{"next": null}"#, 2)
+			.unwrap();
+		assert_eq!(next, None);
+	}
+}
@@ -0,0 +1,251 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+
+//! This module contains the [`Request`] that the [`Http`](`super::Http`) source sends, as well as its [`Body`] and [`Auth`]
+
+use std::fmt::Debug;
+
+use reqwest::Method;
+use url::Url;
+
+use super::{HttpClient, Json};
+use crate::StaticStr;
+
+/// An HTTP request that the [`Http`](`super::Http`) source sends every time it's fetched
+///
+/// # Example
+/// ```
+/// # use fetcher::sources::http::{Request, Method};
+/// let request = Request::builder()
+///     .method(Method::PUT)
+///     .json(serde_json::json!({ "state": "open" }))
+///     .header("X-Api-Version", "2")
+///     .query("page", "1")
+///     .bearer_auth("token")
+///     .build();
+/// ```
+#[derive(bon::Builder, Clone, Default, Debug)]
+pub struct Request {
+	/// Additional headers to send with the request.
+	///
+	/// They override the default headers of the [`HttpClient`], including the user agent
+	#[builder(field)]
+	pub headers: Vec<(StaticStr, StaticStr)>,
+
+	/// Query parameters to append to the URL
+	#[builder(field)]
+	pub query: Vec<(StaticStr, StaticStr)>,
+
+	/// HTTP method of the request. Defaults to `GET`
+	#[builder(default)]
+	pub method: Method,
+
+	/// Body of the request
+	pub body: Option<Body>,
+
+	/// Authentication to use
+	pub auth: Option<Auth>,
+}
+
+/// Body of an HTTP [`Request`]
+#[derive(Clone, Debug)]
+pub enum Body {
+	/// JSON payload, sent with the `application/json` content type
+	Json(Json),
+
+	/// Form fields, sent url-encoded with the `application/x-www-form-urlencoded` content type
+	Form(Vec<(StaticStr, StaticStr)>),
+
+	/// Raw payload that is sent as is
+	Raw {
+		/// Value of the `Content-Type` header, if any
+		content_type: Option<StaticStr>,
+
+		/// The payload itself
+		data: Vec<u8>,
+	},
+}
+
+/// Authentication of an HTTP [`Request`]
+#[derive(Clone)]
+pub enum Auth {
+	/// HTTP Basic authentication
+	Basic {
+		/// Username
+		username: StaticStr,
+
+		/// Password, if any
+		password: Option<StaticStr>,
+	},
+
+	/// Bearer token authentication, e.g. an access token or an API key
+	Bearer(StaticStr),
+}
+
+impl Request {
+	/// Creates a `GET` request without any body, headers, or authentication
+	#[must_use]
+	pub fn get() -> Self {
+		Self::default()
+	}
+
+	/// Creates a `POST` request with a JSON body
+	#[must_use]
+	pub fn post_json(json: Json) -> Self {
+		Self::builder().method(Method::POST).json(json).build()
+	}
+
+	/// Creates a [`reqwest::RequestBuilder`] for this request to the `url` using the `client`
+	pub(crate) fn to_reqwest(&self, client: &HttpClient, url: &Url) -> reqwest::RequestBuilder {
+		tracing::trace!(
+			"Making an HTTP {} request to {url:?} with {:#?}",
+			self.method,
+			self.body
+		);
+
+		let mut request = client
+			.as_reqwest()
+			.request(self.method.clone(), url.as_str());
+
+		let query = self
+			.query
+			.iter()
+			.map(|(key, value)| (key.as_str(), value.as_str()))
+			.collect::<Vec<_>>();
+
+		if !query.is_empty() {
+			request = request.query(&query);
+		}
+
+		for (name, value) in &self.headers {
+			request = request.header(name.as_str(), value.as_str());
+		}
+
+		match &self.auth {
+			Some(Auth::Basic { username, password }) => {
+				request = request.basic_auth(username, password.as_ref());
+			}
+			Some(Auth::Bearer(token)) => {
+				request = request.bearer_auth(token);
+			}
+			None => (),
+		}
+
+		match &self.body {
+			Some(Body::Json(json)) => request.json(json),
+			Some(Body::Form(fields)) => {
+				let fields = fields
+					.iter()
+					.map(|(key, value)| (key.as_str(), value.as_str()))
+					.collect::<Vec<_>>();
+
+				request.form(&fields)
+			}
+			Some(Body::Raw { content_type, data }) => {
+				if let Some(content_type) = content_type {
+					request = request.header(reqwest::header::CONTENT_TYPE, content_type.as_str());
+				}
+
+				request.body(data.clone())
+			}
+			None => request,
+		}
+	}
+}
+
+impl<S: request_builder::State> RequestBuilder<S> {
+	/// Adds a header to send with the request
+	pub fn header(mut self, name: impl Into<StaticStr>, value: impl Into<StaticStr>) -> Self {
+		self.headers.push((name.into(), value.into()));
+		self
+	}
+
+	/// Adds a query parameter to append to the URL
+	pub fn query(mut self, key: impl Into<StaticStr>, value: impl Into<StaticStr>) -> Self {
+		self.query.push((key.into(), value.into()));
+		self
+	}
+
+	/// Sets the body to a JSON payload
+	pub fn json(self, json: Json) -> RequestBuilder<request_builder::SetBody<S>>
+	where
+		S::Body: request_builder::IsUnset,
+	{
+		self.body(Body::Json(json))
+	}
+
+	/// Sets the body to url-encoded form fields
+	pub fn form<K, V>(
+		self,
+		fields: impl IntoIterator<Item = (K, V)>,
+	) -> RequestBuilder<request_builder::SetBody<S>>
+	where
+		S::Body: request_builder::IsUnset,
+		K: Into<StaticStr>,
+		V: Into<StaticStr>,
+	{
+		let fields = fields
+			.into_iter()
+			.map(|(key, value)| (key.into(), value.into()))
+			.collect();
+
+		self.body(Body::Form(fields))
+	}
+
+	/// Sets the body to a raw payload with an optional content type
+	pub fn raw(
+		self,
+		content_type: Option<StaticStr>,
+		data: impl Into<Vec<u8>>,
+	) -> RequestBuilder<request_builder::SetBody<S>>
+	where
+		S::Body: request_builder::IsUnset,
+	{
+		self.body(Body::Raw {
+			content_type,
+			data: data.into(),
+		})
+	}
+
+	/// Uses HTTP Basic authentication
+	pub fn basic_auth(
+		self,
+		username: impl Into<StaticStr>,
+		password: Option<StaticStr>,
+	) -> RequestBuilder<request_builder::SetAuth<S>>
+	where
+		S::Auth: request_builder::IsUnset,
+	{
+		self.auth(Auth::Basic {
+			username: username.into(),
+			password,
+		})
+	}
+
+	/// Uses Bearer token authentication
+	pub fn bearer_auth(
+		self,
+		token: impl Into<StaticStr>,
+	) -> RequestBuilder<request_builder::SetAuth<S>>
+	where
+		S::Auth: request_builder::IsUnset,
+	{
+		self.auth(Auth::Bearer(token.into()))
+	}
+}
+
+impl Debug for Auth {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		// don't leak the credentials into the logs
+		match self {
+			Self::Basic { username, .. } => f
+				.debug_struct("Basic")
+				.field("username", username)
+				.finish_non_exhaustive(),
+			Self::Bearer(_) => f.debug_tuple("Bearer").finish_non_exhaustive(),
+		}
+	}
+}
@@ -0,0 +1,369 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+
+//! This module contains the [`RecordReplay`] source wrapper that records the entries a source returns and replays them later
+
+use futures::{Stream, StreamExt, TryStreamExt, stream};
+use std::{
+	io,
+	path::{Path, PathBuf},
+	sync::{
+		Arc,
+		atomic::{AtomicUsize, Ordering},
+	},
+};
+
+use super::{Fetch, Source, error::SourceError};
+use crate::{
+	entry::{Entry, EntryId},
+	maybe_send::MaybeSend,
+	read_filter::MarkAsRead,
+};
+
+/// Records the entries returned by a source to a fixture directory, or replays them from it instead of fetching.
+///
+/// Every fetch is saved to or loaded from its own JSON file, numbered in the order of the fetches, e.g. `0000.json`, `0001.json`.
+/// This makes it possible to develop and test whole pipelines, e.g. a [`Task`](`crate::Task`) with this as the source,
+/// without hitting the network, and to get the same results every time.
+///
+/// In [`Mode::Replay`], the wrapped source is never used, and neither are entries marked as read in it.
+/// After all fixtures have been replayed, fetches return no entries.
+///
+/// If the source supports [fetching in batches](`Fetch::fetch_batches`), its batches are passed through in [`Mode::Record`]
+/// and all of them are saved to a single fixture once the last one has been fetched. It's then replayed at once
+///
+/// ```no_run
+/// # use fetcher::sources::{Fetch, RecordReplay, record_replay::Mode};
+/// # async fn f() {
+/// let mode = if std::env::var_os("RECORD").is_some() { Mode::Record } else { Mode::Replay };
+/// let mut source = RecordReplay::new("https://example.com".to_owned(), "tests/fixtures/example", mode);
+/// let entries = source.fetch().await.unwrap();
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct RecordReplay<F> {
+	/// The source to record the entries of
+	pub source: F,
+
+	/// The directory the fixtures are saved to and loaded from
+	pub dir: PathBuf,
+
+	/// Whether to record or replay
+	pub mode: Mode,
+
+	/// Number of the next fixture, shared with the streams of batches that are being recorded
+	fetch_num: Arc<AtomicUsize>,
+}
+
+/// Whether a [`RecordReplay`] records or replays entries
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Mode {
+	/// Fetch from the source and save the entries to a new fixture.
+	/// The fixtures of a previous recording are removed before the first one is saved
+	Record,
+
+	/// Load the entries from the next fixture instead of fetching from the source
+	Replay,
+}
+
+#[expect(missing_docs, reason = "error message is self-documenting")]
+#[derive(thiserror::Error, Debug)]
+pub enum FixtureError {
+	#[error("Can't create the fixture directory {}", .1.display())]
+	CreateDir(#[source] io::Error, PathBuf),
+
+	#[error("Can't read the fixture {}", .1.display())]
+	Read(#[source] io::Error, PathBuf),
+
+	#[error("Can't write the fixture {}", .1.display())]
+	Write(#[source] io::Error, PathBuf),
+
+	#[error("Can't remove the old fixtures in {}", .1.display())]
+	RemoveOld(#[source] io::Error, PathBuf),
+
+	#[error("Invalid fixture {}", .1.display())]
+	Invalid(#[source] serde_json::Error, PathBuf),
+}
+
+impl<F> RecordReplay<F> {
+	/// Wraps the `source`, saving the fixtures to or loading them from `dir`
+	pub fn new(source: F, dir: impl Into<PathBuf>, mode: Mode) -> Self {
+		Self {
+			source,
+			dir: dir.into(),
+			mode,
+			fetch_num: Arc::new(AtomicUsize::new(0)),
+		}
+	}
+}
+
+/// Loads the entries from the next fixture in the `dir`
+async fn replay(dir: &Path, fetch_num: &AtomicUsize) -> Result<Vec<Entry>, FixtureError> {
+	let path = fixture(dir, fetch_num.fetch_add(1, Ordering::Relaxed));
+
+	let json = match tokio::fs::read_to_string(&path).await {
+		Ok(json) => json,
+		Err(e) if e.kind() == io::ErrorKind::NotFound => {
+			tracing::debug!(
+				"All fixtures have been replayed, {} doesn't exist",
+				path.display()
+			);
+			return Ok(Vec::new());
+		}
+		Err(e) => return Err(FixtureError::Read(e, path)),
+	};
+
+	tracing::debug!("Replaying entries from {}", path.display());
+	serde_json::from_str(&json).map_err(|e| FixtureError::Invalid(e, path))
+}
+
+/// Path to the fixture of the fetch with the number `num`
+fn fixture(dir: &Path, num: usize) -> PathBuf {
+	dir.join(format!("{num:04}.json"))
+}
+
+/// Checks if the file is named like a fixture, e.g. `0042.json`
+fn is_fixture(path: &Path) -> bool {
+	path.extension().is_some_and(|ext| ext == "json")
+		&& path
+			.file_stem()
+			.and_then(|stem| stem.to_str())
+			.is_some_and(|stem| stem.len() >= 4 && stem.bytes().all(|b| b.is_ascii_digit()))
+}
+
+/// Removes all fixtures from the `dir`, leaving the other files alone,
+/// so that the ones recorded before aren't replayed after the ones being recorded now
+async fn remove_fixtures(dir: &Path) -> Result<(), FixtureError> {
+	let mut entries = tokio::fs::read_dir(dir)
+		.await
+		.map_err(|e| FixtureError::RemoveOld(e, dir.to_owned()))?;
+
+	while let Some(entry) = entries
+		.next_entry()
+		.await
+		.map_err(|e| FixtureError::RemoveOld(e, dir.to_owned()))?
+	{
+		let path = entry.path();
+		if !is_fixture(&path) {
+			continue;
+		}
+
+		tracing::trace!("Removing the old fixture {}", path.display());
+		tokio::fs::remove_file(&path)
+			.await
+			.map_err(|e| FixtureError::RemoveOld(e, path))?;
+	}
+
+	Ok(())
+}
+
+/// Saves the `entries` to the next fixture in the `dir`
+async fn record(
+	dir: &Path,
+	fetch_num: &AtomicUsize,
+	entries: &[Entry],
+) -> Result<(), FixtureError> {
+	tokio::fs::create_dir_all(dir)
+		.await
+		.map_err(|e| FixtureError::CreateDir(e, dir.to_owned()))?;
+
+	let num = fetch_num.fetch_add(1, Ordering::Relaxed);
+	if num == 0 {
+		remove_fixtures(dir).await?;
+	}
+
+	let path = fixture(dir, num);
+	tracing::debug!("Recording {} entries to {}", entries.len(), path.display());
+
+	let json = serde_json::to_string_pretty(entries)
+		.map_err(|e| FixtureError::Invalid(e, path.clone()))?;
+
+	tokio::fs::write(&path, json)
+		.await
+		.map_err(|e| FixtureError::Write(e, path))
+}
+
+impl<F> Fetch for RecordReplay<F>
+where
+	F: Fetch,
+{
+	type Err = SourceError;
+
+	async fn fetch(&mut self) -> Result<Vec<Entry>, Self::Err> {
+		match self.mode {
+			Mode::Record => {
+				let entries = self.source.fetch().await.map_err(Into::into)?;
+				record(&self.dir, &self.fetch_num, &entries).await?;
+
+				Ok(entries)
+			}
+			Mode::Replay => Ok(replay(&self.dir, &self.fetch_num).await?),
+		}
+	}
+
+	/// Passes the batches of the source through, recording all of them once the last one has been fetched if none of them have failed.
+	/// Returns `None` in [`Mode::Replay`] since the fixtures are replayed all at once
+	fn fetch_batches(
+		&mut self,
+	) -> Option<impl Stream<Item = Result<Vec<Entry>, Self::Err>> + MaybeSend + use<F>> {
+		if self.mode == Mode::Replay {
+			return None;
+		}
+
+		let batches = Box::pin(
+			self.source
+				.fetch_batches()?
+				.map_err(Into::<SourceError>::into),
+		);
+		let dir = self.dir.clone();
+		let fetch_num = Arc::clone(&self.fetch_num);
+
+		// the stream ends once the state is None
+		let state = Some((batches, Vec::new(), false));
+
+		Some(stream::unfold(state, move |state| {
+			let dir = dir.clone();
+			let fetch_num = Arc::clone(&fetch_num);
+
+			async move {
+				let (mut batches, mut recorded, mut failed) = state?;
+
+				match batches.next().await {
+					Some(Ok(batch)) => {
+						recorded.extend(batch.iter().cloned());
+						Some((Ok(batch), Some((batches, recorded, failed))))
+					}
+					Some(Err(e)) => {
+						failed = true;
+						Some((Err(e), Some((batches, recorded, failed))))
+					}
+					None if failed => None,
+					None => match record(&dir, &fetch_num, &recorded).await {
+						Ok(()) => None,
+						Err(e) => Some((Err(e.into()), None)),
+					},
+				}
+			}
+		}))
+	}
+}
+
+impl<F> MarkAsRead for RecordReplay<F>
+where
+	F: MarkAsRead,
+{
+	type Err = F::Err;
+
+	async fn mark_as_read(&mut self, id: &EntryId) -> Result<(), Self::Err> {
+		match self.mode {
+			Mode::Record => self.source.mark_as_read(id).await,
+			Mode::Replay => Ok(()),
+		}
+	}
+
+	async fn set_read_only(&mut self) {
+		self.source.set_read_only().await;
+	}
+}
+
+impl<S> Source for RecordReplay<S> where S: Source {}
+
+#[cfg(test)]
+mod tests {
+	use std::num::NonZeroUsize;
+
+	use super::*;
+	use crate::{
+		sinks::message::Message,
+		sources::FetchStream,
+		test_utils::{Entries, TempDir, contents},
+	};
+
+	/// Returns an entry with the number of the fetch
+	struct Counter(u32);
+
+	impl Fetch for Counter {
+		type Err = std::convert::Infallible;
+
+		async fn fetch(&mut self) -> Result<Vec<Entry>, Self::Err> {
+			self.0 += 1;
+
+			Ok(vec![
+				Entry::builder()
+					.id(self.0.to_string())
+					.raw_contents(format!("fetch #{}", self.0))
+					.msg(Message::builder().title("title".to_owned()))
+					.build(),
+			])
+		}
+	}
+
+	#[tokio::test]
+	async fn replays_recorded_fetches() {
+		let temp_dir = TempDir::new("record-replay-replays_recorded_fetches");
+		let dir = temp_dir.path();
+
+		let mut recorder = RecordReplay::new(Counter(0), dir, Mode::Record);
+		let first = recorder.fetch().await.unwrap();
+		let second = recorder.fetch().await.unwrap();
+
+		let mut replayer = RecordReplay::new(Counter(100), dir, Mode::Replay);
+		assert_eq!(replayer.fetch().await.unwrap(), first);
+		assert_eq!(replayer.fetch().await.unwrap(), second);
+		assert!(replayer.fetch().await.unwrap().is_empty());
+		assert_eq!(
+			replayer.source.0, 100,
+			"the source shouldn't be used when replaying"
+		);
+	}
+
+	#[tokio::test]
+	async fn rerecording_removes_old_fixtures() {
+		let temp_dir = TempDir::new("record-replay-rerecording_removes_old_fixtures");
+		let dir = temp_dir.path();
+
+		let mut recorder = RecordReplay::new(Counter(0), dir, Mode::Record);
+		for _ in 0..3 {
+			recorder.fetch().await.unwrap();
+		}
+
+		std::fs::write(dir.join("notes.txt"), "keep me").unwrap();
+
+		let mut recorder = RecordReplay::new(Counter(100), dir, Mode::Record);
+		let rerecorded = recorder.fetch().await.unwrap();
+
+		let mut replayer = RecordReplay::new((), dir, Mode::Replay);
+		assert_eq!(replayer.fetch().await.unwrap(), rerecorded);
+		assert!(
+			replayer.fetch().await.unwrap().is_empty(),
+			"old fixtures shouldn't be replayed after the new ones"
+		);
+		assert!(dir.join("notes.txt").exists());
+	}
+
+	#[tokio::test]
+	async fn records_batches_to_a_single_fixture() {
+		let temp_dir = TempDir::new("record-replay-records_batches_to_a_single_fixture");
+		let dir = temp_dir.path();
+
+		let mut recorder = RecordReplay::new(
+			Entries(vec![Ok("1"), Ok("2")]).batched(NonZeroUsize::MIN),
+			dir,
+			Mode::Record,
+		);
+		let batches = recorder
+			.fetch_batches()
+			.unwrap()
+			.try_collect::<Vec<_>>()
+			.await
+			.unwrap();
+		assert_eq!(batches.len(), 2);
+
+		let mut replayer = RecordReplay::new((), dir, Mode::Replay);
+		assert!(replayer.fetch_batches().is_none());
+		assert_eq!(contents(&replayer.fetch().await.unwrap()), ["1", "2"]);
+	}
+}
@@ -4,45 +4,109 @@
  * file, You can obtain one at https://mozilla.org/MPL/2.0/.
  */
 
-//! This module contains the [`Reddit`] subbreddit API source
+//! This module contains the [`Reddit`] subbreddit and user API source
 
 use super::Fetch;
 use crate::{
-	entry::Entry,
+	entry::{Entry, EntryId},
 	sinks::message::{Media, Message},
 };
 
 use non_non_full::NonEmptyVec;
 use roux::{
-	Subreddit,
-	util::{FeedOption, TimePeriod},
+	Subreddit, User,
+	comment::CommentData,
+	submission::SubmissionData,
+	util::{FeedOption, RouxError, TimePeriod},
 };
-use std::{ffi::OsStr, fmt::Debug, path::Path};
+use std::{
+	collections::{HashSet, VecDeque},
+	ffi::OsStr,
+	fmt::Debug,
+	path::Path,
+};
+
+/// How many posts to fetch from each feed by default
+pub const DEFAULT_MAX_ITEMS: usize = 100;
 
-/// Source that fetches posts from a subreddit using the Reddit API
+/// The most posts the Reddit API returns in a single page
+const PAGE_SIZE: usize = 100;
+
+/// Source that fetches posts from subreddits and users' submissions using the Reddit API
+///
+/// Posts of all feeds are returned one after another, in the order the feeds were added.
+/// If [`Reddit::top_comments`] is set, each post is followed by its top comments
+/// that have the post as their [`Entry::reply_to`], so that a discussion can be mirrored as a thread.
+///
+/// ```
+/// # use fetcher::sources::reddit::{Reddit, Sort};
+/// let reddit = Reddit::builder()
+///     .subreddit("rust")
+///     .subreddit("programming")
+///     .user("spez")
+///     .sort(Sort::TopDay)
+///     .max_items(250)
+///     .top_comments(3)
+///     .build()
+///     .expect("feeds have been added");
+/// ```
+#[derive(bon::Builder)]
+#[builder(finish_fn(name = "build_internal", vis = ""))]
+#[builder(builder_type(doc {
+/// Use builder syntax to add at least one subreddit or user and finish with [`build()`](`RedditBuilder::build()`)
+}))]
 pub struct Reddit {
-	/// Sorting algorithm
+	/// Subreddits and users to fetch posts from
+	#[builder(field)]
+	feeds: Vec<Feed>,
+
+	/// Sorting algorithm of subreddits. Users' submissions are always sorted from the newest
+	#[builder(default)]
 	pub sort: Sort,
+
 	/// If score of a post is below this threshold, it gets skipped
 	pub score_threshold: Option<u32>,
-	subreddit: Subreddit,
+
+	/// How many posts to fetch from each feed, following the pages of the feed if there are more than fit into a single one.
+	/// Defaults to [`DEFAULT_MAX_ITEMS`]
+	#[builder(default = DEFAULT_MAX_ITEMS)]
+	pub max_items: usize,
+
+	/// How many of the top comments of each post to fetch as well. No comments are fetched if `None`.
+	///
+	/// Note that this makes an additional request for every post.
+	/// That's why they are only fetched the first time a post is fetched and not again on the following fetches
+	pub top_comments: Option<u32>,
+
+	/// IDs of the posts whose top comments have already been fetched, the most recently fetched ones last.
+	///
+	/// Twice as many posts as a single fetch can return are remembered,
+	/// so that a post that briefly drops out of a feed doesn't get its comments fetched again when it comes back
+	#[builder(skip)]
+	commented: VecDeque<String>,
 }
 
 #[expect(missing_docs, reason = "error message is self-documenting")]
 #[derive(thiserror::Error, Debug)]
 pub enum RedditError {
 	#[error(transparent)]
-	Reddit(#[from] roux::util::RouxError),
+	Reddit(#[from] RouxError),
 }
 
+#[expect(missing_docs, reason = "error message is self-documenting")]
+#[derive(thiserror::Error, Debug)]
+#[error("No subreddits or users to fetch posts from")]
+pub struct NoFeedsError;
+
 /// Sorting algorithm
-#[derive(Debug)]
+#[derive(Clone, Copy, Default, Debug)]
 pub enum Sort {
 	/// Latest/New
 	Latest,
 	/// Rising
 	Rising,
 	/// Hot
+	#[default]
 	Hot,
 	/// Top of the day
 	TopDay,
@@ -56,120 +120,314 @@ pub enum Sort {
 	TopAllTime,
 }
 
+/// A feed of posts
+enum Feed {
+	Subreddit(Subreddit),
+	User(User),
+}
+
 impl Reddit {
-	/// Creates a new [`Reddit`] source.
-	// TODO: support users
+	/// Creates a new [`Reddit`] source that fetches posts from a single subreddit.
+	///
+	/// Use [`Reddit::builder`] to fetch from several subreddits or users
 	#[must_use]
 	pub fn new(subreddit: &str, sort: Sort, score_threshold: Option<u32>) -> Self {
-		Self {
-			sort,
-			score_threshold,
-			subreddit: Subreddit::new(subreddit),
+		Self::builder()
+			.subreddit(subreddit)
+			.sort(sort)
+			.maybe_score_threshold(score_threshold)
+			.build_internal()
+	}
+
+	/// Fetches the posts of the `feed` and the top comments of each of them that haven't been fetched before,
+	/// adding the IDs of the posts to `fetched`
+	async fn fetch_feed(
+		&self,
+		feed: &Feed,
+		fetched: &mut HashSet<String>,
+	) -> Result<Vec<Entry>, RouxError> {
+		let posts = paginate(self.max_items, |options| async {
+			match feed {
+				Feed::Subreddit(subreddit) => self.sort.fetch(subreddit, options).await,
+				Feed::User(user) => user.submitted(Some(options)).await,
+			}
+		})
+		.await?;
+
+		let mut entries = Vec::new();
+		for post in posts {
+			if let Some(score_threshold) = self.score_threshold
+				&& post.score < score_threshold.into()
+			{
+				continue;
+			}
+
+			let comments = match self.top_comments {
+				Some(count) if !self.commented.contains(&post.id) => {
+					top_comments(feed, &post, count).await?
+				}
+				_ => Vec::new(),
+			};
+			fetched.insert(post.id.clone());
+
+			let post_id = EntryId::new(post.id.clone());
+			entries.push(post_to_entry(post));
+			entries.extend(
+				comments
+					.into_iter()
+					.filter_map(|comment| comment_to_entry(comment, post_id.clone())),
+			);
+		}
+
+		Ok(entries)
+	}
+
+	/// Moves the `fetched` posts to the back of [`Reddit::commented`] and forgets the oldest ones over the limit
+	fn remember_commented(&mut self, fetched: HashSet<String>) {
+		let max_len = 2 * self.max_items * self.feeds.len();
+
+		self.commented.retain(|id| !fetched.contains(id));
+		self.commented.extend(fetched);
+
+		while self.commented.len() > max_len {
+			self.commented.pop_front();
 		}
 	}
 }
 
+impl<S: reddit_builder::State> RedditBuilder<S> {
+	/// Fetch posts from the `subreddit`, sorted by [`Reddit::sort`]
+	pub fn subreddit(mut self, subreddit: &str) -> Self {
+		self.feeds.push(Feed::Subreddit(Subreddit::new(subreddit)));
+		self
+	}
+
+	/// Fetch the posts the `user` has submitted
+	pub fn user(mut self, user: &str) -> Self {
+		self.feeds.push(Feed::User(User::new(user)));
+		self
+	}
+
+	/// Finishes building the source
+	///
+	/// # Errors
+	/// if no subreddits or users have been added
+	pub fn build(self) -> Result<Reddit, NoFeedsError>
+	where
+		S: reddit_builder::IsComplete,
+	{
+		if self.feeds.is_empty() {
+			return Err(NoFeedsError);
+		}
+
+		Ok(self.build_internal())
+	}
+}
+
 impl Fetch for Reddit {
 	type Err = RedditError;
 
-	/// Fetches all posts from a subreddit
+	/// Fetches all posts from all subreddits and users
 	///
 	/// # Errors
 	/// This function may error if the network connection is down, or Reddit API returns a bad or garbage responce
 	async fn fetch(&mut self) -> Result<Vec<Entry>, Self::Err> {
-		let s = &self.subreddit;
-		macro_rules! top_in {
-			($tp:tt) => {
-				s.top(
-					100,
-					Some(FeedOption::new().limit(100).period(TimePeriod::$tp)),
-				)
-				.await
-			};
+		let mut entries = Vec::new();
+		let mut fetched = HashSet::new();
+
+		for feed in &self.feeds {
+			entries.extend(self.fetch_feed(feed, &mut fetched).await?);
 		}
-		let posts = match self.sort {
-			Sort::Latest => s.latest(100, None).await,
-			Sort::Rising => s.rising(100, None).await,
-			Sort::Hot => s.hot(100, None).await,
-			Sort::TopDay => top_in!(Today),
-			Sort::TopWeek => top_in!(ThisWeek),
-			Sort::TopMonth => top_in!(ThisMonth),
-			Sort::TopYear => top_in!(ThisYear),
-			Sort::TopAllTime => top_in!(AllTime),
-		}?;
-
-		let entries = posts
-			.data
-			.children
-			.into_iter()
-			.filter_map(|post| {
-				let post = post.data;
-
-				if let Some(score_threshold) = self.score_threshold {
-					if post.score < score_threshold.into() {
-						return None;
-					}
-				}
 
-				let link = post.url;
-				let is_picture = is_picture(link.as_deref());
-				let is_video = is_video(link.as_deref());
-
-				// TODO: why did I check for is_picture again?
-				let mut body = match (post.is_self, is_picture, &link) {
-					(true, _, _) => post.selftext,
-					(_, false, Some(link)) => link.clone(),
-					_ => String::new(),
-				};
-
-				body.insert_str(0, &format!("Score: {}\n\n", post.score));
-
-				let media = if is_picture {
-					let url = link.expect(
-						"should contain a valid picture url since we confirmed it with is_picture",
-					);
-
-					Some(NonEmptyVec::with_first(Media::Photo(url)))
-				} else if is_video {
-					let url = link.expect(
-						"should contain a valid picture url since we confirmed it with is_video",
-					);
-
-					Some(NonEmptyVec::with_first(Media::Video(url)))
-				} else {
-					None
-				};
-
-				let link = format!("https://reddit.com/{}", post.permalink);
-
-				let entry = Entry::builder()
-					.id(post.id)
-					.msg(
-						Message::builder()
-							.title(post.title)
-							.body(body)
-							.link(link)
-							.maybe_media(media),
-					)
-					.build();
-
-				Some(entry)
-			})
-			.collect::<_>();
+		self.remember_commented(fetched);
+
 		Ok(entries)
 	}
 }
 
+impl Sort {
+	/// Fetches a single page of the `subreddit` sorted by this sorting algorithm
+	async fn fetch(
+		self,
+		subreddit: &Subreddit,
+		options: FeedOption,
+	) -> Result<roux::Submissions, RouxError> {
+		let limit = options.limit.unwrap_or_default();
+		let top_in = |period| Some(options.clone().period(period));
+
+		match self {
+			Self::Latest => subreddit.latest(limit, Some(options)).await,
+			Self::Rising => subreddit.rising(limit, Some(options)).await,
+			Self::Hot => subreddit.hot(limit, Some(options)).await,
+			Self::TopDay => subreddit.top(limit, top_in(TimePeriod::Today)).await,
+			Self::TopWeek => subreddit.top(limit, top_in(TimePeriod::ThisWeek)).await,
+			Self::TopMonth => subreddit.top(limit, top_in(TimePeriod::ThisMonth)).await,
+			Self::TopYear => subreddit.top(limit, top_in(TimePeriod::ThisYear)).await,
+			Self::TopAllTime => subreddit.top(limit, top_in(TimePeriod::AllTime)).await,
+		}
+	}
+}
+
+/// Fetches pages using `fetch_page` until `max_items` posts have been fetched or there are no more pages left
+async fn paginate<F, Fut>(
+	max_items: usize,
+	mut fetch_page: F,
+) -> Result<Vec<SubmissionData>, RouxError>
+where
+	F: FnMut(FeedOption) -> Fut,
+	Fut: Future<Output = Result<roux::Submissions, RouxError>>,
+{
+	let mut posts = Vec::new();
+	let mut after: Option<String> = None;
+
+	while posts.len() < max_items {
+		let limit = (max_items - posts.len()).min(PAGE_SIZE);
+		let mut options =
+			FeedOption::new().limit(u32::try_from(limit).expect("page size should fit into u32"));
+		if let Some(after) = &after {
+			options = options.after(after);
+		}
+
+		let page = fetch_page(options).await?.data;
+		if page.children.is_empty() {
+			break;
+		}
+
+		// the API may return a bit more than requested
+		posts.extend(page.children.into_iter().take(limit).map(|post| post.data));
+
+		match page.after {
+			Some(next) => after = Some(next),
+			None => break,
+		}
+	}
+
+	Ok(posts)
+}
+
+/// Fetches up to `count` top-level comments of the `post`, skipping stickied ones, e.g. by moderator bots
+async fn top_comments(
+	feed: &Feed,
+	post: &SubmissionData,
+	count: u32,
+) -> Result<Vec<CommentData>, RouxError> {
+	let comments = match feed {
+		Feed::Subreddit(subreddit) => {
+			subreddit
+				.article_comments(&post.id, Some(1), Some(count))
+				.await?
+		}
+		// users' posts may be in any subreddit
+		Feed::User(_) => {
+			Subreddit::new(&post.subreddit)
+				.article_comments(&post.id, Some(1), Some(count))
+				.await?
+		}
+	};
+
+	Ok(comments
+		.data
+		.children
+		.into_iter()
+		// "more" is a placeholder for comments that haven't been loaded
+		.filter(|comment| comment.kind.as_deref() == Some("t1"))
+		.map(|comment| comment.data)
+		.filter(|comment| comment.stickied != Some(true))
+		.take(count as usize)
+		.collect())
+}
+
+fn post_to_entry(post: SubmissionData) -> Entry {
+	let link = post.url;
+	let is_picture = is_picture(link.as_deref());
+	let is_video = is_video(link.as_deref());
+
+	// TODO: why did I check for is_picture again?
+	let mut body = match (post.is_self, is_picture, &link) {
+		(true, _, _) => post.selftext,
+		(_, false, Some(link)) => link.clone(),
+		_ => String::new(),
+	};
+
+	body.insert_str(0, &format!("Score: {}\n\n", post.score));
+
+	let media = if is_picture {
+		let url =
+			link.expect("should contain a valid picture url since we confirmed it with is_picture");
+
+		Some(NonEmptyVec::with_first(Media::Photo(url)))
+	} else if is_video {
+		let url =
+			link.expect("should contain a valid picture url since we confirmed it with is_video");
+
+		Some(NonEmptyVec::with_first(Media::Video(url)))
+	} else {
+		None
+	};
+
+	let link = format!("https://reddit.com/{}", post.permalink);
+
+	Entry::builder()
+		.id(post.id)
+		.msg(
+			Message::builder()
+				.title(post.title)
+				.body(body)
+				.link(link)
+				.maybe_media(media),
+		)
+		.build()
+}
+
+/// Converts the comment to an entry replying to the post with `post_id`.
+///
+/// The ID of the entry is the fullname of the comment, e.g. `t1_abc`, since IDs of posts and comments may overlap.
+/// Returns `None` if the comment has been deleted or is missing its fullname
+fn comment_to_entry(comment: CommentData, post_id: Option<EntryId>) -> Option<Entry> {
+	let id = comment.name?;
+	let body = comment.body?;
+	let author = comment.author.unwrap_or_else(|| "[deleted]".to_owned());
+	let score = comment.score.unwrap_or_default();
+
+	let entry = Entry::builder()
+		.id(id)
+		.maybe_reply_to(post_id)
+		.msg(
+			Message::builder()
+				.body(format!("u/{author}, score: {score}\n\n{body}"))
+				.maybe_link(
+					comment
+						.permalink
+						.map(|permalink| format!("https://reddit.com/{permalink}")),
+				),
+		)
+		.build();
+
+	Some(entry)
+}
+
 impl Debug for Reddit {
 	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
 		f.debug_struct("Reddit")
-			.field("subreddit", &self.subreddit.name)
+			.field("feeds", &self.feeds)
 			.field("sort", &self.sort)
 			.field("score_threshold", &self.score_threshold)
+			.field("max_items", &self.max_items)
+			.field("top_comments", &self.top_comments)
+			.field("commented", &self.commented)
 			.finish()
 	}
 }
 
+impl Debug for Feed {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match self {
+			Self::Subreddit(subreddit) => write!(f, "r/{}", subreddit.name),
+			Self::User(user) => write!(f, "u/{}", user.user),
+		}
+	}
+}
+
 fn is_picture(url: Option<&str>) -> bool {
 	let Some(url) = url else {
 		return false;
@@ -191,3 +449,149 @@ fn is_video(url: Option<&str>) -> bool {
 		.extension()
 		.is_some_and(|ext| video_extensions.contains(&ext))
 }
+
+#[cfg(test)]
+mod tests {
+	use assert_matches::assert_matches;
+	use serde_json::json;
+
+	use super::*;
+
+	fn post(id: &str, score: u32) -> serde_json::Value {
+		json!({
+			"kind": "t3",
+			"data": {
+				"subreddit": "rust",
+				"selftext": "Hello",
+				"id": id,
+				"gilded": 0,
+				"archived": false,
+				"clicked": false,
+				"author": "ferris",
+				"score": score,
+				"over_18": false,
+				"spoiler": false,
+				"hidden": false,
+				"num_comments": 0,
+				"thumbnail": "self",
+				"subreddit_id": "t5_2s7lj",
+				"hide_score": false,
+				"edited": false,
+				"downs": 0,
+				"ups": score,
+				"upvote_ratio": 1.0,
+				"saved": false,
+				"stickied": false,
+				"is_self": true,
+				"permalink": format!("/r/rust/comments/{id}/post/"),
+				"locked": false,
+				"name": format!("t3_{id}"),
+				"created": 0.0,
+				"url": null,
+				"quarantine": false,
+				"title": format!("Post {id}"),
+				"created_utc": 0.0,
+				"visited": false,
+			}
+		})
+	}
+
+	fn page(ids: &[&str], after: Option<&str>) -> roux::Submissions {
+		serde_json::from_value(json!({
+			"kind": "Listing",
+			"data": {
+				"after": after,
+				"children": ids.iter().map(|id| post(id, 1)).collect::<Vec<_>>(),
+			}
+		}))
+		.unwrap()
+	}
+
+	#[tokio::test]
+	async fn follows_pages_until_max_items() {
+		let mut requests = Vec::new();
+
+		let posts = paginate(250, |options| {
+			requests.push((options.limit, options.after.clone()));
+
+			let page = match options.after.as_deref() {
+				None => page(&["a"; 100], Some("t3_a")),
+				Some("t3_a") => page(&["b"; 100], Some("t3_b")),
+				_ => page(&["c"; 100], Some("t3_c")),
+			};
+
+			async { Ok(page) }
+		})
+		.await
+		.unwrap();
+
+		assert_eq!(posts.len(), 250);
+		assert_eq!(
+			requests,
+			[
+				(Some(100), None),
+				(Some(100), Some("t3_a".to_owned())),
+				(Some(50), Some("t3_b".to_owned())),
+			]
+		);
+	}
+
+	#[tokio::test]
+	async fn stops_at_the_last_page() {
+		let posts = paginate(250, |options| {
+			let page = match options.after {
+				None => page(&["a", "b"], Some("t3_b")),
+				Some(_) => page(&["c"], None),
+			};
+
+			async { Ok(page) }
+		})
+		.await
+		.unwrap();
+
+		let ids = posts.into_iter().map(|post| post.id).collect::<Vec<_>>();
+		assert_eq!(ids, ["a", "b", "c"]);
+	}
+
+	#[test]
+	fn requires_a_feed() {
+		assert_matches!(Reddit::builder().build(), Err(NoFeedsError));
+		assert_matches!(Reddit::builder().user("spez").build(), Ok(_));
+	}
+
+	#[test]
+	fn remembers_recently_commented_posts() {
+		let mut reddit = Reddit::builder().user("spez").max_items(1).build().unwrap();
+
+		for id in ["a", "b", "c", "b"] {
+			reddit.remember_commented(HashSet::from([id.to_owned()]));
+		}
+
+		assert_eq!(reddit.commented, ["c", "b"]);
+	}
+
+	#[test]
+	fn comments_reply_to_their_post() {
+		let post: SubmissionData = serde_json::from_value(post("abc", 42)["data"].clone()).unwrap();
+		let post_entry = post_to_entry(post);
+
+		let comment: CommentData = serde_json::from_value(json!({
+			"id": "def",
+			"name": "t1_def",
+			"author": "ferris",
+			"score": 7,
+			"body": "Nice",
+			"permalink": "/r/rust/comments/abc/post/def/",
+		}))
+		.unwrap();
+		let comment = comment_to_entry(comment, post_entry.id.clone()).unwrap();
+
+		assert_eq!(post_entry.id.as_ref().map(EntryId::as_str), Some("abc"));
+		assert_eq!(comment.id.as_ref().map(EntryId::as_str), Some("t1_def"));
+		assert_eq!(comment.reply_to, post_entry.id);
+		assert_eq!(
+			comment.msg.body.as_deref(),
+			Some("u/ferris, score: 7\n\nNice")
+		);
+	}
+}
@@ -0,0 +1,796 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+
+//! This module contains the [`Sqlite`] source that creates an entry out of every row a query returns
+
+use futures::{Stream, StreamExt, TryStreamExt, future, stream};
+use rusqlite::{
+	Connection, OpenFlags, Row, Statement,
+	types::{Value, ValueRef},
+};
+use serde::{Deserialize, Serialize};
+use std::{
+	collections::HashMap,
+	convert::Infallible,
+	path::{Path, PathBuf},
+	sync::{Arc, Mutex, PoisonError},
+};
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::ReceiverStream;
+
+use super::{Fetch, FetchStream, MarkAsRead, Source};
+use crate::{
+	StaticStr,
+	entry::{Entry, EntryId},
+	external_save::{ExternalSave, ExternalSaveError},
+	maybe_send::MaybeSend,
+	sinks::message::Message,
+};
+
+/// Name of the parameter the last seen ID is bound to
+pub const LAST_SEEN_ID_PARAM: &str = ":last_seen_id";
+
+/// Most rows that are read ahead of the ones that have been returned when fetching as a stream
+const ROWS_AHEAD: usize = 100;
+
+/// A source that runs an SQL query against an SQLite database and creates an [`Entry`] out of every returned row.
+///
+/// The query may contain a [`:last_seen_id`](`LAST_SEEN_ID_PARAM`) parameter
+/// which is bound to the value of the ID column of the last row whose entry has been [marked as read](`MarkAsRead`),
+/// or to the last seen ID passed to the [builder](`Sqlite::builder`) before anything has been marked as read.
+/// That way rows whose entries haven't been processed, e.g. because a sink has failed, are fetched again.
+/// Use the source directly as the source of a task rather than with a read filter for it to be marked as read.
+/// This keeps the query incremental, e.g.
+///
+/// ```sql
+/// SELECT id, title, text FROM alerts
+/// WHERE :last_seen_id IS NULL OR id > :last_seen_id
+/// ORDER BY id
+/// ```
+///
+/// The database is opened read-only on every fetch.
+/// The last seen ID is only kept in memory unless [`Sqlite::with_external_save`] is used.
+///
+/// The source also implements [`FetchStream`] to return the entries as the rows are read,
+/// e.g. to process the results of a large query [in batches](`FetchStream::batched`)
+#[expect(clippy::doc_markdown, reason = "false positive")]
+pub struct Sqlite<E = Infallible> {
+	/// Path to the database file
+	pub path: PathBuf,
+
+	/// The query to run. May use the [`LAST_SEEN_ID_PARAM`] parameter
+	pub query: StaticStr,
+
+	/// How a row is turned into an entry
+	pub mapping: RowMapping,
+
+	last_seen_id: Option<Value>,
+
+	/// Values of the ID column of the fetched rows by the IDs of their entries until they are marked as read.
+	/// Shared with the streams of rows that are being fetched
+	unread: Arc<Mutex<HashMap<EntryId, Value>>>,
+
+	/// Don't advance the last seen ID when entries are marked as read
+	read_only: bool,
+
+	external_save: Option<E>,
+}
+
+/// The last seen ID of an [`Sqlite`] source that is saved externally
+#[derive(Serialize, Deserialize, PartialEq, Clone, Debug)]
+pub struct SqliteState {
+	/// The value of the ID column of the last row that has been marked as read
+	#[serde(with = "ValueDef")]
+	pub last_seen_id: Value,
+}
+
+/// Serializes a [`Value`] which doesn't implement serde traits itself
+#[derive(Serialize, Deserialize)]
+#[serde(remote = "Value")]
+enum ValueDef {
+	Null,
+	Integer(i64),
+	Real(f64),
+	Text(String),
+	Blob(Vec<u8>),
+}
+
+/// How a row of the result is turned into an [`Entry`]
+#[derive(Clone, Debug)]
+pub enum RowMapping {
+	/// Map the columns with these names to the fields of the entry
+	Columns(Columns),
+
+	/// Serialize the whole row as a JSON object with the column names as keys into [`Entry::raw_contents`]
+	Json {
+		/// Name of the column that contains the ID of the entry
+		id: Option<StaticStr>,
+	},
+}
+
+/// Names of the columns that are mapped to the fields of the [`Entry`]. Fields without a column are left empty
+#[derive(bon::Builder, Clone, Default, Debug)]
+#[builder(on(StaticStr, into))]
+pub struct Columns {
+	/// Column for [`Entry::id`]. Its value is also remembered as the last seen ID
+	pub id: Option<StaticStr>,
+
+	/// Column for [`Message::title`]
+	pub title: Option<StaticStr>,
+
+	/// Column for [`Message::body`]
+	pub body: Option<StaticStr>,
+
+	/// Column for [`Message::link`]
+	pub link: Option<StaticStr>,
+
+	/// Column for [`Entry::raw_contents`]
+	pub raw_contents: Option<StaticStr>,
+}
+
+#[expect(missing_docs, reason = "error message is self-documenting")]
+#[derive(thiserror::Error, Debug)]
+pub enum SqliteError {
+	#[error("Can't open database {}", .1.display())]
+	Open(#[source] rusqlite::Error, PathBuf),
+
+	#[error("Failed to run the query")]
+	Query(#[source] rusqlite::Error),
+
+	#[error("The query didn't return the column {0:?}")]
+	ColumnNotFound(StaticStr),
+}
+
+/// Indices of the [`Columns`] in the result
+struct ColumnIndices {
+	id: Option<usize>,
+	title: Option<usize>,
+	body: Option<usize>,
+	link: Option<usize>,
+	raw_contents: Option<usize>,
+}
+
+#[bon::bon]
+impl Sqlite {
+	/// Creates a new [`Sqlite`] source using the builder syntax
+	#[builder]
+	pub fn new(
+		/// Path to the database file
+		#[builder(into)]
+		path: PathBuf,
+
+		/// The query to run. May use the [`LAST_SEEN_ID_PARAM`] parameter
+		#[builder(into)]
+		query: StaticStr,
+
+		/// How a row is turned into an entry
+		#[builder(default)]
+		mapping: RowMapping,
+
+		/// The value of the ID column of the last row that has been marked as read.
+		/// Bound as `NULL` if nothing has been marked as read yet
+		#[builder(into)]
+		last_seen_id: Option<Value>,
+	) -> Self {
+		Self {
+			path,
+			query,
+			mapping,
+			last_seen_id,
+			unread: Arc::default(),
+			read_only: false,
+			external_save: None,
+		}
+	}
+}
+
+impl Sqlite {
+	/// Continues from the previously saved `state`, if there is one.
+	///
+	/// [`ExternalSave::save_source_state`] is called with the new [`SqliteState`] every time an entry has been marked as read.
+	/// The key is the path to the database and the query, e.g. `/srv/alerts.db: SELECT ...`
+	pub fn with_external_save<E>(self, state: Option<SqliteState>, external_save: E) -> Sqlite<E>
+	where
+		E: ExternalSave,
+	{
+		let last_seen_id = state.map(|state| state.last_seen_id).or(self.last_seen_id);
+
+		Sqlite {
+			path: self.path,
+			query: self.query,
+			mapping: self.mapping,
+			last_seen_id,
+			unread: self.unread,
+			read_only: self.read_only,
+			external_save: Some(external_save),
+		}
+	}
+}
+
+impl<E> Sqlite<E> {
+	/// Returns the value of the ID column of the last row that has been marked as read, if any
+	#[must_use]
+	pub fn last_seen_id(&self) -> Option<&Value> {
+		self.last_seen_id.as_ref()
+	}
+
+	/// Runs the query once the stream is polled and returns the entries as the rows are read,
+	/// remembering the IDs of their rows until they are marked as read
+	fn rows(&self) -> impl Stream<Item = Result<Entry, SqliteError>> + MaybeSend + use<E> {
+		tracing::debug!("Querying SQLite database {}", self.path.display());
+
+		let path = self.path.clone();
+		let query = self.query.clone();
+		let mapping = self.mapping.clone();
+		let last_seen_id = self.last_seen_id.clone();
+		let unread = Arc::clone(&self.unread);
+
+		// the entries of the previous fetch that haven't been marked as read are fetched again
+		unread
+			.lock()
+			.unwrap_or_else(PoisonError::into_inner)
+			.clear();
+
+		stream::once(async move {
+			let (tx, rx) = mpsc::channel(ROWS_AHEAD);
+
+			let query = tokio::task::spawn_blocking(move || {
+				// stop reading the rows if nobody needs them anymore
+				let res = query_entries(&path, &query, &mapping, last_seen_id, |row| {
+					tx.blocking_send(Ok(row)).is_ok()
+				});
+
+				if let Err(e) = res {
+					_ = tx.blocking_send(Err(e));
+				}
+			});
+
+			let finished = stream::once(async move {
+				if let Err(e) = query.await {
+					std::panic::resume_unwind(e.into_panic());
+				}
+
+				None
+			})
+			.filter_map(future::ready);
+
+			ReceiverStream::new(rx).chain(finished)
+		})
+		.flatten()
+		.map_ok(move |(entry, row_id)| {
+			if let (Some(entry_id), Some(row_id)) = (&entry.id, row_id) {
+				unread
+					.lock()
+					.unwrap_or_else(PoisonError::into_inner)
+					.insert(entry_id.clone(), row_id);
+			}
+
+			entry
+		})
+	}
+}
+
+impl<E> Fetch for Sqlite<E>
+where
+	E: ExternalSave,
+{
+	type Err = SqliteError;
+
+	async fn fetch(&mut self) -> Result<Vec<Entry>, Self::Err> {
+		self.rows().try_collect().await
+	}
+}
+
+impl<E> FetchStream for Sqlite<E>
+where
+	E: ExternalSave,
+{
+	type Err = SqliteError;
+
+	/// Returns the entries as the rows are read
+	fn fetch_stream(
+		&mut self,
+	) -> impl Stream<Item = Result<Entry, Self::Err>> + MaybeSend + use<E> {
+		self.rows()
+	}
+}
+
+impl<E> MarkAsRead for Sqlite<E>
+where
+	E: ExternalSave,
+{
+	type Err = ExternalSaveError;
+
+	/// Advances the last seen ID to the one of the row of the entry and saves it externally, if enabled
+	async fn mark_as_read(&mut self, id: &EntryId) -> Result<(), Self::Err> {
+		if self.read_only {
+			return Ok(());
+		}
+
+		let Some(row_id) = self
+			.unread
+			.lock()
+			.unwrap_or_else(PoisonError::into_inner)
+			.remove(id)
+		else {
+			return Ok(());
+		};
+
+		if let Some(external_save) = &mut self.external_save {
+			let key = format!("{}: {}", self.path.display(), self.query);
+			let state = SqliteState {
+				last_seen_id: row_id.clone(),
+			};
+
+			external_save.save_source_state(&key, &state).await?;
+		}
+
+		self.last_seen_id = Some(row_id);
+		Ok(())
+	}
+
+	async fn set_read_only(&mut self) {
+		self.read_only = true;
+	}
+}
+
+impl<E> Source for Sqlite<E> where E: ExternalSave {}
+
+impl RowMapping {
+	fn id_column(&self) -> Option<&StaticStr> {
+		match self {
+			Self::Columns(columns) => columns.id.as_ref(),
+			Self::Json { id } => id.as_ref(),
+		}
+	}
+}
+
+impl Default for RowMapping {
+	fn default() -> Self {
+		Self::Json { id: None }
+	}
+}
+
+/// Runs the query and passes every entry along with the ID of its row, unless it's `NULL`, to `on_row`
+/// until it returns false or there are no more rows
+fn query_entries(
+	path: &Path,
+	query: &str,
+	mapping: &RowMapping,
+	last_seen_id: Option<Value>,
+	mut on_row: impl FnMut((Entry, Option<Value>)) -> bool,
+) -> Result<(), SqliteError> {
+	let conn = Connection::open_with_flags(
+		path,
+		OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_NO_MUTEX,
+	)
+	.map_err(|e| SqliteError::Open(e, path.to_owned()))?;
+
+	let mut stmt = conn.prepare(query).map_err(SqliteError::Query)?;
+
+	if let Some(idx) = stmt
+		.parameter_index(LAST_SEEN_ID_PARAM)
+		.map_err(SqliteError::Query)?
+	{
+		stmt.raw_bind_parameter(idx, last_seen_id.unwrap_or(Value::Null))
+			.map_err(SqliteError::Query)?;
+	}
+
+	let id_idx = mapping
+		.id_column()
+		.map(|name| column_index(&stmt, name))
+		.transpose()?;
+
+	let column_names = stmt
+		.column_names()
+		.into_iter()
+		.map(ToOwned::to_owned)
+		.collect::<Vec<_>>();
+
+	let indices = match mapping {
+		RowMapping::Columns(columns) => {
+			let idx = |name: &Option<StaticStr>| {
+				name.as_ref()
+					.map(|name| column_index(&stmt, name))
+					.transpose()
+			};
+
+			Some(ColumnIndices {
+				id: id_idx,
+				title: idx(&columns.title)?,
+				body: idx(&columns.body)?,
+				link: idx(&columns.link)?,
+				raw_contents: idx(&columns.raw_contents)?,
+			})
+		}
+		RowMapping::Json { .. } => None,
+	};
+
+	let mut rows_num = 0;
+	let mut rows = stmt.raw_query();
+	while let Some(row) = rows.next().map_err(SqliteError::Query)? {
+		let id = id_idx
+			.map(|idx| row.get::<_, Value>(idx))
+			.transpose()
+			.map_err(SqliteError::Query)?;
+
+		let entry = match &indices {
+			Some(indices) => columns_to_entry(row, indices),
+			None => json_to_entry(row, &column_names, id.as_ref()),
+		}
+		.map_err(SqliteError::Query)?;
+
+		rows_num += 1;
+		if !on_row((entry, id.filter(|id| *id != Value::Null))) {
+			tracing::debug!(
+				"Stopped reading the rows after {rows_num} since they aren't needed anymore"
+			);
+			return Ok(());
+		}
+	}
+
+	tracing::debug!("Query returned {rows_num} rows");
+	Ok(())
+}
+
+impl<E> std::fmt::Debug for Sqlite<E> {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		f.debug_struct("Sqlite")
+			.field("path", &self.path)
+			.field("query", &self.query)
+			.field("mapping", &self.mapping)
+			.field("last_seen_id", &self.last_seen_id)
+			.field("read_only", &self.read_only)
+			.finish_non_exhaustive()
+	}
+}
+
+fn column_index(stmt: &Statement<'_>, name: &StaticStr) -> Result<usize, SqliteError> {
+	stmt.column_index(name)
+		.map_err(|_| SqliteError::ColumnNotFound(name.clone()))
+}
+
+fn columns_to_entry(row: &Row<'_>, indices: &ColumnIndices) -> rusqlite::Result<Entry> {
+	let text = |idx: Option<usize>| -> rusqlite::Result<Option<String>> {
+		idx.map(|idx| row.get_ref(idx).map(value_to_string))
+			.transpose()
+			.map(Option::flatten)
+	};
+
+	let msg = Message::builder()
+		.maybe_title(text(indices.title)?)
+		.maybe_body(text(indices.body)?)
+		.maybe_link(text(indices.link)?)
+		.build();
+
+	let entry = Entry::builder()
+		.maybe_raw_contents(text(indices.raw_contents)?)
+		.msg(msg);
+
+	Ok(match text(indices.id)? {
+		Some(id) => entry.id(id).build(),
+		None => entry.build(),
+	})
+}
+
+fn json_to_entry(
+	row: &Row<'_>,
+	column_names: &[String],
+	id: Option<&Value>,
+) -> rusqlite::Result<Entry> {
+	let mut object = serde_json::Map::new();
+
+	for (idx, name) in column_names.iter().enumerate() {
+		let value = match row.get_ref(idx)? {
+			ValueRef::Null => serde_json::Value::Null,
+			ValueRef::Integer(i) => i.into(),
+			ValueRef::Real(f) => f.into(),
+			ValueRef::Text(text) => String::from_utf8_lossy(text).into(),
+			ValueRef::Blob(blob) => blob.into(),
+		};
+
+		object.insert(name.clone(), value);
+	}
+
+	let entry = Entry::builder().raw_contents(serde_json::Value::Object(object).to_string());
+
+	Ok(match id.and_then(|id| value_to_string(id.into())) {
+		Some(id) => entry.id(id).build(),
+		None => entry.build(),
+	})
+}
+
+fn value_to_string(value: ValueRef<'_>) -> Option<String> {
+	match value {
+		ValueRef::Null => None,
+		ValueRef::Integer(i) => Some(i.to_string()),
+		ValueRef::Real(f) => Some(f.to_string()),
+		ValueRef::Text(text) | ValueRef::Blob(text) => {
+			Some(String::from_utf8_lossy(text).into_owned())
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use assert_matches::assert_matches;
+
+	use std::num::NonZeroUsize;
+
+	use super::*;
+	use crate::test_utils::{SourceStates, TempDir};
+
+	const QUERY: &str = "SELECT id, title, text, url FROM alerts \
+		WHERE :last_seen_id IS NULL OR id > :last_seen_id ORDER BY id";
+
+	/// A database in a temp dir that is removed on drop
+	struct TempDb {
+		path: PathBuf,
+		_dir: TempDir,
+	}
+
+	impl TempDb {
+		fn new(test: &str) -> Self {
+			let dir = TempDir::new(&format!("sqlite-{test}"));
+			let path = dir.path().join("alerts.db");
+
+			let conn = Connection::open(&path).unwrap();
+			conn.execute_batch(
+				"CREATE TABLE alerts (id INTEGER PRIMARY KEY, title TEXT, text TEXT, url TEXT);",
+			)
+			.unwrap();
+
+			Self { path, _dir: dir }
+		}
+
+		fn insert(&self, id: i64, title: &str) {
+			Connection::open(&self.path)
+				.unwrap()
+				.execute(
+					"INSERT INTO alerts VALUES (?1, ?2, NULL, 'https://example.com')",
+					(id, title),
+				)
+				.unwrap();
+		}
+	}
+
+	async fn mark_all_as_read<E: ExternalSave>(sqlite: &mut Sqlite<E>, entries: &[Entry]) {
+		for entry in entries {
+			if let Some(id) = &entry.id {
+				sqlite.mark_as_read(id).await.unwrap();
+			}
+		}
+	}
+
+	#[tokio::test]
+	async fn maps_columns_and_stays_incremental() {
+		let db = TempDb::new("columns");
+		db.insert(1, "first");
+		db.insert(2, "second");
+
+		let mut sqlite = Sqlite::builder()
+			.path(&db.path)
+			.query(QUERY)
+			.mapping(RowMapping::Columns(
+				Columns::builder()
+					.id("id")
+					.title("title")
+					.body("text")
+					.link("url")
+					.build(),
+			))
+			.build();
+
+		let entries = sqlite.fetch().await.unwrap();
+		assert_eq!(entries.len(), 2);
+		assert_eq!(entries[1].id.as_ref().map(EntryId::as_str), Some("2"));
+		assert_eq!(entries[1].msg.title.as_deref(), Some("second"));
+		assert_eq!(entries[1].msg.body, None);
+		assert_eq!(entries[1].msg.link.as_deref(), Some("https://example.com"));
+		assert_eq!(sqlite.last_seen_id(), None);
+
+		mark_all_as_read(&mut sqlite, &entries).await;
+		assert_eq!(sqlite.last_seen_id(), Some(&Value::Integer(2)));
+		assert!(sqlite.fetch().await.unwrap().is_empty());
+
+		db.insert(3, "third");
+		let entries = sqlite.fetch().await.unwrap();
+		assert_eq!(entries.len(), 1);
+		assert_eq!(entries[0].msg.title.as_deref(), Some("third"));
+
+		mark_all_as_read(&mut sqlite, &entries).await;
+		assert_eq!(sqlite.last_seen_id(), Some(&Value::Integer(3)));
+	}
+
+	#[tokio::test]
+	async fn refetches_rows_that_havent_been_marked_as_read() {
+		let db = TempDb::new("unread");
+		db.insert(1, "first");
+		db.insert(2, "second");
+
+		let mut sqlite = Sqlite::builder()
+			.path(&db.path)
+			.query(QUERY)
+			.mapping(RowMapping::Columns(Columns::builder().id("id").build()))
+			.build();
+
+		let entries = sqlite.fetch().await.unwrap();
+		mark_all_as_read(&mut sqlite, &entries[..1]).await;
+
+		let entries = sqlite.fetch().await.unwrap();
+		assert_eq!(
+			entries.len(),
+			1,
+			"a sink failed to process the second entry"
+		);
+		assert_eq!(entries[0].id.as_ref().map(EntryId::as_str), Some("2"));
+
+		sqlite.set_read_only().await;
+		mark_all_as_read(&mut sqlite, &entries).await;
+		assert_eq!(sqlite.last_seen_id(), Some(&Value::Integer(1)));
+	}
+
+	#[tokio::test]
+	async fn serializes_rows_as_json() {
+		let db = TempDb::new("json");
+		db.insert(5, "alert");
+
+		let mut sqlite = Sqlite::builder()
+			.path(&db.path)
+			.query(QUERY)
+			.mapping(RowMapping::Json {
+				id: Some("id".into()),
+			})
+			.last_seen_id(4)
+			.build();
+
+		let entries = sqlite.fetch().await.unwrap();
+		assert_eq!(entries.len(), 1);
+		assert_eq!(entries[0].id.as_ref().map(EntryId::as_str), Some("5"));
+
+		let json: serde_json::Value =
+			serde_json::from_str(entries[0].raw_contents.as_deref().unwrap()).unwrap();
+		assert_eq!(
+			json,
+			serde_json::json!({ "id": 5, "title": "alert", "text": null, "url": "https://example.com" })
+		);
+	}
+
+	#[tokio::test]
+	async fn row_without_id_keeps_last_seen_id() {
+		let db = TempDb::new("null-id");
+		db.insert(1, "first");
+		db.insert(2, "second");
+
+		let mut sqlite = Sqlite::builder()
+			.path(&db.path)
+			.query(
+				"SELECT NULLIF(alerts.id, 2) AS id, title FROM alerts \
+				WHERE :last_seen_id IS NULL OR alerts.id > :last_seen_id ORDER BY alerts.id",
+			)
+			.mapping(RowMapping::Json {
+				id: Some("id".into()),
+			})
+			.build();
+
+		let entries = sqlite.fetch().await.unwrap();
+		assert_eq!(entries.len(), 2);
+
+		mark_all_as_read(&mut sqlite, &entries).await;
+		assert_eq!(sqlite.last_seen_id(), Some(&Value::Integer(1)));
+	}
+
+	#[tokio::test]
+	async fn last_seen_id_is_saved_and_restored() {
+		let db = TempDb::new("external-save");
+		db.insert(1, "first");
+
+		let states = SourceStates::default();
+		let key = format!("{}: {QUERY}", db.path.display());
+		let sqlite = || {
+			Sqlite::builder()
+				.path(&db.path)
+				.query(QUERY)
+				.mapping(RowMapping::Columns(Columns::builder().id("id").build()))
+				.build()
+		};
+
+		let mut first_run = sqlite().with_external_save(None, states.clone());
+		let entries = first_run.fetch().await.unwrap();
+		assert_eq!(entries.len(), 1);
+		assert!(
+			states.get::<SqliteState>(&key).is_none(),
+			"the last seen ID shouldn't be saved before the entries have been processed"
+		);
+
+		mark_all_as_read(&mut first_run, &entries).await;
+
+		let saved = states.get::<SqliteState>(&key).unwrap();
+		assert_eq!(saved.last_seen_id, Value::Integer(1));
+
+		db.insert(2, "second");
+		let mut second_run = sqlite().with_external_save(Some(saved), states);
+		let entries = second_run.fetch().await.unwrap();
+		assert_eq!(entries.len(), 1);
+		assert_eq!(entries[0].id.as_ref().map(EntryId::as_str), Some("2"));
+	}
+
+	#[tokio::test]
+	async fn streams_rows() {
+		let db = TempDb::new("stream");
+		for id in 1..=3 {
+			db.insert(id, &format!("alert #{id}"));
+		}
+
+		let mut sqlite = Sqlite::builder()
+			.path(&db.path)
+			.query(QUERY)
+			.mapping(RowMapping::Columns(
+				Columns::builder().id("id").title("title").build(),
+			))
+			.build()
+			.batched(NonZeroUsize::new(2).unwrap());
+
+		let batches = sqlite
+			.fetch_batches()
+			.unwrap()
+			.try_collect::<Vec<_>>()
+			.await
+			.unwrap();
+		let titles = batches
+			.concat()
+			.into_iter()
+			.map(|entry| entry.msg.title.unwrap())
+			.collect::<Vec<_>>();
+
+		assert_eq!(titles, ["alert #1", "alert #2", "alert #3"]);
+
+		mark_all_as_read(&mut sqlite.source, &batches.concat()).await;
+		assert_eq!(sqlite.source.last_seen_id(), Some(&Value::Integer(3)));
+	}
+
+	#[tokio::test]
+	async fn streaming_saves_last_seen_id() {
+		let db = TempDb::new("stream-external-save");
+		db.insert(1, "first");
+
+		let states = SourceStates::default();
+		let key = format!("{}: {QUERY}", db.path.display());
+
+		let mut sqlite = Sqlite::builder()
+			.path(&db.path)
+			.query(QUERY)
+			.mapping(RowMapping::Columns(Columns::builder().id("id").build()))
+			.build()
+			.with_external_save(None, states.clone())
+			.batched(NonZeroUsize::MIN);
+
+		let batches = sqlite
+			.fetch_batches()
+			.unwrap()
+			.try_collect::<Vec<_>>()
+			.await
+			.unwrap();
+		mark_all_as_read(&mut sqlite.source, &batches.concat()).await;
+
+		let saved = states.get::<SqliteState>(&key).unwrap();
+		assert_eq!(saved.last_seen_id, Value::Integer(1));
+	}
+
+	#[tokio::test]
+	async fn missing_column() {
+		let db = TempDb::new("missing");
+
+		let mut sqlite = Sqlite::builder()
+			.path(&db.path)
+			.query(QUERY)
+			.mapping(RowMapping::Columns(Columns::builder().id("uuid").build()))
+			.build();
+
+		let err = sqlite.fetch().await.unwrap_err();
+		assert_matches!(err, SqliteError::ColumnNotFound(col) if &*col == "uuid");
+	}
+}
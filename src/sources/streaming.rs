@@ -0,0 +1,190 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+
+//! This module contains the [`FetchStream`] trait for sources that produce entries one by one,
+//! and the [`Batched`] adapter that turns them into a regular [`Fetch`]
+
+use futures::{Stream, StreamExt, TryStreamExt, stream};
+use std::{mem, num::NonZeroUsize};
+
+use super::{Fetch, Source, error::SourceError};
+use crate::{
+	entry::{Entry, EntryId},
+	maybe_send::{MaybeSend, MaybeSendSync},
+	read_filter::MarkAsRead,
+};
+
+/// Batches at most this many entries by default
+pub const DEFAULT_BATCH_SIZE: NonZeroUsize = NonZeroUsize::new(100).unwrap();
+
+/// Fetches entries one by one as they become available, instead of all of them at once like [`Fetch`].
+///
+/// Useful for sources that return a lot of entries or need a long time to fetch all of them, e.g. a large query or a multi-page crawl.
+/// Use [`FetchStream::batched`] to turn it into a [`Fetch`] that can be used with the rest of the pipeline.
+/// A [`Task`](`crate::Task`) then runs the actions on every batch as soon as it's available.
+pub trait FetchStream: MaybeSendSync {
+	/// Error that may be returned. Returns [`Infallible`](`std::convert::Infallible`) if it never errors
+	type Err: Into<SourceError>;
+
+	/// Starts fetching all available entries.
+	///
+	/// The stream must not borrow the source, so that it can still be used, e.g. to mark entries as read, while the stream is in progress.
+	/// Any state that has to be updated while fetching should be shared with the stream instead
+	fn fetch_stream(
+		&mut self,
+	) -> impl Stream<Item = Result<Entry, Self::Err>> + MaybeSend + use<Self>;
+
+	/// Groups the entries into batches of at most `batch_size` entries, see [`Batched`]
+	fn batched(self, batch_size: NonZeroUsize) -> Batched<Self>
+	where
+		Self: Sized,
+	{
+		Batched {
+			source: self,
+			batch_size,
+		}
+	}
+}
+
+/// Groups the entries of a [`FetchStream`] into batches to implement [`Fetch`].
+///
+/// A batch contains all entries that are already available but no more than [`Batched::batch_size`].
+/// This way, a source that yields entries in bursts, e.g. page by page, gets a batch per burst and never waits for a batch to fill up.
+/// [`Fetch::fetch`] still waits for all of them and returns them at once
+#[derive(Debug)]
+pub struct Batched<S> {
+	/// The source to fetch entries from
+	pub source: S,
+
+	/// Maximum number of entries in a batch
+	pub batch_size: NonZeroUsize,
+}
+
+impl<S> Batched<S> {
+	/// Groups the entries of `source` into batches of at most [`DEFAULT_BATCH_SIZE`] entries
+	pub const fn new(source: S) -> Self {
+		Self {
+			source,
+			batch_size: DEFAULT_BATCH_SIZE,
+		}
+	}
+}
+
+impl<S> Fetch for Batched<S>
+where
+	S: FetchStream,
+{
+	type Err = SourceError;
+
+	async fn fetch(&mut self) -> Result<Vec<Entry>, Self::Err> {
+		self.source
+			.fetch_stream()
+			.map_err(Into::into)
+			.try_collect()
+			.await
+	}
+
+	fn fetch_batches(
+		&mut self,
+	) -> Option<impl Stream<Item = Result<Vec<Entry>, Self::Err>> + MaybeSend + use<S>> {
+		let batches = self
+			.source
+			.fetch_stream()
+			.map_err(Into::into)
+			.ready_chunks(self.batch_size.get())
+			.flat_map(|chunk| stream::iter(split_errors(chunk)));
+
+		Some(batches)
+	}
+}
+
+impl<S> MarkAsRead for Batched<S>
+where
+	S: MarkAsRead,
+{
+	type Err = S::Err;
+
+	async fn mark_as_read(&mut self, id: &EntryId) -> Result<(), Self::Err> {
+		self.source.mark_as_read(id).await
+	}
+
+	#[expect(
+		clippy::semicolon_if_nothing_returned,
+		reason = "just forwards the method call, should return the same value"
+	)]
+	async fn set_read_only(&mut self) {
+		self.source.set_read_only().await
+	}
+}
+
+impl<S> Source for Batched<S> where S: FetchStream + MarkAsRead {}
+
+/// Splits a chunk of results into batches of entries between the errors, keeping the order
+fn split_errors<E>(chunk: Vec<Result<Entry, E>>) -> Vec<Result<Vec<Entry>, E>> {
+	let mut batches = Vec::new();
+	let mut batch = Vec::new();
+
+	for res in chunk {
+		match res {
+			Ok(entry) => batch.push(entry),
+			Err(e) => {
+				if !batch.is_empty() {
+					batches.push(Ok(mem::take(&mut batch)));
+				}
+
+				batches.push(Err(e));
+			}
+		}
+	}
+
+	if !batch.is_empty() {
+		batches.push(Ok(batch));
+	}
+
+	batches
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::test_utils::{Entries, TestError, contents};
+
+	#[tokio::test]
+	async fn batches_entries() {
+		let mut source = Entries(vec![Ok("1"), Ok("2"), Ok("3"), Err(TestError), Ok("4")])
+			.batched(NonZeroUsize::new(2).unwrap());
+
+		let batches = source.fetch_batches().unwrap().collect::<Vec<_>>().await;
+
+		assert_eq!(batches.len(), 4);
+		assert_eq!(contents(batches[0].as_ref().unwrap()), ["1", "2"]);
+		assert_eq!(contents(batches[1].as_ref().unwrap()), ["3"]);
+		assert!(batches[2].is_err());
+		assert_eq!(contents(batches[3].as_ref().unwrap()), ["4"]);
+
+		let mut source = Entries(vec![Ok("1"), Ok("2"), Ok("3")]).batched(DEFAULT_BATCH_SIZE);
+		assert_eq!(contents(&source.fetch().await.unwrap()), ["1", "2", "3"]);
+	}
+
+	#[tokio::test]
+	async fn options_and_vecs_forward_batches() {
+		let mut source = Some(Entries(vec![Ok("1")]).batched(DEFAULT_BATCH_SIZE));
+		assert!(source.fetch_batches().is_some());
+		assert!(None::<Batched<Entries>>.fetch_batches().is_none());
+
+		let mut sources = vec![
+			Entries(vec![Ok("1")]).batched(DEFAULT_BATCH_SIZE),
+			Entries(vec![Ok("2")]).batched(DEFAULT_BATCH_SIZE),
+		];
+		let batches = sources
+			.fetch_batches()
+			.unwrap()
+			.try_collect::<Vec<_>>()
+			.await
+			.unwrap();
+		assert_eq!(contents(&batches.concat()), ["1", "2"]);
+	}
+}
@@ -0,0 +1,561 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+
+//! [`Fetch`], [`MarkAsRead`], and [`Source`] implementations for tuples of different sources, as well as the [`PartialResults`] wrapper.
+//!
+//! To know which of the sources to mark an entry as read in, tuples of several sources prefix the IDs of the entries
+//! (and of the entries they reply to) with the number of the source that has fetched them, e.g. `1:id` for the second one.
+//! Marking an entry as read then strips the prefix and marks it as read only in that source.
+//! If the IDs are replaced along the way, e.g. by a transform that splits a page into several entries,
+//! share a single read filter between all of the sources with [`Fetch::into_source_with_read_filter`] instead.
+//! Tuples of a single source forward everything to it as is.
+//!
+//! If all sources of a tuple support [fetching in batches](`Fetch::fetch_batches`), the batches of all of them are returned as soon as they arrive
+
+use futures::{Stream, StreamExt, TryStreamExt, future, stream};
+use std::collections::HashSet;
+use tokio::{join, try_join};
+
+use super::{Fetch, Source, error::SourceError};
+use crate::{
+	entry::{Entry, EntryId},
+	error::ErrorChainDisplay,
+	maybe_send::MaybeSend,
+	read_filter::{MarkAsRead, mark_as_read::MarkAsReadError},
+};
+
+/// Separates the number of the source from the original ID in the IDs of the entries fetched by tuples of several sources
+const SOURCE_NUM_SEPARATOR: char = ':';
+
+/// Fetches all sources of a tuple concurrently but doesn't fail if only some of them have failed.
+///
+/// Tuples of sources fetch all of them concurrently, too, but fail as soon as any one of them fails, dropping the rest of the results.
+/// This wrapper returns the entries of all sources that have succeeded instead,
+/// logs the errors of the ones that haven't, and saves them to [`PartialResults::errors`].
+/// It still fails if all sources have failed.
+///
+/// When fetched [in batches](`Fetch::fetch_batches`), the errors are only logged, and the last one is returned if all sources have failed without returning any entries.
+///
+/// Entries are marked as read the same way as with tuples of sources, i.e. only in the source that has fetched them.
+///
+/// ```
+/// # use fetcher::sources::{Fetch, PartialResults};
+/// # async fn f() {
+/// let mut sources = PartialResults::new(("hello".to_owned(), ()));
+/// let entries = sources.fetch().await.unwrap();
+/// assert_eq!(entries.len(), 2);
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct PartialResults<T> {
+	/// The tuple of sources to fetch
+	pub sources: T,
+
+	/// Errors of the sources that have failed during the last fetch
+	pub errors: Vec<SourceError>,
+}
+
+impl<T> PartialResults<T> {
+	/// Wraps the tuple of `sources`
+	pub const fn new(sources: T) -> Self {
+		Self {
+			sources,
+			errors: Vec::new(),
+		}
+	}
+}
+
+impl<F> Fetch for (F,)
+where
+	F: Fetch,
+{
+	type Err = F::Err;
+
+	async fn fetch(&mut self) -> Result<Vec<Entry>, Self::Err> {
+		self.0.fetch().await
+	}
+
+	fn fetch_batches(
+		&mut self,
+	) -> Option<impl Stream<Item = Result<Vec<Entry>, Self::Err>> + MaybeSend + use<F>> {
+		self.0.fetch_batches()
+	}
+}
+
+impl<M> MarkAsRead for (M,)
+where
+	M: MarkAsRead,
+{
+	type Err = M::Err;
+
+	async fn mark_as_read(&mut self, id: &EntryId) -> Result<(), Self::Err> {
+		self.0.mark_as_read(id).await
+	}
+
+	#[expect(
+		clippy::semicolon_if_nothing_returned,
+		reason = "just forwards the method call, should return the same value"
+	)]
+	async fn set_read_only(&mut self) {
+		self.0.set_read_only().await
+	}
+}
+
+impl<S> Source for (S,) where S: Source {}
+
+macro_rules! impl_source_for_tuples {
+	($($type_name:ident)+) => {
+		impl<$($type_name),+> Fetch for ($($type_name),+)
+		where
+			$($type_name: Fetch),+
+		{
+			type Err = SourceError;
+
+			#[expect(non_snake_case, reason = "it's fine to re-use the names to make calling the macro easier")]
+			async fn fetch(&mut self) -> Result<Vec<Entry>, Self::Err> {
+				// following code expands into something like this
+				//let (entries1, entries2) = try_join!(
+				//	async { self.0.fetch().await.map_err(Into::into) },
+				//	async { self.1.fetch().await.map_err(Into::into) },
+				//)?;
+				//let mut entries = Vec::new();
+				//entries.extend(entries1);
+				//entries.extend(entries2);
+
+				let ($($type_name),+) = self;
+				let results = try_join!($(
+					async { $type_name.fetch().await.map_err(Into::<SourceError>::into) }
+				),+)?;
+
+				let results: [_; _] = results.into();
+				let entries = results
+					.into_iter()
+					.enumerate()
+					.flat_map(|(source_num, source_entries)| tag_ids(source_num, source_entries))
+					.collect();
+
+				Ok(entries)
+			}
+
+			#[expect(non_snake_case, reason = "it's fine to re-use the names to make calling the macro easier")]
+			fn fetch_batches(
+				&mut self,
+			) -> Option<impl Stream<Item = Result<Vec<Entry>, Self::Err>> + MaybeSend + use<$($type_name),+>> {
+				let ($($type_name),+) = self;
+
+				let mut source_num = 0;
+				let batches = stream::empty();
+				$(
+					let num = source_num;
+					let batches = stream::select(
+						batches,
+						$type_name
+							.fetch_batches()?
+							.map_ok(move |batch| tag_ids(num, batch))
+							.map_err(Into::<SourceError>::into),
+					);
+
+					#[allow(unused_assignments, reason = "last iteration won't use it, it's fine")]
+					{
+						source_num += 1;
+					}
+				)+
+
+				Some(batches)
+			}
+		}
+
+		impl<$($type_name),+> Fetch for PartialResults<($($type_name),+)>
+		where
+			$($type_name: Fetch),+
+		{
+			type Err = SourceError;
+
+			#[expect(non_snake_case, reason = "it's fine to re-use the names to make calling the macro easier")]
+			async fn fetch(&mut self) -> Result<Vec<Entry>, Self::Err> {
+				let ($($type_name),+) = &mut self.sources;
+				let results = join!($(
+					async { $type_name.fetch().await.map_err(Into::<SourceError>::into) }
+				),+);
+
+				let results: [_; _] = results.into();
+				let sources_num = results.len();
+
+				let mut entries = Vec::new();
+				let mut errors = Vec::new();
+				for (source_num, result) in results.into_iter().enumerate() {
+					match result {
+						Ok(source_entries) => entries.extend(tag_ids(source_num, source_entries)),
+						Err(e) => {
+							tracing::warn!("Source #{source_num} failed, using the results of the others: {}", ErrorChainDisplay(&e));
+							errors.push(e);
+						}
+					}
+				}
+
+				if errors.len() == sources_num {
+					self.errors = Vec::new();
+					return Err(errors.swap_remove(0));
+				}
+
+				self.errors = errors;
+				Ok(entries)
+			}
+
+			#[expect(non_snake_case, reason = "it's fine to re-use the names to make calling the macro easier")]
+			fn fetch_batches(
+				&mut self,
+			) -> Option<impl Stream<Item = Result<Vec<Entry>, Self::Err>> + MaybeSend + use<$($type_name),+>> {
+				let ($($type_name),+) = &mut self.sources;
+
+				let mut source_num = 0;
+				let batches = stream::empty();
+				$(
+					let num = source_num;
+					let batches = stream::select(
+						batches,
+						$type_name
+							.fetch_batches()?
+							.map(move |res| {
+								let res = res
+									.map(|batch| tag_ids(num, batch))
+									.map_err(Into::<SourceError>::into);
+
+								(num, res)
+							}),
+					);
+
+					#[allow(unused_assignments, reason = "last iteration won't use it, it's fine")]
+					{
+						source_num += 1;
+					}
+				)+
+
+				Some(skip_errors(batches, source_num))
+			}
+		}
+
+		/// Marks the entry as read only in the source that has fetched it
+		impl<$($type_name),+> MarkAsRead for ($($type_name),+)
+		where
+			$($type_name: MarkAsRead),+
+		{
+			type Err = MarkAsReadError;
+
+			#[expect(non_snake_case, reason = "it's fine to re-use the names to make calling the macro easier")]
+			async fn mark_as_read(&mut self, id: &EntryId) -> Result<(), Self::Err> {
+				let (source_num, untagged_id) = untag_id(id)?;
+				let ($($type_name),+) = self;
+
+				let mut num = 0;
+				$(
+					if num == source_num {
+						return $type_name.mark_as_read(&untagged_id).await.map_err(Into::into);
+					}
+
+					#[allow(unused_assignments, reason = "last iteration won't use it, it's fine")]
+					{
+						num += 1;
+					}
+				)+
+
+				Err(MarkAsReadError::UnknownSource(id.clone()))
+			}
+
+			#[expect(non_snake_case, reason = "it's fine to re-use the names to make calling the macro easier")]
+			async fn set_read_only(&mut self) {
+				let ($($type_name),+) = self;
+				$(
+					$type_name.set_read_only().await;
+				)+
+			}
+		}
+
+		impl<$($type_name),+> MarkAsRead for PartialResults<($($type_name),+)>
+		where
+			$($type_name: MarkAsRead),+
+		{
+			type Err = MarkAsReadError;
+
+			async fn mark_as_read(&mut self, id: &EntryId) -> Result<(), Self::Err> {
+				self.sources.mark_as_read(id).await
+			}
+
+			async fn set_read_only(&mut self) {
+				self.sources.set_read_only().await;
+			}
+		}
+
+		impl<$($type_name),+> Source for ($($type_name),+)
+		where
+			$($type_name: Source),+
+		{
+		}
+
+		impl<$($type_name),+> Source for PartialResults<($($type_name),+)>
+		where
+			$($type_name: Source),+
+		{
+		}
+	}
+}
+
+/// Prefixes the IDs of the `entries` and of the entries they reply to with the number of the source that has fetched them
+fn tag_ids(source_num: usize, mut entries: Vec<Entry>) -> Vec<Entry> {
+	for entry in &mut entries {
+		for id in [&mut entry.id, &mut entry.reply_to].into_iter().flatten() {
+			id.0.insert_str(0, &format!("{source_num}{SOURCE_NUM_SEPARATOR}"));
+		}
+	}
+
+	entries
+}
+
+/// Splits the `id` prefixed by [`tag_ids`] into the number of the source and the original ID
+fn untag_id(id: &EntryId) -> Result<(usize, EntryId), MarkAsReadError> {
+	id.split_once(SOURCE_NUM_SEPARATOR)
+		.and_then(|(source_num, untagged_id)| {
+			Some((
+				source_num.parse().ok()?,
+				EntryId::try_from(untagged_id).ok()?,
+			))
+		})
+		.ok_or_else(|| MarkAsReadError::UnknownSource(id.clone()))
+}
+
+/// Logs and skips the errors of the batches of the sources, unless all `sources_num` of them have failed without returning any batches
+fn skip_errors<S>(
+	batches: S,
+	sources_num: usize,
+) -> impl Stream<Item = Result<Vec<Entry>, SourceError>> + MaybeSend
+where
+	S: Stream<Item = (usize, Result<Vec<Entry>, SourceError>)> + MaybeSend,
+{
+	let mut failed = HashSet::new();
+	let mut any_succeeded = false;
+
+	batches.filter_map(move |(source_num, res)| {
+		let res = match res {
+			Ok(batch) => {
+				any_succeeded = true;
+				Some(Ok(batch))
+			}
+			Err(e) => {
+				tracing::warn!(
+					"Source #{source_num} failed, using the results of the others: {}",
+					ErrorChainDisplay(&e)
+				);
+
+				failed.insert(source_num);
+				(failed.len() == sources_num && !any_succeeded).then_some(Err(e))
+			}
+		};
+
+		future::ready(res)
+	})
+}
+
+impl_source_for_tuples!(A1 A2);
+impl_source_for_tuples!(A1 A2 A3);
+impl_source_for_tuples!(A1 A2 A3 A4);
+impl_source_for_tuples!(A1 A2 A3 A4 A5);
+impl_source_for_tuples!(A1 A2 A3 A4 A5 A6);
+impl_source_for_tuples!(A1 A2 A3 A4 A5 A6 A7);
+impl_source_for_tuples!(A1 A2 A3 A4 A5 A6 A7 A8);
+impl_source_for_tuples!(A1 A2 A3 A4 A5 A6 A7 A8 A9);
+impl_source_for_tuples!(A1 A2 A3 A4 A5 A6 A7 A8 A9 A10);
+impl_source_for_tuples!(A1 A2 A3 A4 A5 A6 A7 A8 A9 A10 A11);
+impl_source_for_tuples!(A1 A2 A3 A4 A5 A6 A7 A8 A9 A10 A11 A12);
+
+#[cfg(test)]
+mod tests {
+	use assert_matches::assert_matches;
+	use std::{
+		convert::Infallible,
+		num::NonZeroUsize,
+		time::{Duration, Instant},
+	};
+
+	use super::*;
+	use crate::{
+		read_filter::NotPresent,
+		sources::FetchStream,
+		test_utils::{Entries, Failing, TestError, contents},
+	};
+
+	/// Returns an entry with the text after the delay
+	struct Delayed(Duration, &'static str);
+
+	/// Returns an entry with the ID that replies to the entry with the same ID
+	struct WithId(&'static str);
+
+	impl Fetch for WithId {
+		type Err = Infallible;
+
+		async fn fetch(&mut self) -> Result<Vec<Entry>, Self::Err> {
+			let id = EntryId::try_from(self.0).unwrap();
+			Ok(vec![
+				Entry::builder().id(self.0.to_owned()).reply_to(id).build(),
+			])
+		}
+	}
+
+	impl Fetch for Delayed {
+		type Err = Infallible;
+
+		async fn fetch(&mut self) -> Result<Vec<Entry>, Self::Err> {
+			tokio::time::sleep(self.0).await;
+			Ok(vec![
+				Entry::builder().raw_contents(self.1.to_owned()).build(),
+			])
+		}
+	}
+
+	#[tokio::test]
+	async fn fetches_concurrently_in_order() {
+		let mut sources = (
+			Delayed(Duration::from_millis(200), "slow"),
+			"string".to_owned(),
+			Delayed(Duration::from_millis(200), "fast"),
+		);
+
+		let start = Instant::now();
+		let entries = sources.fetch().await.unwrap();
+
+		assert_eq!(contents(&entries), ["slow", "string", "fast"]);
+		assert!(start.elapsed() < Duration::from_millis(400));
+	}
+
+	#[tokio::test]
+	async fn fails_fast() {
+		let mut sources = (
+			Delayed(Duration::from_secs(10), "slow"),
+			Failing(Duration::from_millis(10)),
+		);
+
+		let start = Instant::now();
+		assert_matches!(sources.fetch().await, Err(SourceError::Other(_)));
+		assert!(start.elapsed() < Duration::from_secs(10));
+	}
+
+	#[tokio::test]
+	async fn partial_results() {
+		let mut sources = PartialResults::new((
+			Delayed(Duration::from_millis(20), "ok"),
+			Failing(Duration::from_millis(10)),
+		));
+
+		let entries = sources.fetch().await.unwrap();
+		assert_eq!(contents(&entries), ["ok"]);
+		assert_matches!(&*sources.errors, [SourceError::Other(_)]);
+
+		let mut sources = PartialResults::new((
+			Failing(Duration::from_millis(10)),
+			Failing(Duration::from_millis(20)),
+		));
+		assert_matches!(sources.fetch().await, Err(SourceError::Other(_)));
+		assert!(sources.errors.is_empty());
+	}
+
+	#[tokio::test]
+	async fn fetches_batches_of_all_sources() {
+		let mut sources = (
+			Entries(vec![Ok("1"), Ok("2")]).batched(NonZeroUsize::MIN),
+			Entries(vec![Ok("3")]).batched(NonZeroUsize::MIN),
+		);
+
+		let batches = sources
+			.fetch_batches()
+			.unwrap()
+			.try_collect::<Vec<_>>()
+			.await
+			.unwrap();
+
+		let entries = batches.concat();
+		let mut entries = contents(&entries);
+		entries.sort_unstable();
+		assert_eq!(entries, ["1", "2", "3"]);
+
+		let mut sources = (
+			Entries(vec![Ok("1")]).batched(NonZeroUsize::MIN),
+			"string".to_owned(),
+		);
+		assert!(
+			sources.fetch_batches().is_none(),
+			"strings can't be fetched in batches"
+		);
+	}
+
+	#[tokio::test]
+	async fn partial_results_skip_failed_batches() {
+		let mut sources = PartialResults::new((
+			Entries(vec![Ok("1"), Err(TestError)]).batched(NonZeroUsize::MIN),
+			Entries(vec![Err(TestError)]).batched(NonZeroUsize::MIN),
+		));
+
+		let batches = sources
+			.fetch_batches()
+			.unwrap()
+			.try_collect::<Vec<_>>()
+			.await
+			.unwrap();
+		assert_eq!(contents(&batches.concat()), ["1"]);
+
+		let mut sources = PartialResults::new((
+			Entries(vec![Err(TestError)]).batched(NonZeroUsize::MIN),
+			Entries(vec![Err(TestError)]).batched(NonZeroUsize::MIN),
+		));
+
+		let batches = sources.fetch_batches().unwrap().collect::<Vec<_>>().await;
+		assert_matches!(&*batches, [Err(SourceError::Other(_))]);
+	}
+
+	#[tokio::test]
+	async fn shares_a_read_filter() {
+		let mut sources = ("first".to_owned(), "second".to_owned())
+			.into_source_with_read_filter(NotPresent::new());
+		let id = EntryId::try_from("id").unwrap();
+
+		sources.mark_as_read(&id).await.unwrap();
+		assert!(!sources.rf.unwrap().is_unread(&id));
+	}
+
+	#[tokio::test]
+	async fn marks_as_read_only_in_the_source_that_has_fetched_the_entry() {
+		let mut sources = (
+			WithId("id").into_source_with_read_filter(NotPresent::new()),
+			WithId("id").into_source_with_read_filter(NotPresent::new()),
+		);
+
+		let entries = sources.fetch().await.unwrap();
+		let ids = entries
+			.iter()
+			.map(|entry| (entry.id.as_deref(), entry.reply_to.as_deref()))
+			.collect::<Vec<_>>();
+		assert_eq!(
+			ids,
+			[(Some("0:id"), Some("0:id")), (Some("1:id"), Some("1:id"))]
+		);
+
+		sources
+			.mark_as_read(&EntryId::try_from("1:id").unwrap())
+			.await
+			.unwrap();
+
+		let id = EntryId::try_from("id").unwrap();
+		assert!(sources.0.rf.as_ref().unwrap().is_unread(&id));
+		assert!(!sources.1.rf.as_ref().unwrap().is_unread(&id));
+
+		for unknown in ["id", "2:id", "first:id"] {
+			assert_matches!(
+				sources
+					.mark_as_read(&EntryId::try_from(unknown).unwrap())
+					.await,
+				Err(MarkAsReadError::UnknownSource(_))
+			);
+		}
+	}
+}
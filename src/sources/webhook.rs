@@ -0,0 +1,650 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+
+//! This module contains the [`Webhook`] source that receives the requests upstream systems push to it,
+//! and the [`OnRequest`] trigger that fires as soon as one arrives
+
+use ring::hmac;
+use std::{
+	collections::VecDeque,
+	convert::Infallible,
+	fmt::{self, Debug},
+	io,
+	net::SocketAddr,
+	sync::{Arc, Mutex},
+	time::Duration,
+};
+use tokio::{
+	io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader},
+	net::{TcpListener, TcpStream},
+	sync::{Notify, Semaphore},
+	task::JoinHandle,
+};
+
+use super::Fetch;
+use crate::{
+	StaticStr,
+	cancellation_token::{CancellationToken, cancel_wait},
+	entry::Entry,
+	job::trigger::{Trigger, TriggerResult},
+};
+
+/// Header that contains the signature of the body by default, as sent by GitHub and others
+pub const DEFAULT_SIGNATURE_HEADER: &str = "X-Hub-Signature-256";
+
+/// Largest body that is accepted by default, in bytes
+pub const DEFAULT_MAX_BODY_SIZE: usize = 1024 * 1024;
+
+/// Most bodies that are kept until the next fetch by default
+pub const DEFAULT_MAX_QUEUED: usize = 1000;
+
+/// Most connections that are handled at the same time by default
+pub const DEFAULT_MAX_CONNECTIONS: usize = 64;
+
+/// Longest line of the request line or a header that is accepted
+const MAX_LINE_LEN: u64 = 8 * 1024;
+
+/// Most headers a request may have
+const MAX_HEADERS: usize = 100;
+
+/// How long a client may take to send the whole request
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A source that runs an embedded HTTP server and queues the bodies of all `POST` requests it receives
+/// until they are drained by [`Fetch::fetch`].
+///
+/// Every body becomes an entry with the body as its [`Entry::raw_contents`].
+/// Use [`Webhook::on_request`] to create a trigger that re-triggers the job as soon as a request arrives.
+///
+/// If a [secret](`Webhook::bind`) is set, requests must be signed with HMAC-SHA256 using it.
+/// The signature is the hex-encoded HMAC of the body, optionally prefixed with `sha256=`, as sent by, e.g. GitHub or Gitea.
+///
+/// The server responds with `202 Accepted` to all requests that have been queued and stops when the source is dropped.
+/// If the queue is full, requests are rejected with `503 Service Unavailable` until the source is fetched.
+/// New connections aren't accepted while [`max_connections`](`Webhook::bind`) are being handled.
+///
+/// ```no_run
+/// # use fetcher::sources::webhook::Webhook;
+/// # async fn f() -> std::io::Result<()> {
+/// let webhook = Webhook::bind()
+///     .addr(([0, 0, 0, 0], 8080).into())
+///     .path("/github")
+///     .secret("hunter2")
+///     .call()
+///     .await?;
+///
+/// let trigger = webhook.on_request();
+/// # Ok(())
+/// # }
+/// ```
+pub struct Webhook {
+	addr: SocketAddr,
+	queue: Arc<Queue>,
+	server: JoinHandle<()>,
+}
+
+/// A [`Trigger`] that re-triggers the job as soon as a [`Webhook`] receives a request.
+///
+/// Created with [`Webhook::on_request`]. Resumes right away if there are requests that haven't been fetched yet
+pub struct OnRequest {
+	/// Stop the job when signalled, even while waiting for a request
+	pub cancel_token: Option<CancellationToken>,
+
+	queue: Arc<Queue>,
+}
+
+/// Received bodies shared between the server, the source, and the trigger
+#[derive(Default)]
+struct Queue {
+	bodies: Mutex<VecDeque<String>>,
+	received: Notify,
+}
+
+/// What requests the server accepts
+struct Config {
+	path: Option<StaticStr>,
+	key: Option<hmac::Key>,
+	signature_header: StaticStr,
+	max_body_size: usize,
+	max_queued: usize,
+}
+
+/// An HTTP status the server responds with
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Status {
+	Accepted,
+	BadRequest,
+	Unauthorized,
+	NotFound,
+	MethodNotAllowed,
+	LengthRequired,
+	PayloadTooLarge,
+	HeadersTooLarge,
+	ServiceUnavailable,
+}
+
+#[bon::bon]
+impl Webhook {
+	/// Starts listening for requests in the background
+	///
+	/// # Errors
+	/// This function fails if the address can't be bound to
+	#[builder]
+	pub async fn bind(
+		/// Address to listen on. Use port 0 to let the OS pick a free one, see [`Webhook::local_addr`]
+		addr: SocketAddr,
+
+		/// Accept requests only to this path, e.g. `/github`. Accepts requests to all paths if `None`
+		#[builder(into)]
+		path: Option<StaticStr>,
+
+		/// Require all requests to be signed with HMAC-SHA256 using this secret
+		#[builder(into)]
+		secret: Option<StaticStr>,
+
+		/// Header that contains the signature. Defaults to [`DEFAULT_SIGNATURE_HEADER`]
+		#[builder(into, default = DEFAULT_SIGNATURE_HEADER)]
+		signature_header: StaticStr,
+
+		/// Largest body to accept, in bytes. Defaults to [`DEFAULT_MAX_BODY_SIZE`]
+		#[builder(default = DEFAULT_MAX_BODY_SIZE)]
+		max_body_size: usize,
+
+		/// Most bodies to keep until the next fetch. Defaults to [`DEFAULT_MAX_QUEUED`]
+		#[builder(default = DEFAULT_MAX_QUEUED)]
+		max_queued: usize,
+
+		/// Most connections to handle at the same time. Defaults to [`DEFAULT_MAX_CONNECTIONS`]
+		#[builder(default = DEFAULT_MAX_CONNECTIONS)]
+		max_connections: usize,
+	) -> io::Result<Self> {
+		let listener = TcpListener::bind(addr).await?;
+		let addr = listener.local_addr()?;
+		tracing::info!("Listening for webhook requests on {addr}");
+
+		let config = Arc::new(Config {
+			path,
+			key: secret.map(|secret| hmac::Key::new(hmac::HMAC_SHA256, secret.as_bytes())),
+			signature_header,
+			max_body_size,
+			max_queued,
+		});
+		let queue = Arc::new(Queue::default());
+
+		let server = tokio::spawn(serve(listener, config, Arc::clone(&queue), max_connections));
+
+		Ok(Self {
+			addr,
+			queue,
+			server,
+		})
+	}
+}
+
+impl Webhook {
+	/// Returns the address the server is listening on
+	#[must_use]
+	pub fn local_addr(&self) -> SocketAddr {
+		self.addr
+	}
+
+	/// Creates an [`OnRequest`] trigger that fires as soon as this source receives a request
+	#[must_use]
+	pub fn on_request(&self) -> OnRequest {
+		OnRequest {
+			cancel_token: None,
+			queue: Arc::clone(&self.queue),
+		}
+	}
+}
+
+impl Fetch for Webhook {
+	type Err = Infallible;
+
+	/// Returns the bodies of all requests that have been received since the last fetch
+	async fn fetch(&mut self) -> Result<Vec<Entry>, Self::Err> {
+		let bodies = std::mem::take(&mut *self.queue.bodies.lock().expect("poisoned"));
+
+		Ok(bodies
+			.into_iter()
+			.map(|body| Entry::builder().raw_contents(body).build())
+			.collect())
+	}
+}
+
+impl Drop for Webhook {
+	fn drop(&mut self) {
+		self.server.abort();
+	}
+}
+
+impl OnRequest {
+	/// Sets the [`OnRequest::cancel_token`], usually to the one of the job
+	#[must_use]
+	pub fn with_cancel_token(mut self, cancel_token: CancellationToken) -> Self {
+		self.cancel_token = Some(cancel_token);
+		self
+	}
+}
+
+impl Trigger for OnRequest {
+	type Err = Infallible;
+
+	async fn wait(&mut self) -> Result<TriggerResult, Self::Err> {
+		let received = async {
+			// a permit is stored if a request has been received while nobody was waiting,
+			// so the check and the wait can't miss one
+			while self.queue.bodies.lock().expect("poisoned").is_empty() {
+				self.queue.received.notified().await;
+			}
+		};
+
+		tokio::select! {
+			() = received => Ok(TriggerResult::Resume),
+			() = cancel_wait(self.cancel_token.as_mut()) => {
+				tracing::debug!("Stopped waiting for webhook requests since the job has been cancelled");
+				Ok(TriggerResult::Stop)
+			}
+		}
+	}
+
+	/// Requests may arrive at any time, so an hour without errors is assumed to be long enough
+	fn twice_as_duration(&self) -> Duration {
+		Duration::from_hours(1)
+	}
+}
+
+/// Accepts connections until the task is aborted, handling at most `max_connections` at a time
+async fn serve(
+	listener: TcpListener,
+	config: Arc<Config>,
+	queue: Arc<Queue>,
+	max_connections: usize,
+) {
+	let connections = Arc::new(Semaphore::new(max_connections));
+
+	loop {
+		let permit = Arc::clone(&connections)
+			.acquire_owned()
+			.await
+			.expect("semaphore is never closed");
+
+		let (stream, peer) = match listener.accept().await {
+			Ok(conn) => conn,
+			Err(e) => {
+				tracing::warn!("Failed to accept a webhook connection: {e}");
+				continue;
+			}
+		};
+
+		let config = Arc::clone(&config);
+		let queue = Arc::clone(&queue);
+
+		tokio::spawn(async move {
+			if let Err(e) = handle(stream, &config, &queue).await {
+				tracing::debug!("Failed to handle a webhook request from {peer}: {e}");
+			}
+
+			drop(permit);
+		});
+	}
+}
+
+/// Reads a single request, queues its body if it's valid, and responds to it
+async fn handle(stream: TcpStream, config: &Config, queue: &Queue) -> io::Result<()> {
+	let mut stream = BufReader::new(stream);
+
+	let status = tokio::time::timeout(REQUEST_TIMEOUT, read_request(&mut stream, config))
+		.await
+		.map_err(|_| io::Error::new(io::ErrorKind::TimedOut, "request took too long"))??;
+
+	let status = match status {
+		Ok(body) => queue.push(body, config.max_queued),
+		Err(status) => {
+			tracing::debug!("Rejected a webhook request with {status:?}");
+			status
+		}
+	};
+
+	let (code, reason) = status.code_and_reason();
+	let response =
+		format!("HTTP/1.1 {code} {reason}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n");
+
+	stream.write_all(response.as_bytes()).await?;
+	stream.shutdown().await
+}
+
+/// Reads the request and returns its body if it should be accepted, or the status to reject it with
+async fn read_request(
+	stream: &mut BufReader<TcpStream>,
+	config: &Config,
+) -> io::Result<Result<String, Status>> {
+	let Some(request_line) = read_line(stream).await? else {
+		return Ok(Err(Status::HeadersTooLarge));
+	};
+
+	let mut parts = request_line.split(' ');
+	let (Some(method), Some(target)) = (parts.next(), parts.next()) else {
+		return Ok(Err(Status::BadRequest));
+	};
+
+	let mut headers = Vec::new();
+	loop {
+		let Some(line) = read_line(stream).await? else {
+			return Ok(Err(Status::HeadersTooLarge));
+		};
+
+		if line.is_empty() {
+			break;
+		}
+
+		if headers.len() == MAX_HEADERS {
+			return Ok(Err(Status::HeadersTooLarge));
+		}
+
+		let Some((name, value)) = line.split_once(':') else {
+			return Ok(Err(Status::BadRequest));
+		};
+
+		headers.push((name.trim().to_owned(), value.trim().to_owned()));
+	}
+
+	let header = |name: &str| {
+		headers
+			.iter()
+			.find(|(header, _)| header.eq_ignore_ascii_case(name))
+			.map(|(_, value)| value.as_str())
+	};
+
+	let path = target.split_once('?').map_or(target, |(path, _)| path);
+	if config
+		.path
+		.as_deref()
+		.is_some_and(|expected| expected != path)
+	{
+		return Ok(Err(Status::NotFound));
+	}
+
+	if method != "POST" {
+		return Ok(Err(Status::MethodNotAllowed));
+	}
+
+	let Some(len) = header("content-length") else {
+		return Ok(Err(Status::LengthRequired));
+	};
+
+	let Ok(len) = len.parse::<usize>() else {
+		return Ok(Err(Status::BadRequest));
+	};
+
+	if len > config.max_body_size {
+		return Ok(Err(Status::PayloadTooLarge));
+	}
+
+	let mut body = vec![0; len];
+	stream.read_exact(&mut body).await?;
+
+	if let Some(key) = &config.key
+		&& !is_signed(key, &body, header(&config.signature_header))
+	{
+		return Ok(Err(Status::Unauthorized));
+	}
+
+	Ok(String::from_utf8(body).map_err(|_| Status::BadRequest))
+}
+
+/// Reads a line without the line break. Returns `None` if it's too long
+async fn read_line(stream: &mut BufReader<TcpStream>) -> io::Result<Option<String>> {
+	let mut line = String::new();
+	(&mut *stream)
+		.take(MAX_LINE_LEN)
+		.read_line(&mut line)
+		.await?;
+
+	let Some(line) = line.strip_suffix('\n') else {
+		return Ok(None);
+	};
+
+	Ok(Some(line.strip_suffix('\r').unwrap_or(line).to_owned()))
+}
+
+/// Checks if the `signature` is a valid HMAC of the `body`
+fn is_signed(key: &hmac::Key, body: &[u8], signature: Option<&str>) -> bool {
+	let Some(signature) = signature else {
+		return false;
+	};
+
+	let signature = signature.strip_prefix("sha256=").unwrap_or(signature);
+	let Ok(signature) = hex::decode(signature) else {
+		return false;
+	};
+
+	hmac::verify(key, body, &signature).is_ok()
+}
+
+impl Queue {
+	/// Queues the `body` if there are less than `max_queued` bodies already
+	fn push(&self, body: String, max_queued: usize) -> Status {
+		tracing::debug!("Received a webhook request with {} bytes", body.len());
+
+		{
+			let mut bodies = self.bodies.lock().expect("poisoned");
+
+			if bodies.len() >= max_queued {
+				tracing::warn!(
+					"Rejected a webhook request since there are already {max_queued} queued"
+				);
+				return Status::ServiceUnavailable;
+			}
+
+			bodies.push_back(body);
+		}
+
+		self.received.notify_one();
+
+		Status::Accepted
+	}
+}
+
+impl Status {
+	fn code_and_reason(self) -> (u16, &'static str) {
+		match self {
+			Self::Accepted => (202, "Accepted"),
+			Self::BadRequest => (400, "Bad Request"),
+			Self::Unauthorized => (401, "Unauthorized"),
+			Self::NotFound => (404, "Not Found"),
+			Self::MethodNotAllowed => (405, "Method Not Allowed"),
+			Self::LengthRequired => (411, "Length Required"),
+			Self::PayloadTooLarge => (413, "Content Too Large"),
+			Self::HeadersTooLarge => (431, "Request Header Fields Too Large"),
+			Self::ServiceUnavailable => (503, "Service Unavailable"),
+		}
+	}
+}
+
+impl Debug for Webhook {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.debug_struct("Webhook")
+			.field("addr", &self.addr)
+			.finish_non_exhaustive()
+	}
+}
+
+impl Debug for OnRequest {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.debug_struct("OnRequest")
+			.field("cancel_token", &self.cancel_token)
+			.finish_non_exhaustive()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use assert_matches::assert_matches;
+
+	use super::*;
+
+	async fn webhook(secret: Option<&'static str>) -> Webhook {
+		Webhook::bind()
+			.addr(([127, 0, 0, 1], 0).into())
+			.path("/hook")
+			.maybe_secret(secret)
+			.call()
+			.await
+			.unwrap()
+	}
+
+	/// Sends a request and returns the status code of the response
+	async fn send(webhook: &Webhook, request: &str) -> u16 {
+		let mut stream = TcpStream::connect(webhook.local_addr()).await.unwrap();
+		stream.write_all(request.as_bytes()).await.unwrap();
+
+		let mut response = String::new();
+		stream.read_to_string(&mut response).await.unwrap();
+
+		response.split(' ').nth(1).unwrap().parse().unwrap()
+	}
+
+	fn post(path: &str, headers: &str, body: &str) -> String {
+		format!(
+			"POST {path} HTTP/1.1\r\nHost: localhost\r\n{headers}Content-Length: {}\r\n\r\n{body}",
+			body.len()
+		)
+	}
+
+	#[tokio::test]
+	async fn fetch_drains_the_queue() {
+		let mut webhook = webhook(None).await;
+
+		assert_eq!(send(&webhook, &post("/hook", "", "first")).await, 202);
+		assert_eq!(send(&webhook, &post("/hook?x=1", "", "second")).await, 202);
+		assert_eq!(send(&webhook, &post("/other", "", "third")).await, 404);
+		assert_eq!(
+			send(&webhook, "GET /hook HTTP/1.1\r\nHost: localhost\r\n\r\n").await,
+			405
+		);
+
+		let entries = webhook.fetch().await.unwrap();
+		let bodies = entries
+			.iter()
+			.map(|entry| entry.raw_contents.as_deref().unwrap())
+			.collect::<Vec<_>>();
+		assert_eq!(bodies, ["first", "second"]);
+
+		assert!(webhook.fetch().await.unwrap().is_empty());
+	}
+
+	#[tokio::test]
+	async fn checks_the_signature() {
+		let mut webhook = webhook(Some("secret")).await;
+
+		let key = hmac::Key::new(hmac::HMAC_SHA256, b"secret");
+		let signature = hex::encode(hmac::sign(&key, b"signed"));
+
+		let signed = post(
+			"/hook",
+			&format!("X-Hub-Signature-256: sha256={signature}\r\n"),
+			"signed",
+		);
+		let forged = post(
+			"/hook",
+			&format!("X-Hub-Signature-256: sha256={signature}\r\n"),
+			"forged",
+		);
+
+		assert_eq!(send(&webhook, &signed).await, 202);
+		assert_eq!(send(&webhook, &forged).await, 401);
+		assert_eq!(send(&webhook, &post("/hook", "", "unsigned")).await, 401);
+
+		let entries = webhook.fetch().await.unwrap();
+		assert_eq!(entries.len(), 1);
+		assert_eq!(entries[0].raw_contents.as_deref(), Some("signed"));
+	}
+
+	#[tokio::test]
+	async fn rejects_requests_when_queue_is_full() {
+		let mut webhook = Webhook::bind()
+			.addr(([127, 0, 0, 1], 0).into())
+			.max_queued(1)
+			.call()
+			.await
+			.unwrap();
+
+		assert_eq!(send(&webhook, &post("/", "", "first")).await, 202);
+		assert_eq!(send(&webhook, &post("/", "", "second")).await, 503);
+
+		assert_eq!(webhook.fetch().await.unwrap().len(), 1);
+		assert_eq!(send(&webhook, &post("/", "", "third")).await, 202);
+	}
+
+	#[tokio::test]
+	async fn limits_concurrent_connections() {
+		let webhook = Webhook::bind()
+			.addr(([127, 0, 0, 1], 0).into())
+			.max_connections(1)
+			.call()
+			.await
+			.unwrap();
+
+		// takes up the only connection without sending anything
+		let idle = TcpStream::connect(webhook.local_addr()).await.unwrap();
+		tokio::time::sleep(Duration::from_millis(50)).await;
+
+		let webhook = Arc::new(webhook);
+		let request = tokio::spawn({
+			let webhook = Arc::clone(&webhook);
+			async move { send(&webhook, &post("/", "", "event")).await }
+		});
+
+		tokio::time::sleep(Duration::from_millis(50)).await;
+		assert!(!request.is_finished());
+
+		drop(idle);
+		let status = tokio::time::timeout(Duration::from_secs(5), request)
+			.await
+			.unwrap()
+			.unwrap();
+		assert_eq!(status, 202);
+	}
+
+	#[tokio::test]
+	async fn trigger_fires_on_request() {
+		let webhook = webhook(None).await;
+		let mut trigger = webhook.on_request();
+
+		let wait = tokio::spawn(async move { trigger.wait().await });
+		tokio::time::sleep(Duration::from_millis(50)).await;
+		assert!(!wait.is_finished());
+
+		send(&webhook, &post("/hook", "", "event")).await;
+
+		let res = tokio::time::timeout(Duration::from_secs(5), wait)
+			.await
+			.unwrap()
+			.unwrap();
+		assert_matches!(res, Ok(TriggerResult::Resume));
+
+		// the request hasn't been fetched yet
+		let res = webhook.on_request().wait().await;
+		assert_matches!(res, Ok(TriggerResult::Resume));
+	}
+
+	#[tokio::test]
+	async fn trigger_stops_when_cancelled() {
+		let webhook = webhook(None).await;
+		let (token, tx) = CancellationToken::new();
+		let mut trigger = webhook.on_request().with_cancel_token(token);
+
+		let wait = tokio::spawn(async move { trigger.wait().await });
+		tx.send(()).unwrap();
+
+		let res = tokio::time::timeout(Duration::from_secs(5), wait)
+			.await
+			.unwrap()
+			.unwrap();
+		assert_matches!(res, Ok(TriggerResult::Stop));
+	}
+}
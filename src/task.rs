@@ -12,7 +12,8 @@ mod task_group;
 
 pub mod entry_to_msg_map;
 
-use std::convert::Infallible;
+use futures::StreamExt;
+use std::{convert::Infallible, pin::pin};
 
 pub use self::disabled_task::DisabledTask;
 pub use self::opaque_task::OpaqueTask;
@@ -26,7 +27,7 @@ use crate::{
 	entry::Entry,
 	error::FetcherError,
 	external_save::ExternalSave,
-	sources::Source,
+	sources::{Source, error::SourceError},
 };
 
 /// A core primitive of [`fetcher`](`crate`). A single instance of a data pipeline.
@@ -74,6 +75,9 @@ where
 {
 	/// Run a task once to completion
 	///
+	/// If the source supports [fetching in batches](`crate::sources::Fetch::fetch_batches`),
+	/// the actions are run on every batch as soon as it has been fetched.
+	///
 	/// # Errors
 	/// Errors if any part of the pipeline (source -> actions) failed,
 	/// if the [`ReadFilter`](`crate::read_filter::ReadFilter`) failed,
@@ -83,11 +87,40 @@ where
 	pub async fn run(&mut self) -> Result<(), FetcherError> {
 		tracing::trace!("Running task");
 
-		let raw = match &mut self.source {
-			Some(source) => source.fetch().await.map_err(Into::into)?,
-			None => vec![Entry::default()], // return just an empty entry if there is no source
+		let Some(source) = &mut self.source else {
+			// return just an empty entry if there is no source
+			self.process(vec![Entry::default()]).await?;
+			return Ok(());
+		};
+
+		let Some(batches) = source.fetch_batches() else {
+			let raw = source.fetch().await.map_err(Into::into)?;
+			self.process(raw).await?;
+			return Ok(());
 		};
 
+		let mut batches = pin!(batches);
+		loop {
+			let raw = match batches.next().await {
+				Some(Ok(raw)) => raw,
+				Some(Err(e)) => return Err(Into::<SourceError>::into(e).into()),
+				None => break,
+			};
+
+			if let ActionResult::Terminated = self.process(raw).await? {
+				tracing::debug!("Task terminated, skipping the rest of the batches");
+				break;
+			}
+		}
+
+		Ok(())
+	}
+
+	/// Runs the entries through the actions
+	async fn process(
+		&mut self,
+		raw: Vec<Entry>,
+	) -> Result<ActionResult<Infallible, ()>, FetcherError> {
 		tracing::debug!("Got {} raw entries from the sources", raw.len());
 		tracing::trace!("Raw entries: {raw:#?}");
 
@@ -99,12 +132,13 @@ where
 				cancel_token: self.cancel_token.as_ref(),
 			};
 			match action.apply(raw, ctx).await {
-				ActionResult::Ok(_) | ActionResult::Terminated => (),
+				ActionResult::Ok(_) => (),
+				ActionResult::Terminated => return Ok(ActionResult::Terminated),
 				ActionResult::Err(e) => return Err(e.into()),
 			}
 		}
 
-		Ok(())
+		Ok(ActionResult::Ok(()))
 	}
 }
 
@@ -0,0 +1,147 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+
+//! Helpers shared by unit tests of different modules
+
+#![allow(
+	dead_code,
+	reason = "not every helper is used with every set of features"
+)]
+
+use futures::{Stream, stream};
+use serde::{Serialize, de::DeserializeOwned};
+use std::{
+	collections::HashMap,
+	path::{Path, PathBuf},
+	sync::{Arc, Mutex},
+	time::Duration,
+};
+
+use crate::{
+	entry::{Entry, EntryId},
+	error::Error,
+	external_save::{ExternalSave, ExternalSaveError},
+	maybe_send::{MaybeSend, MaybeSync},
+	sinks::message::MessageId,
+	sources::{Fetch, FetchStream, error::SourceError},
+};
+
+/// An empty directory in the temp dir unique to a test that is removed on drop
+pub struct TempDir(PathBuf);
+
+impl TempDir {
+	/// Creates an empty directory for the `test`, removing whatever a previous run has left there
+	pub fn new(test: &str) -> Self {
+		let path = std::env::temp_dir().join(format!("fetcher-{test}-{}", std::process::id()));
+
+		_ = std::fs::remove_dir_all(&path);
+		std::fs::create_dir_all(&path).unwrap();
+
+		Self(path)
+	}
+
+	pub fn path(&self) -> &Path {
+		&self.0
+	}
+}
+
+impl Drop for TempDir {
+	fn drop(&mut self) {
+		_ = std::fs::remove_dir_all(&self.0);
+	}
+}
+
+/// Keeps the last state saved by each source as JSON. Everything else isn't saved
+#[derive(Clone, Default)]
+pub struct SourceStates(Arc<Mutex<HashMap<String, String>>>);
+
+impl SourceStates {
+	/// Returns the last state saved under the `key`
+	pub fn get<T: DeserializeOwned>(&self, key: &str) -> Option<T> {
+		let state = self.0.lock().unwrap().get(key)?.clone();
+		Some(serde_json::from_str(&state).unwrap())
+	}
+}
+
+impl ExternalSave for SourceStates {
+	async fn save_read_filter<RF>(&mut self, _read_filter: &RF) -> Result<(), ExternalSaveError>
+	where
+		RF: Serialize + MaybeSync,
+	{
+		Ok(())
+	}
+
+	async fn save_entry_to_msg_map(
+		&mut self,
+		_map: &HashMap<EntryId, MessageId>,
+	) -> Result<(), ExternalSaveError> {
+		Ok(())
+	}
+
+	async fn save_source_state<T>(&mut self, key: &str, state: &T) -> Result<(), ExternalSaveError>
+	where
+		T: Serialize + MaybeSync,
+	{
+		let state = serde_json::to_string(state).unwrap();
+		self.0.lock().unwrap().insert(key.to_owned(), state);
+
+		Ok(())
+	}
+}
+
+/// Error of the test sources
+#[derive(thiserror::Error, Clone, Copy, Debug)]
+#[error("Test source failed")]
+pub struct TestError;
+
+impl Error for TestError {
+	fn is_network_related(&self) -> Option<&dyn Error> {
+		None
+	}
+}
+
+impl From<TestError> for SourceError {
+	fn from(e: TestError) -> Self {
+		Self::Other(Box::new(e))
+	}
+}
+
+/// Fails after the delay
+pub struct Failing(pub Duration);
+
+impl Fetch for Failing {
+	type Err = TestError;
+
+	async fn fetch(&mut self) -> Result<Vec<Entry>, Self::Err> {
+		tokio::time::sleep(self.0).await;
+		Err(TestError)
+	}
+}
+
+/// Streams entries with the text one by one, or errors
+pub struct Entries(pub Vec<Result<&'static str, TestError>>);
+
+impl FetchStream for Entries {
+	type Err = TestError;
+
+	fn fetch_stream(&mut self) -> impl Stream<Item = Result<Entry, Self::Err>> + MaybeSend + use<> {
+		let entries = self
+			.0
+			.clone()
+			.into_iter()
+			.map(|res| res.map(|text| Entry::builder().raw_contents(text.to_owned()).build()));
+
+		stream::iter(entries)
+	}
+}
+
+/// Returns the raw contents of all entries
+pub fn contents(entries: &[Entry]) -> Vec<&str> {
+	entries
+		.iter()
+		.map(|entry| entry.raw_contents.as_deref().unwrap())
+		.collect()
+}
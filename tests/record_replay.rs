@@ -0,0 +1,103 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+
+//! This test asserts that a task with a replayed source sends exactly the same messages as when it was recorded
+
+#![allow(clippy::missing_assert_message)]
+#![allow(clippy::tests_outside_test_module)]
+#![allow(clippy::unwrap_used)]
+
+use std::{
+	convert::Infallible,
+	sync::{Arc, Mutex},
+};
+
+use fetcher::{
+	Task,
+	actions::{sink, transform_fn},
+	entry::Entry,
+	sinks::{
+		Sink,
+		message::{Message, MessageId},
+	},
+	sources::{Fetch, RecordReplay, record_replay::Mode},
+};
+
+/// Pretends to be a live website that returns different data every time
+struct LiveSource(u32);
+
+/// Saves all messages it receives
+#[derive(Clone, Default)]
+struct CollectSink(Arc<Mutex<Vec<Message>>>);
+
+impl Fetch for LiveSource {
+	type Err = Infallible;
+
+	async fn fetch(&mut self) -> Result<Vec<Entry>, Self::Err> {
+		self.0 += 1;
+
+		let entry = Entry::builder()
+			.id(self.0.to_string())
+			.raw_contents(format!(r#"{{ "title": "Post #{}" }}"#, self.0))
+			.build();
+
+		Ok(vec![entry])
+	}
+}
+
+impl Sink for CollectSink {
+	type Err = Infallible;
+
+	async fn send(
+		&mut self,
+		message: &Message,
+		_reply_to: Option<&MessageId>,
+		_tag: Option<&str>,
+	) -> Result<Option<MessageId>, Self::Err> {
+		self.0.lock().unwrap().push(message.clone());
+		Ok(None)
+	}
+}
+
+async fn run_task<F: Fetch>(source: RecordReplay<F>) -> Vec<Message> {
+	let collected = CollectSink::default();
+
+	let mut task = Task::builder("record_replay_test")
+		.source(source.into_source_without_read_filter())
+		.action((
+			transform_fn(async |entry: Entry| {
+				let raw = entry.raw_contents.unwrap();
+				let title = raw.split('"').nth(3).unwrap().to_owned();
+
+				Entry::builder()
+					.msg(Message::builder().title(title))
+					.build()
+			}),
+			sink(collected.clone()),
+		))
+		.build_without_replies();
+
+	task.run().await.unwrap();
+	task.run().await.unwrap();
+
+	collected.0.lock().unwrap().clone()
+}
+
+#[tokio::test]
+async fn record_replay() {
+	let dir = std::env::temp_dir().join(format!("fetcher-record-replay-{}", std::process::id()));
+	_ = std::fs::remove_dir_all(&dir);
+
+	let recorded = run_task(RecordReplay::new(LiveSource(0), &dir, Mode::Record)).await;
+	assert_eq!(recorded.len(), 2);
+	assert_eq!(recorded[1].title.as_deref(), Some("Post #2"));
+
+	// the live source would have returned different posts by now
+	let replayed = run_task(RecordReplay::new(LiveSource(10), &dir, Mode::Replay)).await;
+	assert_eq!(replayed, recorded);
+
+	std::fs::remove_dir_all(&dir).unwrap();
+}
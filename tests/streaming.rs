@@ -0,0 +1,114 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+
+//! This test asserts that a task runs the actions on every batch of a streaming source as soon as it arrives,
+//! instead of waiting for the source to finish fetching
+
+#![allow(clippy::missing_assert_message)]
+#![allow(clippy::tests_outside_test_module)]
+#![allow(clippy::unwrap_used)]
+
+use std::{
+	convert::Infallible,
+	num::NonZeroUsize,
+	sync::{Arc, Mutex},
+	time::Duration,
+};
+
+use futures::{Stream, StreamExt, stream};
+use tokio::sync::Notify;
+
+use fetcher::{
+	Task,
+	actions::sink,
+	entry::{Entry, EntryId},
+	maybe_send::MaybeSend,
+	read_filter::NotPresent,
+	sinks::{
+		Sink,
+		message::{Message, MessageId},
+	},
+	sources::{Fetch, FetchStream},
+};
+
+/// Yields the second entry only after the first one has been sent
+struct PagedSource(Arc<Notify>);
+
+/// Saves the bodies of all messages it receives and notifies the source
+struct NotifySink {
+	sent: Arc<Mutex<Vec<String>>>,
+	notify: Arc<Notify>,
+}
+
+fn entry(id: &str) -> Entry {
+	Entry::builder()
+		.id(id.to_owned())
+		.msg(Message::builder().body(format!("entry #{id}")))
+		.build()
+}
+
+impl FetchStream for PagedSource {
+	type Err = Infallible;
+
+	fn fetch_stream(&mut self) -> impl Stream<Item = Result<Entry, Self::Err>> + MaybeSend + use<> {
+		let notify = Arc::clone(&self.0);
+
+		stream::once(async { Ok(entry("1")) }).chain(stream::once(async move {
+			notify.notified().await;
+			Ok(entry("2"))
+		}))
+	}
+}
+
+impl Sink for NotifySink {
+	type Err = Infallible;
+
+	async fn send(
+		&mut self,
+		message: &Message,
+		_reply_to: Option<&MessageId>,
+		_tag: Option<&str>,
+	) -> Result<Option<MessageId>, Self::Err> {
+		self.sent
+			.lock()
+			.unwrap()
+			.push(message.body.clone().unwrap());
+		self.notify.notify_one();
+
+		Ok(None)
+	}
+}
+
+#[tokio::test]
+async fn processes_batches_as_they_arrive() {
+	let notify = Arc::new(Notify::new());
+	let sent = Arc::new(Mutex::new(Vec::new()));
+
+	let mut task = Task::builder("streaming_test")
+		.source(
+			PagedSource(Arc::clone(&notify))
+				.batched(NonZeroUsize::new(10).unwrap())
+				.into_source_with_read_filter(NotPresent::new()),
+		)
+		.action(sink(NotifySink {
+			sent: Arc::clone(&sent),
+			notify,
+		}))
+		.build_without_replies();
+
+	// the source would never yield the second entry if the task waited for all of them before sending the first one
+	tokio::time::timeout(Duration::from_secs(5), task.run())
+		.await
+		.expect("task should process the first batch before the source has finished")
+		.unwrap();
+
+	assert_eq!(*sent.lock().unwrap(), ["entry #1", "entry #2"]);
+
+	let rf = task.source.unwrap().rf.unwrap();
+	for id in ["1", "2"] {
+		assert!(!rf.is_unread(&EntryId::try_from(id).unwrap()));
+	}
+}